@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+
+use p2panda_core::Hash;
+use p2panda_encryption::crypto::aead::{AeadNonce, aead_decrypt, aead_encrypt};
+
+/// Files are transferred through the mailboxes in chunks of this many bytes.
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+/// The largest file which is shared or downloaded, in bytes.
+pub const MAX_FILE_SIZE: u64 = 100 * 1024 * 1024;
+
+/// The number of chunks a file of the given size is split into.
+pub fn chunk_count(size: u64) -> u64 {
+    size.div_ceil(CHUNK_SIZE as u64)
+}
+
+/// The ID the chunks of a file are stored under in the mailboxes.
+///
+/// It's derived from the hash of the file, so that anyone who knows the hash
+/// can fetch the chunks, but the mailboxes can't tell the hash, nor derive the key
+/// of the chunks, from the ID.
+pub fn chunk_file_id(hash: &Hash) -> [u8; 32] {
+    blake3::derive_key("dashchat 2025 chunk file id", hash.as_bytes())
+}
+
+/// Encrypt a chunk of a file to the key derived from the hash of the file.
+/// The index of the chunk is the nonce, so chunks can't be swapped around.
+pub fn encrypt_chunk(hash: &Hash, index: u64, chunk: &[u8]) -> anyhow::Result<Vec<u8>> {
+    Ok(aead_encrypt(
+        &chunk_key(hash),
+        chunk,
+        chunk_nonce(index),
+        None,
+    )?)
+}
+
+/// Decrypt a chunk of a file, which fails if it was tampered with.
+pub fn decrypt_chunk(hash: &Hash, index: u64, chunk: &[u8]) -> anyhow::Result<Vec<u8>> {
+    Ok(aead_decrypt(
+        &chunk_key(hash),
+        chunk,
+        chunk_nonce(index),
+        None,
+    )?)
+}
+
+fn chunk_key(hash: &Hash) -> [u8; 32] {
+    blake3::derive_key("dashchat 2025 chunk key", hash.as_bytes())
+}
+
+fn chunk_nonce(index: u64) -> AeadNonce {
+    let mut nonce = AeadNonce::default();
+    nonce[..8].copy_from_slice(&index.to_le_bytes());
+    nonce
+}
+
+/// A local directory of complete files, each named after the hash of its content.
+#[derive(Clone, Debug)]
+pub struct BlobCache {
+    dir: PathBuf,
+}
+
+impl BlobCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Where the file with this hash is, or would be, cached.
+    pub fn path(&self, hash: &Hash) -> PathBuf {
+        self.dir.join(hash.to_hex())
+    }
+
+    pub async fn contains(&self, hash: &Hash) -> bool {
+        tokio::fs::try_exists(self.path(hash))
+            .await
+            .unwrap_or(false)
+    }
+
+    /// Cache a file, returning the hash of its content.
+    ///
+    /// The file is written under a temporary name first, so that an interrupted
+    /// write never leaves a partial file behind.
+    pub async fn insert(&self, data: &[u8]) -> anyhow::Result<Hash> {
+        let hash = Hash::new(data);
+        let path = self.path(&hash);
+        if tokio::fs::try_exists(&path).await? {
+            return Ok(hash);
+        }
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let partial = path.with_extension("part");
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(hash)
+    }
+
+    pub async fn get(&self, hash: &Hash) -> anyhow::Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(hash)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_blob_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = BlobCache::new(dir.path().join("blobs"));
+
+        let hash = cache.insert(b"hello").await.unwrap();
+        assert_eq!(hash, Hash::new(b"hello"));
+        assert!(cache.contains(&hash).await);
+        assert_eq!(cache.get(&hash).await.unwrap(), Some(b"hello".to_vec()));
+
+        let missing = Hash::new(b"missing");
+        assert!(!cache.contains(&missing).await);
+        assert_eq!(cache.get(&missing).await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_chunk_encryption() {
+        let hash = Hash::new(b"file");
+        let encrypted = encrypt_chunk(&hash, 1, b"chunk").unwrap();
+        assert_ne!(encrypted, b"chunk");
+        assert_eq!(decrypt_chunk(&hash, 1, &encrypted).unwrap(), b"chunk");
+
+        // The chunk only decrypts at its own index, with the hash of its own file.
+        assert!(decrypt_chunk(&hash, 0, &encrypted).is_err());
+        assert!(decrypt_chunk(&Hash::new(b"other"), 1, &encrypted).is_err());
+
+        let mut tampered = encrypted.clone();
+        tampered[0] ^= 1;
+        assert!(decrypt_chunk(&hash, 1, &tampered).is_err());
+
+        assert_ne!(chunk_file_id(&hash), *hash.as_bytes());
+    }
+
+    #[test]
+    fn test_chunk_count() {
+        assert_eq!(chunk_count(0), 0);
+        assert_eq!(chunk_count(1), 1);
+        assert_eq!(chunk_count(CHUNK_SIZE as u64), 1);
+        assert_eq!(chunk_count(CHUNK_SIZE as u64 + 1), 2);
+    }
+}
@@ -1,7 +1,14 @@
 mod message;
+mod search;
 pub use message::*;
+pub use search::*;
 
-use crate::Topic;
+use std::collections::BTreeMap;
+
+use p2panda_core::Hash;
+use serde::{Deserialize, Serialize};
+
+use crate::{AgentId, ChatPayload, DeviceId, Topic};
 
 pub type ChatId = Topic<crate::topic::kind::Chat>;
 pub type GroupChatId = ChatId;
@@ -9,3 +16,169 @@ pub type DirectChatId = ChatId;
 pub type DeviceGroupId = Topic<crate::topic::kind::DeviceGroup>;
 // pub type GroupChatId = Topic<crate::topic::kind::GroupChat>;
 // pub type DirectChatId = Topic<crate::topic::kind::DirectChat>;
+
+/// An invitation to a group chat, received through a direct chat with a contact.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupInvitation {
+    pub chat_id: ChatId,
+    /// The contact who sent the invitation
+    pub inviter: AgentId,
+}
+
+/// What the chat list shows for a chat.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatSummary {
+    pub chat_id: ChatId,
+    /// The number of messages from others after the latest one I marked as read
+    /// on any of my devices.
+    pub unread: usize,
+    /// The latest message which wasn't deleted, if any.
+    pub last_message: Option<MessagePreview>,
+    /// The timestamp of the latest message, if any.
+    pub last_activity: Option<u64>,
+}
+
+/// The start of a message, as shown in the chat list.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessagePreview {
+    /// The hash of the header of the message.
+    pub hash: Hash,
+    /// The agent of the device which wrote the message, if I know it.
+    pub author: Option<AgentId>,
+    pub device: DeviceId,
+    /// The start of the latest version of a text message, or the name of a file.
+    pub excerpt: String,
+}
+
+/// The members of a group chat after applying its chat payloads in order,
+/// in the order they were added. Members who left are removed again,
+/// until they are added anew.
+///
+/// Only members can add members, so an AddMember only counts if its author acts
+/// for a member, going by the devices I know of and the devices listed by earlier
+/// AddMembers. The first AddMember is the creator adding themselves.
+/// Likewise, a LeaveGroup only counts if its author acts for the leaving agent.
+pub(crate) fn fold_group_members(
+    known_devices: &BTreeMap<DeviceId, AgentId>,
+    payloads: impl IntoIterator<Item = (DeviceId, ChatPayload)>,
+) -> Vec<AgentId> {
+    let mut members = vec![];
+    let mut devices = known_devices.clone();
+    let mut created = false;
+    for (author, payload) in payloads {
+        match payload {
+            ChatPayload::AddMember {
+                agent_id,
+                devices: added,
+            } => {
+                let valid = if created {
+                    devices
+                        .get(&author)
+                        .is_some_and(|agent| members.contains(agent))
+                } else {
+                    added.contains(&author)
+                        && devices.get(&author).is_none_or(|agent| *agent == agent_id)
+                };
+                if !valid {
+                    continue;
+                }
+                created = true;
+                for device in added {
+                    devices.entry(device).or_insert(agent_id);
+                }
+                if !members.contains(&agent_id) {
+                    members.push(agent_id);
+                }
+            }
+            ChatPayload::LeaveGroup(agent_id) if devices.get(&author) == Some(&agent_id) => {
+                members.retain(|member| *member != agent_id);
+            }
+            _ => {}
+        }
+    }
+    members
+}
+
+#[cfg(test)]
+mod tests {
+    use p2panda_core::PrivateKey;
+    use p2panda_spaces::ActorId;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn device() -> DeviceId {
+        DeviceId::from(PrivateKey::new().public_key())
+    }
+
+    fn agent() -> AgentId {
+        AgentId::from(ActorId::from(PrivateKey::new().public_key()))
+    }
+
+    fn add(agent_id: AgentId, devices: &[DeviceId]) -> ChatPayload {
+        ChatPayload::AddMember {
+            agent_id,
+            devices: devices.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_only_members_add_members() {
+        let [alice, bobbi, carol, mallory] = std::array::from_fn(|_| agent());
+        let [alice_1, bobbi_1, carol_1, mallory_1] = std::array::from_fn(|_| device());
+        // Alice knows bobbi, but not carol, whom bobbi adds.
+        let known = BTreeMap::from_iter([(alice_1, alice), (bobbi_1, bobbi)]);
+
+        let members = fold_group_members(
+            &known,
+            [
+                (alice_1, add(alice, &[alice_1])),
+                (mallory_1, add(mallory, &[mallory_1])),
+                (alice_1, add(bobbi, &[bobbi_1])),
+                (bobbi_1, add(carol, &[carol_1])),
+                (carol_1, add(mallory, &[mallory_1])),
+            ],
+        );
+        assert_eq!(members, vec![alice, bobbi, carol, mallory]);
+
+        // The first member has to add themselves, with the device doing so.
+        let members = fold_group_members(
+            &known,
+            [
+                (mallory_1, add(alice, &[alice_1])),
+                (bobbi_1, add(alice, &[bobbi_1])),
+                (alice_1, add(alice, &[alice_1])),
+                (mallory_1, add(mallory, &[mallory_1])),
+            ],
+        );
+        assert_eq!(members, vec![alice]);
+    }
+
+    #[test]
+    fn test_only_members_leave_by_themselves() {
+        let [alice, bobbi] = std::array::from_fn(|_| agent());
+        let [alice_1, bobbi_1] = std::array::from_fn(|_| device());
+        let known = BTreeMap::from_iter([(alice_1, alice)]);
+
+        let members = fold_group_members(
+            &known,
+            [
+                (alice_1, add(alice, &[alice_1])),
+                (alice_1, add(bobbi, &[bobbi_1])),
+                (alice_1, ChatPayload::LeaveGroup(bobbi)),
+                (bobbi_1, ChatPayload::LeaveGroup(alice)),
+            ],
+        );
+        assert_eq!(members, vec![alice, bobbi]);
+
+        let members = fold_group_members(
+            &known,
+            [
+                (alice_1, add(alice, &[alice_1])),
+                (alice_1, add(bobbi, &[bobbi_1])),
+                (bobbi_1, ChatPayload::LeaveGroup(bobbi)),
+            ],
+        );
+        assert_eq!(members, vec![alice]);
+    }
+}
@@ -1,29 +1,133 @@
-use named_id::RenameNone;
+use std::collections::BTreeSet;
+
+use named_id::{RenameAll, RenameNone};
 use p2panda_core::Hash;
-use serde::{Deserialize, Serialize};
-
-#[derive(
-    Clone,
-    Debug,
-    PartialEq,
-    Eq,
-    Serialize,
-    Deserialize,
-    derive_more::From,
-    derive_more::Deref,
-    RenameNone,
-)]
-pub struct ChatMessageContent(String);
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{AgentId, DeviceId, Header};
+
+/// Max number of characters of a quoted message shown above a reply.
+pub const EXCERPT_LENGTH: usize = 100;
+
+/// The content of a chat message.
+///
+/// A message which isn't a reply is encoded as a plain string,
+/// so that it stays compatible with messages from before replies existed.
+#[derive(Clone, Debug, PartialEq, Eq, derive_more::Deref, RenameNone)]
+pub struct ChatMessageContent {
+    #[deref]
+    pub text: String,
+    /// The hash of the header of the message being replied to.
+    pub reply_to: Option<Hash>,
+}
+
+impl ChatMessageContent {
+    pub fn reply(text: impl Into<String>, reply_to: Hash) -> Self {
+        Self {
+            text: text.into(),
+            reply_to: Some(reply_to),
+        }
+    }
+
+    /// The start of the text, cut off at [`EXCERPT_LENGTH`] characters.
+    pub fn excerpt(&self) -> String {
+        match self.text.char_indices().nth(EXCERPT_LENGTH) {
+            Some((end, _)) => format!("{}…", &self.text[..end]),
+            None => self.text.clone(),
+        }
+    }
+}
+
+impl From<String> for ChatMessageContent {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            reply_to: None,
+        }
+    }
+}
 
 impl From<&str> for ChatMessageContent {
     fn from(value: &str) -> Self {
-        Self(value.to_string())
+        Self::from(value.to_string())
+    }
+}
+
+impl Serialize for ChatMessageContent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.reply_to {
+            None => serializer.serialize_str(&self.text),
+            Some(reply_to) => {
+                let mut content = serializer.serialize_struct("ChatMessageContent", 2)?;
+                content.serialize_field("text", &self.text)?;
+                content.serialize_field("reply_to", reply_to)?;
+                content.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ChatMessageContent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ContentVisitor)
     }
 }
 
+struct ContentVisitor;
+
+impl<'de> Visitor<'de> for ContentVisitor {
+    type Value = ChatMessageContent;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a string, or a map with text and reply_to")
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> Result<Self::Value, E> {
+        Ok(text.into())
+    }
+
+    fn visit_string<E: de::Error>(self, text: String) -> Result<Self::Value, E> {
+        Ok(text.into())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut text = None;
+        let mut reply_to = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "text" => text = Some(map.next_value()?),
+                "reply_to" => reply_to = map.next_value()?,
+                _ => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(ChatMessageContent {
+            text: text.ok_or_else(|| de::Error::missing_field("text"))?,
+            reply_to,
+        })
+    }
+}
+
+/// The message a reply refers to, as shown above the reply.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+pub struct QuotedMessage {
+    /// The hash of the header of the quoted message.
+    pub hash: Hash,
+    /// The author of the quoted message.
+    /// None if the message hasn't arrived yet or was deleted.
+    pub author: Option<DeviceId>,
+    /// The start of the latest version of the quoted message.
+    /// None if the message hasn't arrived yet or was deleted.
+    pub excerpt: Option<String>,
+}
+
 /// An emoji reaction to a message.
 ///
 /// If an author creates multiple reactions to the same message, only the last one is shown.
+/// See [`crate::Node::reactions`] for the current reactions to a message.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameNone)]
 pub struct ChatReaction {
     /// The emoji to react with.
@@ -33,6 +137,162 @@ pub struct ChatReaction {
     pub target: Hash,
 }
 
+/// One version of a message: either the original message or one of its edits.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+pub struct MessageVersion {
+    pub content: ChatMessageContent,
+    /// The device which wrote this version.
+    pub author: DeviceId,
+    pub timestamp: u64,
+    /// The hash of the header of the operation which wrote this version.
+    pub hash: Hash,
+}
+
+impl MessageVersion {
+    pub fn new(content: ChatMessageContent, header: &Header) -> Self {
+        Self {
+            content,
+            author: header.public_key.into(),
+            timestamp: header.timestamp,
+            hash: header.hash(),
+        }
+    }
+}
+
+/// A message with its edits applied.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+pub struct EditedMessage {
+    /// The hash of the header of the original message.
+    pub hash: Hash,
+    /// The content of the latest version.
+    pub content: ChatMessageContent,
+    /// All versions of the message, oldest first, starting with the original.
+    pub history: Vec<MessageVersion>,
+}
+
+impl EditedMessage {
+    /// The edits must already be validated, and be in the order they apply in.
+    pub(crate) fn new(
+        original: MessageVersion,
+        edits: impl IntoIterator<Item = MessageVersion>,
+    ) -> Self {
+        let hash = original.hash;
+        let mut history = vec![original];
+        history.extend(edits);
+        let content = history
+            .last()
+            .expect("history is never empty")
+            .content
+            .clone();
+
+        Self {
+            hash,
+            content,
+            history,
+        }
+    }
+
+    /// The original author of the message.
+    pub fn author(&self) -> DeviceId {
+        self.history[0].author
+    }
+}
+
+/// How far a message has made it to the other members of its chat.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+pub struct MessageStatus {
+    /// Whether the message was published to a mailbox.
+    pub published: bool,
+    /// The devices which have received the message.
+    pub delivered_to: BTreeSet<DeviceId>,
+    /// The agents who have read the message.
+    pub read_by: BTreeSet<AgentId>,
+}
+
+/// The number of messages in a page of history, unless another size is asked for.
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Which page of a chat's history to get.
+///
+/// The cursors are hashes of messages in the chat. Without a cursor,
+/// the latest messages are returned. Either way, the page holds the messages
+/// closest to the cursor, ordered oldest first.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+pub struct HistoryQuery {
+    /// Only the closest messages which come before this one.
+    pub before: Option<Hash>,
+    /// Only the closest messages which come after this one.
+    pub after: Option<Hash>,
+    /// The max number of messages to return.
+    pub limit: usize,
+}
+
+impl Default for HistoryQuery {
+    fn default() -> Self {
+        Self {
+            before: None,
+            after: None,
+            limit: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+/// A page of a chat's history, oldest message first.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+pub struct HistoryPage {
+    pub messages: Vec<HistoryMessage>,
+    /// Whether there are more messages beyond the page, in the direction of the query.
+    pub has_more: bool,
+}
+
+/// A message in a chat's history, with its edits and reactions applied.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+pub struct HistoryMessage {
+    /// The hash of the header of the message.
+    pub hash: Hash,
+    /// The agent of the device which wrote the message, if I know it.
+    pub author: Option<AgentId>,
+    /// The device which wrote the message.
+    pub device: DeviceId,
+    pub timestamp: u64,
+    pub content: HistoryContent,
+    /// The message this one replies to, if any.
+    pub quoted: Option<QuotedMessage>,
+    /// The current reactions to the message, ordered by emoji.
+    pub reactions: Vec<ReactionCount>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+#[serde(tag = "type", content = "payload")]
+pub enum HistoryContent {
+    /// A text message, with the history of all its versions.
+    Text(EditedMessage),
+    /// A shared file, which can be fetched with `download_attachment`.
+    Attachment(Attachment),
+}
+
+/// The metadata of a file shared in a chat.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+pub struct Attachment {
+    /// The blake3 hash of the file's content.
+    pub hash: Hash,
+    /// The size of the file in bytes.
+    pub size: u64,
+    pub mime: String,
+    pub name: String,
+}
+
+/// Everyone who currently reacts to a message with the same emoji.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+pub struct ReactionCount {
+    pub emoji: String,
+    /// The number of agents who reacted with this emoji.
+    /// Devices whose agent I don't know count as an agent each.
+    pub count: usize,
+    /// The agents I know of who reacted with this emoji.
+    pub agents: Vec<AgentId>,
+}
+
 #[cfg(feature = "testing")]
 pub mod testing {
     use super::*;
@@ -41,7 +301,7 @@ pub mod testing {
 
     use named_id::RenameAll;
 
-    use crate::{Cbor, DeviceId, Header};
+    use crate::Cbor;
 
     /// A standalone chat message suitable for sending to the frontend.
     #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
@@ -49,6 +309,8 @@ pub mod testing {
         pub content: ChatMessageContent,
         pub author: DeviceId,
         pub timestamp: u64,
+        /// The message this one replies to, if any.
+        pub quoted: Option<QuotedMessage>,
     }
 
     impl ChatMessage {
@@ -57,6 +319,7 @@ pub mod testing {
                 content,
                 author: header.public_key.into(),
                 timestamp: header.timestamp,
+                quoted: None,
             }
         }
     }
@@ -83,3 +346,35 @@ pub mod testing {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use p2panda_core::cbor::{decode_cbor, encode_cbor};
+
+    use super::*;
+
+    #[test]
+    fn test_content_cbor_compatible_with_plain_string() {
+        let old = encode_cbor(&"hello".to_string()).unwrap();
+        let content: ChatMessageContent = decode_cbor(&old[..]).unwrap();
+        assert_eq!(content, ChatMessageContent::from("hello"));
+        assert_eq!(encode_cbor(&content).unwrap(), old);
+
+        let reply = ChatMessageContent::reply("hi", Hash::new(b"message"));
+        let bytes = encode_cbor(&reply).unwrap();
+        assert_eq!(
+            decode_cbor::<ChatMessageContent, _>(&bytes[..]).unwrap(),
+            reply
+        );
+    }
+
+    #[test]
+    fn test_excerpt() {
+        assert_eq!(ChatMessageContent::from("short").excerpt(), "short");
+        let long = "ä".repeat(EXCERPT_LENGTH + 1);
+        assert_eq!(
+            ChatMessageContent::from(long).excerpt(),
+            format!("{}…", "ä".repeat(EXCERPT_LENGTH))
+        );
+    }
+}
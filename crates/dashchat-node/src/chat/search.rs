@@ -0,0 +1,93 @@
+use std::collections::BTreeSet;
+
+use named_id::RenameAll;
+use p2panda_core::Hash;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+use crate::{ChatId, DeviceId};
+
+/// Max number of characters shown on either side of the matching word in a snippet.
+pub const SNIPPET_CONTEXT: usize = 30;
+
+/// A message whose latest version matches a search.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+pub struct SearchResult {
+    pub chat_id: ChatId,
+    /// The hash of the header of the original message.
+    pub hash: Hash,
+    pub author: DeviceId,
+    pub timestamp: u64,
+    /// The part of the latest version around the first matching word.
+    pub snippet: String,
+}
+
+/// Lowercase a word and strip its diacritics, so that "Café" and "cafe" are the same.
+pub(crate) fn normalize(word: &str) -> String {
+    word.nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The distinct normalized words of a text.
+pub(crate) fn tokenize(text: &str) -> BTreeSet<String> {
+    words(text)
+        .into_iter()
+        .map(|(_, word)| normalize(word))
+        .collect()
+}
+
+/// Whether every token of a query is the start of a word of the text.
+pub(crate) fn matches(text: &str, query: &BTreeSet<String>) -> bool {
+    let words = tokenize(text);
+    query
+        .iter()
+        .all(|token| words.iter().any(|word| word.starts_with(token.as_str())))
+}
+
+/// The part of a text around the first word which starts with a token of the query.
+pub(crate) fn snippet(text: &str, query: &BTreeSet<String>) -> Option<String> {
+    let (start, word) = words(text).into_iter().find(|(_, word)| {
+        let word = normalize(word);
+        query.iter().any(|token| word.starts_with(token.as_str()))
+    })?;
+    let end = start + word.len();
+
+    let from = text[..start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT - 1)
+        .map_or(0, |(i, _)| i);
+    let to = text[end..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT)
+        .map_or(text.len(), |(i, _)| end + i);
+
+    let prefix = if from > 0 { "…" } else { "" };
+    let suffix = if to < text.len() { "…" } else { "" };
+    Some(format!("{prefix}{}{suffix}", &text[from..to]))
+}
+
+/// The words of a text, with their byte offsets.
+/// Combining marks are part of the word they follow.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = vec![];
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        let in_word = c.is_alphanumeric() || is_combining_mark(c);
+        match (in_word, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                words.push((s, &text[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, &text[s..]));
+    }
+    words
+}
@@ -1,10 +1,12 @@
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use named_id::RenameAll;
 use p2panda_core::cbor::{decode_cbor, encode_cbor};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
-use crate::{AgentId, DeviceId, Topic, topic::kind};
+use crate::{AgentId, DeviceGroupPayload, DeviceId, KeyBundle, Profile, Topic, topic::kind};
 
 /// The content for a QR code or deep link.
 ///
@@ -22,18 +24,30 @@ use crate::{AgentId, DeviceId, Topic, topic::kind};
 ///
 /// When adding a contact, no groups are joined, it's only for the purpose of exchanging
 /// pubkeys and key bundles, so that chat groups can be joined in the future.
+///
+/// Codes are encoded as a CBOR map keyed by field name, so that fields can be added
+/// later: codes without the optional fields still decode, and fields which an older
+/// version doesn't know are skipped.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
 // #[serde(into = "String", try_from = "String")]
 pub struct QrCode {
     /// Pubkey of this node: allows adding this node to groups.
     pub device_pubkey: DeviceId,
+    /// The key bundle of this node, so that direct chat payloads can be sealed for it
+    /// before its announcements arrive.
+    pub key_bundle: KeyBundle,
     /// Agent ID to add to spaces
     pub agent_id: AgentId,
     /// Topic for receiving messages from this node during the lifetime of the QR code.
     /// The initiator will specify an InboxTopic, and the recipient will send back a QR
     /// code without an associated inbox, because after this exchange the two nodes
     /// can communicate directly.
+    #[serde(default)]
     pub inbox_topic: Option<InboxTopic>,
+    /// An ephemeral key which only lives as long as the inbox. What is sent to the inbox
+    /// is sealed to it, so that only the device which issued the code can read it.
+    #[serde(default)]
+    pub inbox_key: Option<KeyBundle>,
     /// The intent of the QR code: whether to add this node as a contact or a device.
     pub share_intent: ShareIntent,
 }
@@ -53,15 +67,85 @@ pub struct InboxTopic {
     pub topic: Topic<kind::Inbox>,
 }
 
+/// The contacts in effect after applying device group payloads in order.
+///
+/// A RemoveContact only undoes the AddContacts which came before it,
+/// so a contact can be added again later.
+pub(crate) fn fold_contacts(
+    payloads: impl IntoIterator<Item = DeviceGroupPayload>,
+) -> BTreeMap<AgentId, QrCode> {
+    let mut contacts = BTreeMap::new();
+    for payload in payloads {
+        match payload {
+            DeviceGroupPayload::AddContact(code) => {
+                contacts.insert(code.agent_id, code);
+            }
+            DeviceGroupPayload::RemoveContact(agent_id) => {
+                contacts.remove(&agent_id);
+            }
+            DeviceGroupPayload::RejectContactRequest(_)
+            | DeviceGroupPayload::MarkRead { .. }
+            | DeviceGroupPayload::AddDevice(_)
+            | DeviceGroupPayload::RemoveDevice { .. }
+            | DeviceGroupPayload::MoveDeviceGroup(_)
+            | DeviceGroupPayload::Backfill { .. }
+            | DeviceGroupPayload::JoinGroup(_) => {}
+        }
+    }
+    contacts
+}
+
+/// The agents whose contact requests were rejected, with the timestamp of the
+/// latest rejection, after applying timestamped device group payloads in order.
+/// Adding someone as a contact later undoes the rejection.
+pub(crate) fn fold_rejected_contact_requests(
+    payloads: impl IntoIterator<Item = (u64, DeviceGroupPayload)>,
+) -> BTreeMap<AgentId, u64> {
+    let mut rejected = BTreeMap::new();
+    for (timestamp, payload) in payloads {
+        match payload {
+            DeviceGroupPayload::RejectContactRequest(agent_id) => {
+                rejected.insert(agent_id, timestamp);
+            }
+            DeviceGroupPayload::AddContact(code) => {
+                rejected.remove(&code.agent_id);
+            }
+            DeviceGroupPayload::RemoveContact(_)
+            | DeviceGroupPayload::MarkRead { .. }
+            | DeviceGroupPayload::AddDevice(_)
+            | DeviceGroupPayload::RemoveDevice { .. }
+            | DeviceGroupPayload::MoveDeviceGroup(_)
+            | DeviceGroupPayload::Backfill { .. }
+            | DeviceGroupPayload::JoinGroup(_) => {}
+        }
+    }
+    rejected
+}
+
+/// One of my contacts, with the latest profile they announced.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    pub agent_id: AgentId,
+    /// None until their announcements have been received.
+    pub profile: Option<Profile>,
+}
+
+/// A contact request which arrived in one of my active inboxes,
+/// and which I haven't accepted or rejected yet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingContactRequest {
+    /// The code to add the requester as a contact with.
+    pub code: QrCode,
+    /// The profile the requester sent along with the request.
+    pub profile: Profile,
+    /// When the inbox the request arrived in expires,
+    /// after which the request is no longer listed.
+    pub expires_at: DateTime<Utc>,
+}
+
 impl std::fmt::Display for QrCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let bytes = encode_cbor(&(
-            &self.device_pubkey,
-            &self.inbox_topic,
-            &self.agent_id,
-            &self.share_intent,
-        ))
-        .map_err(|_| std::fmt::Error)?;
+        let bytes = encode_cbor(self).map_err(|_| std::fmt::Error)?;
         write!(f, "{}", hex::encode(bytes))
     }
 }
@@ -70,13 +154,7 @@ impl FromStr for QrCode {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let bytes = hex::decode(s)?;
-        let (device_pubkey, inbox_topic, agent_id, share_intent) = decode_cbor(bytes.as_slice())?;
-        Ok(QrCode {
-            device_pubkey,
-            inbox_topic,
-            agent_id,
-            share_intent,
-        })
+        Ok(decode_cbor(bytes.as_slice())?)
     }
 }
 
@@ -96,10 +174,17 @@ impl TryFrom<String> for QrCode {
 #[cfg(test)]
 mod tests {
 
-    use p2panda_core::PublicKey;
+    use p2panda_core::{PrivateKey, PublicKey};
+    use p2panda_encryption::crypto::x25519::SecretKey;
     use p2panda_spaces::ActorId;
 
     use super::*;
+    use crate::encryption::new_key_manager;
+
+    fn key_bundle(byte: u8) -> KeyBundle {
+        let keys = new_key_manager(&SecretKey::from_bytes([byte; 32]), 3600).unwrap();
+        KeyBundle::new(&keys, &PrivateKey::new()).unwrap()
+    }
 
     #[test]
     fn test_contact_roundtrip() {
@@ -107,10 +192,12 @@ mod tests {
         let agent_id = AgentId::from(ActorId::from_bytes(&[22; 32]).unwrap());
         let contact = QrCode {
             device_pubkey: DeviceId::from(pubkey),
+            key_bundle: key_bundle(33),
             inbox_topic: Some(InboxTopic {
                 topic: Topic::inbox(),
                 expires_at: Utc::now() + chrono::Duration::seconds(3600),
             }),
+            inbox_key: Some(key_bundle(44)),
             agent_id,
             share_intent: ShareIntent::AddDevice,
         };
@@ -119,4 +206,78 @@ mod tests {
 
         assert_eq!(contact, decoded);
     }
+
+    #[test]
+    fn test_contact_decodes_with_missing_and_unknown_fields() {
+        #[derive(Serialize)]
+        struct OlderCode {
+            device_pubkey: DeviceId,
+            key_bundle: KeyBundle,
+            agent_id: AgentId,
+            share_intent: ShareIntent,
+        }
+        #[derive(Serialize)]
+        struct NewerCode {
+            #[serde(flatten)]
+            code: QrCode,
+            added_later: u32,
+        }
+
+        let code = QrCode {
+            device_pubkey: DeviceId::from(PublicKey::from_bytes(&[11; 32]).unwrap()),
+            key_bundle: key_bundle(33),
+            agent_id: AgentId::from(ActorId::from_bytes(&[22; 32]).unwrap()),
+            inbox_topic: None,
+            inbox_key: None,
+            share_intent: ShareIntent::AddContact,
+        };
+        let older = OlderCode {
+            device_pubkey: code.device_pubkey,
+            key_bundle: code.key_bundle.clone(),
+            agent_id: code.agent_id,
+            share_intent: code.share_intent.clone(),
+        };
+        let decoded = QrCode::from_str(&hex::encode(encode_cbor(&older).unwrap())).unwrap();
+        assert_eq!(decoded, code);
+
+        let newer = NewerCode {
+            code: code.clone(),
+            added_later: 1,
+        };
+        let decoded = QrCode::from_str(&hex::encode(encode_cbor(&newer).unwrap())).unwrap();
+        assert_eq!(decoded, code);
+    }
+
+    #[test]
+    fn test_fold_contacts_add_remove_ordering() {
+        let code = |byte: u8| QrCode {
+            device_pubkey: DeviceId::from(PublicKey::from_bytes(&[byte; 32]).unwrap()),
+            key_bundle: key_bundle(byte),
+            agent_id: AgentId::from(ActorId::from_bytes(&[byte; 32]).unwrap()),
+            inbox_topic: None,
+            inbox_key: None,
+            share_intent: ShareIntent::AddContact,
+        };
+        let (alice, bobbi) = (code(1), code(2));
+
+        let contacts = fold_contacts([
+            DeviceGroupPayload::AddContact(alice.clone()),
+            DeviceGroupPayload::AddContact(bobbi.clone()),
+            DeviceGroupPayload::RemoveContact(alice.agent_id),
+        ]);
+        assert_eq!(
+            contacts.into_keys().collect::<Vec<_>>(),
+            vec![bobbi.agent_id]
+        );
+
+        // Removing before adding has no effect on the later add
+        let contacts = fold_contacts([
+            DeviceGroupPayload::RemoveContact(alice.agent_id),
+            DeviceGroupPayload::AddContact(alice.clone()),
+        ]);
+        assert_eq!(
+            contacts.into_keys().collect::<Vec<_>>(),
+            vec![alice.agent_id]
+        );
+    }
 }
@@ -0,0 +1,170 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{Cbor, DeviceId, LogHeight, topic::TopicId};
+
+/// A device which asked to be linked to my agent through the inbox of one of my
+/// AddDevice codes, and which hasn't been approved yet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingDeviceRequest {
+    pub device: DeviceId,
+    /// Shown on both devices, so that the user can check that the request
+    /// comes from the device they are linking, see [`verification_code`].
+    pub verification_code: String,
+    /// When the inbox the request arrived in expires,
+    /// after which the request is no longer listed.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The code which a device shows while it's being linked,
+/// and which the device approving it shows next to its request.
+///
+/// It's long enough that no one can generate a device key with the same code
+/// as the device which is being linked.
+pub fn verification_code(device: DeviceId) -> String {
+    let hash = blake3::derive_key("dashchat 2025 device verification", device.as_bytes());
+    hash[..8]
+        .chunks(2)
+        .map(hex::encode)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The devices which act for an agent: the devices it's anchored on, which are trusted
+/// up front, and every device which was added by a device acting for the agent.
+///
+/// Additions are pairs of the adding device and the added one, in any order.
+pub(crate) fn authorized_devices(
+    anchors: impl IntoIterator<Item = DeviceId>,
+    additions: impl IntoIterator<Item = (DeviceId, DeviceId)>,
+) -> BTreeSet<DeviceId> {
+    let mut authorized = anchors.into_iter().collect::<BTreeSet<_>>();
+    let mut additions = additions.into_iter().collect::<Vec<_>>();
+    loop {
+        let (authorized_now, rest): (Vec<_>, Vec<_>) = additions
+            .into_iter()
+            .partition(|(author, _)| authorized.contains(author));
+        if authorized_now.is_empty() {
+            break;
+        }
+        authorized.extend(authorized_now.into_iter().map(|(_, device)| device));
+        additions = rest;
+    }
+    authorized
+}
+
+/// Which operations of a revoked device still count: those up to and including
+/// the latest one which the revoking device had seen in each of its logs.
+/// Its operations in topics which the revoking device had no log of don't count at all.
+///
+/// This is a position in each log rather than a time, since the revoked device
+/// can claim any timestamp it likes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revocation {
+    up_to: BTreeMap<TopicId, u64>,
+}
+
+impl Cbor for Revocation {}
+
+impl Revocation {
+    pub fn new(heights: impl IntoIterator<Item = LogHeight>) -> Self {
+        Self {
+            up_to: heights
+                .into_iter()
+                .map(|height| (height.topic, height.seq_num))
+                .collect(),
+        }
+    }
+
+    /// Whether the operation of the revoked device with this seq_num in this topic counts.
+    pub fn counts(&self, topic: TopicId, seq_num: u64) -> bool {
+        self.up_to
+            .get(&topic)
+            .is_some_and(|up_to| seq_num <= *up_to)
+    }
+
+    /// Combine with another revocation of the same device,
+    /// so that only the operations which count for both still count.
+    pub fn merge(&mut self, other: &Revocation) {
+        self.up_to
+            .retain(|topic, up_to| match other.up_to.get(topic) {
+                Some(other) => {
+                    *up_to = (*up_to).min(*other);
+                    true
+                }
+                None => false,
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p2panda_core::PrivateKey;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn device() -> DeviceId {
+        DeviceId::from(PrivateKey::new().public_key())
+    }
+
+    #[test]
+    fn test_authorized_devices() {
+        let [anchor, added, added_later, stranger, added_by_stranger] =
+            std::array::from_fn(|_| device());
+
+        // Additions can come in any order, and only count if the adding device is authorized.
+        let authorized = authorized_devices(
+            [anchor],
+            [
+                (added, added_later),
+                (stranger, added_by_stranger),
+                (anchor, added),
+            ],
+        );
+        assert_eq!(
+            authorized,
+            BTreeSet::from_iter([anchor, added, added_later])
+        );
+
+        assert_eq!(authorized_devices([], [(anchor, added)]), BTreeSet::new());
+    }
+
+    #[test]
+    fn test_revocation() {
+        let [chat, group, other] = [1, 2, 3].map(|byte| TopicId::from([byte; 32]));
+        let mut revocation = Revocation::new([
+            LogHeight {
+                topic: chat,
+                seq_num: 3,
+            },
+            LogHeight {
+                topic: group,
+                seq_num: 0,
+            },
+        ]);
+        assert!(revocation.counts(chat, 3));
+        assert!(!revocation.counts(chat, 4));
+        assert!(revocation.counts(group, 0));
+        assert!(!revocation.counts(other, 0));
+
+        revocation.merge(&Revocation::new([LogHeight {
+            topic: chat,
+            seq_num: 1,
+        }]));
+        assert!(revocation.counts(chat, 1));
+        assert!(!revocation.counts(chat, 2));
+        assert!(!revocation.counts(group, 0));
+    }
+
+    #[test]
+    fn test_verification_code() {
+        let a = device();
+        let b = device();
+        assert_eq!(verification_code(a), verification_code(a));
+        assert_ne!(verification_code(a), verification_code(b));
+        assert_eq!(verification_code(a).len(), 19);
+    }
+}
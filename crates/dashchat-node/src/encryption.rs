@@ -0,0 +1,177 @@
+//! End-to-end encryption of the payloads of direct chats.
+//!
+//! Each device has an X25519 identity key, and a prekey signed by it, which are managed
+//! by p2panda-encryption's key manager. Its long-term key bundle, signed once more
+//! by the device itself, is published as a [`KeyBundle`] on the announcements topic
+//! of its agent, and handed out in QR codes.
+//! The prekey only lives for a few weeks, so the device replaces it before it expires
+//! and publishes the new bundle. It keeps the former prekeys to open older payloads.
+//! A payload is sealed with HPKE to the signed prekey of every device of both agents
+//! of a direct chat, so that only they can read it, and mailboxes only ever see ciphertext.
+//!
+//! Codes with an inbox carry an ephemeral key bundle of their own, which only lives
+//! as long as the inbox, and what is sent to the inbox is sealed to it.
+//!
+//! Operations are still signed by their author, so the sender of a sealed payload
+//! is authenticated by the header, not by the encryption.
+
+use named_id::RenameNone;
+use p2panda_core::cbor::encode_cbor;
+use p2panda_core::{PrivateKey, Signature};
+use p2panda_encryption::crypto::Rng;
+use p2panda_encryption::crypto::hpke::{HpkeCiphertext, hpke_open, hpke_seal};
+use p2panda_encryption::crypto::x25519::SecretKey;
+use p2panda_encryption::key_bundle::{Lifetime, LongTermKeyBundle};
+use p2panda_encryption::key_manager::{KeyManager, KeyManagerState};
+use p2panda_encryption::traits::{KeyBundle as _, PreKeyManager};
+use serde::{Deserialize, Serialize};
+
+use crate::{Cbor, DeviceId, Payload};
+
+/// Binds the payloads sealed for a device to the purpose of this module.
+const HPKE_INFO: &[u8] = b"dashchat-sealed-payload";
+
+impl Cbor for KeyManagerState {}
+
+/// Make the keys of a device or an inbox from its identity secret,
+/// with a signed prekey which lives for the given number of seconds.
+pub(crate) fn new_key_manager(
+    identity_secret: &SecretKey,
+    lifetime: u64,
+) -> anyhow::Result<KeyManagerState> {
+    Ok(KeyManager::init(
+        identity_secret,
+        Lifetime::new(lifetime),
+        &Rng::default(),
+    )?)
+}
+
+/// The signed prekey bundle of a device or an inbox, which payloads for it are sealed to,
+/// signed by the device it belongs to, so that no other device can pass off its own keys
+/// as the device's.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameNone)]
+pub struct KeyBundle {
+    pub bundle: LongTermKeyBundle,
+    pub signature: Signature,
+}
+
+impl Cbor for KeyBundle {}
+
+impl KeyBundle {
+    pub fn new(keys: &KeyManagerState, private_key: &PrivateKey) -> anyhow::Result<Self> {
+        let bundle = KeyManager::prekey_bundle(keys);
+        let signature = private_key.sign(&encode_cbor(&bundle)?);
+        Ok(Self { bundle, signature })
+    }
+
+    /// Check that the bundle was signed by the device, and that its prekey was signed
+    /// by its identity key and hasn't expired.
+    pub fn verify(&self, device: DeviceId) -> anyhow::Result<()> {
+        if !device.verify(&encode_cbor(&self.bundle)?, &self.signature) {
+            anyhow::bail!("key bundle not signed by {device:?}");
+        }
+        self.bundle.verify()?;
+        Ok(())
+    }
+}
+
+/// A payload encrypted separately for each device which may read it.
+#[derive(Clone, Debug, Serialize, Deserialize, RenameNone)]
+pub struct SealedPayload {
+    pub recipients: Vec<(DeviceId, HpkeCiphertext)>,
+}
+
+impl SealedPayload {
+    /// Seal a payload for each of the given devices, once their key bundles check out.
+    pub fn seal(
+        payload: &Payload,
+        recipients: impl IntoIterator<Item = (DeviceId, KeyBundle)>,
+    ) -> anyhow::Result<Self> {
+        let plaintext = payload.as_bytes()?;
+        let rng = Rng::default();
+        let recipients = recipients
+            .into_iter()
+            .map(|(device, bundle)| {
+                bundle.verify(device)?;
+                let ciphertext = hpke_seal(
+                    bundle.bundle.signed_prekey(),
+                    Some(HPKE_INFO),
+                    Some(device.as_bytes()),
+                    &plaintext,
+                    &rng,
+                )?;
+                Ok((device, ciphertext))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { recipients })
+    }
+
+    /// Open the payload with whichever of the prekeys of a device it was sealed to.
+    /// None if it wasn't sealed for the device.
+    pub fn open(
+        &self,
+        device: DeviceId,
+        keys: &[KeyManagerState],
+    ) -> anyhow::Result<Option<Payload>> {
+        let Some((_, ciphertext)) = self.recipients.iter().find(|(d, _)| *d == device) else {
+            return Ok(None);
+        };
+        let plaintext = keys
+            .iter()
+            .find_map(|keys| {
+                hpke_open(
+                    ciphertext,
+                    KeyManager::prekey_secret(keys),
+                    Some(HPKE_INFO),
+                    Some(device.as_bytes()),
+                )
+                .ok()
+            })
+            .ok_or(anyhow::anyhow!(
+                "sealed to none of the prekeys of {device:?}"
+            ))?;
+        let payload = Payload::from_bytes(&plaintext)?;
+        if matches!(payload, Payload::Sealed(_)) {
+            anyhow::bail!("sealed payload within a sealed payload");
+        }
+        Ok(Some(payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ChatPayload;
+
+    use super::*;
+
+    #[test]
+    fn test_seal_for_signed_key_bundles() {
+        let private_key = PrivateKey::new();
+        let device = DeviceId::from(private_key.public_key());
+        let secret = SecretKey::from_bytes(rand::random());
+        let keys = new_key_manager(&secret, 3600).unwrap();
+        let bundle = KeyBundle::new(&keys, &private_key).unwrap();
+        bundle.verify(device).unwrap();
+
+        let payload = Payload::Chat(ChatPayload::Message("hi".into()));
+        let sealed = SealedPayload::seal(&payload, [(device, bundle.clone())]).unwrap();
+        assert!(matches!(
+            sealed.open(device, &[keys.clone()]).unwrap(),
+            Some(Payload::Chat(ChatPayload::Message(_)))
+        ));
+
+        // Once the prekey is replaced, the former one is still needed to open the payload.
+        let rotated = new_key_manager(&secret, 3600).unwrap();
+        assert!(sealed.open(device, &[rotated.clone()]).is_err());
+        assert!(matches!(
+            sealed.open(device, &[rotated, keys.clone()]).unwrap(),
+            Some(Payload::Chat(ChatPayload::Message(_)))
+        ));
+
+        // Another device can't pass the bundle off as its own.
+        let other = DeviceId::from(PrivateKey::new().public_key());
+        assert!(bundle.verify(other).is_err());
+        assert!(SealedPayload::seal(&payload, [(other, bundle)]).is_err());
+        assert!(sealed.open(other, &[keys]).unwrap().is_none());
+    }
+}
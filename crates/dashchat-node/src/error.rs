@@ -15,6 +15,15 @@ pub enum Error {
 
     #[error("Failed to get active inboxes: {0}")]
     GetActiveInboxes(String),
+
+    #[error("Failed to upload avatar: {0}")]
+    UploadAvatar(String),
+
+    #[error("Failed to get contact requests: {0}")]
+    GetContactRequests(String),
+
+    #[error("Failed to get key bundle: {0}")]
+    KeyBundle(String),
 }
 
 #[derive(Debug, Error, Serialize)]
@@ -29,6 +38,12 @@ pub enum AddContactError {
     #[error("Failed to create direct chat: {0}")]
     CreateDirectChat(String),
 
+    #[error("No pending contact request from {0}")]
+    NoPendingRequest(String),
+
+    #[error("Failed to link device: {0}")]
+    LinkDevice(String),
+
     #[error(transparent)]
     #[serde(untagged)]
     Common(#[from] Error),
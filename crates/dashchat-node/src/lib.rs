@@ -1,7 +1,10 @@
 #![feature(bool_to_result)]
 
+pub mod blobs;
 mod chat;
 mod contact;
+mod devices;
+mod encryption;
 mod error;
 pub mod node;
 mod payload;
@@ -14,17 +17,24 @@ pub mod polestar;
 mod id;
 pub mod local_store;
 pub mod mailbox;
+pub mod read_model;
 
 #[cfg(feature = "testing")]
 pub mod testing;
 
 use named_id::*;
 
+#[cfg(feature = "testing")]
+pub use chat::testing::ChatMessage;
 pub use chat::*;
-pub use contact::{QrCode, ShareIntent};
+pub use contact::{Contact, PendingContactRequest, QrCode, ShareIntent};
+pub use devices::{PendingDeviceRequest, Revocation, verification_code};
+pub use encryption::{KeyBundle, SealedPayload};
 pub use error::{AddContactError, Error};
 pub use id::*;
-pub use node::{LocalStore, Node, NodeConfig, Notification};
+pub use node::{
+    Event, GroupInvitationPolicy, LocalStore, Node, NodeConfig, Notification, OpStoreBackend,
+};
 pub use p2panda_core::PrivateKey;
 pub use p2panda_spaces::ActorId;
 pub use payload::*;
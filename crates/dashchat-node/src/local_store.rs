@@ -1,23 +1,106 @@
-use std::{collections::BTreeSet, path::Path, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use chrono::{DateTime, Utc};
+use p2panda_encryption::crypto::x25519::SecretKey;
+use p2panda_encryption::key_manager::KeyManagerState;
 use redb::*;
+use serde::{Deserialize, Serialize};
 
-use crate::{contact::InboxTopic, *};
+use crate::{
+    contact::InboxTopic, encryption::new_key_manager, read_model::ReadModel, topic::TopicId, *,
+};
 
 mod impls;
 
 const IDENTITY_TABLE: TableDefinition<&'static str, [u8; 32]> = TableDefinition::new("identity");
 const ACTIVE_INBOXES_TABLE: TableDefinition<InboxTopic, ()> =
     TableDefinition::new("active_inboxes");
+/// Topic ID -> CBOR-encoded SubscribedTopic
+const SUBSCRIBED_TOPICS_TABLE: TableDefinition<[u8; 32], &'static [u8]> =
+    TableDefinition::new("subscribed_topics");
+/// Chat ID -> agent ID of the inviter
+const GROUP_INVITATIONS_TABLE: TableDefinition<[u8; 32], [u8; 32]> =
+    TableDefinition::new("group_invitations");
+/// Topic ID -> hashes of operations which have been processed in that topic
+const PROCESSED_OPS_TABLE: MultimapTableDefinition<[u8; 32], [u8; 32]> =
+    MultimapTableDefinition::new("processed_ops");
+/// Topic ID -> hashes of operations which were received but not processed yet
+const PENDING_OPS_TABLE: MultimapTableDefinition<[u8; 32], [u8; 32]> =
+    MultimapTableDefinition::new("pending_ops");
+/// Message hash -> devices which deleted that message
+const DELETED_MESSAGES_TABLE: MultimapTableDefinition<[u8; 32], [u8; 32]> =
+    MultimapTableDefinition::new("deleted_messages");
+/// Message hash -> topic ID of deleted messages whose deletion couldn't be checked yet
+const PENDING_DELETIONS_TABLE: TableDefinition<[u8; 32], [u8; 32]> =
+    TableDefinition::new("pending_deletions");
+/// Chat IDs of chats for which I don't send read receipts
+const READ_RECEIPTS_DISABLED_TABLE: TableDefinition<[u8; 32], ()> =
+    TableDefinition::new("read_receipts_disabled");
+/// Inbox topic IDs of AddDevice codes which no device was linked through yet
+const DEVICE_INBOXES_TABLE: TableDefinition<[u8; 32], ()> = TableDefinition::new("device_inboxes");
+/// Device -> CBOR-encoded Revocation, which says which of its operations still count
+const REVOCATIONS_TABLE: TableDefinition<[u8; 32], &'static [u8]> =
+    TableDefinition::new("revocations");
+/// (Agent ID, index) -> topic IDs which the device group of the agent moved to, in order,
+/// after the one it started on
+const DEVICE_GROUP_TOPICS_TABLE: TableDefinition<([u8; 32], u64), [u8; 32]> =
+    TableDefinition::new("device_group_topics");
+
+/// Hashes of operations which were published to at least one mailbox
+const PUBLISHED_OPS_TABLE: TableDefinition<[u8; 32], ()> = TableDefinition::new("published_ops");
+
+/// Inbox topic ID -> CBOR-encoded keys which what is sent to the inbox is sealed to
+const INBOX_KEYS_TABLE: TableDefinition<[u8; 32], &'static [u8]> =
+    TableDefinition::new("inbox_key_managers");
+/// Time the keys were made at, in seconds -> CBOR-encoded keys of this device, with the signed
+/// prekey which payloads for it are sealed to. The latest keys are the current ones.
+/// Earlier ones are kept, since what was sealed to them stays in the logs, and is opened
+/// again whenever the read model is rebuilt.
+const KEY_MANAGERS_TABLE: TableDefinition<u64, &'static [u8]> =
+    TableDefinition::new("key_managers");
+/// Hashes of operations whose payloads weren't sealed for this device,
+/// until another of my devices backfills them
+const UNOPENED_OPS_TABLE: TableDefinition<[u8; 32], ()> = TableDefinition::new("unopened_ops");
+/// Operation hash -> CBOR-encoded payload which another of my devices opened for this one
+const BACKFILLS_TABLE: TableDefinition<[u8; 32], &'static [u8]> = TableDefinition::new("backfills");
+
+/// How long the signed prekey of a device lives, in seconds.
+const DEVICE_PREKEY_LIFETIME: u64 = 4 * 7 * 24 * 60 * 60;
+/// How old the signed prekey of a device gets before it's replaced, in seconds.
+/// This leaves a week for the new key bundle to reach the other devices
+/// before the old one expires.
+const DEVICE_PREKEY_ROTATION: u64 = 3 * 7 * 24 * 60 * 60;
 
 const PRIVATE_KEY_KEY: &str = "private_key";
 const AGENT_ID_KEY: &str = "agent_id";
+const ENCRYPTION_KEY_KEY: &str = "encryption_key";
+/// The agent this device asked to be linked to, until it's added
+const LINKING_AGENT_KEY: &str = "linking_agent";
+/// The device which showed the AddDevice code this device was linked with
+const LINKING_DEVICE_KEY: &str = "linking_device";
+const LINKED_FROM_KEY: &str = "linked_from";
+
+/// A topic which the node subscribed to, and why,
+/// so that it can be subscribed to again when the node starts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubscribedTopic {
+    /// A contact's announcements topic
+    Announcements(AgentId),
+    /// The direct chat with a contact
+    DirectChat(AgentId),
+    /// A group chat
+    GroupChat,
+}
+
+impl Cbor for SubscribedTopic {}
 
 #[derive(Clone, Debug)]
 pub struct NodeData {
     pub private_key: PrivateKey,
-    pub agent_id: AgentId,
 }
 
 impl NodeData {
@@ -29,13 +112,16 @@ impl NodeData {
 #[derive(Clone)]
 pub struct LocalStore {
     db: Arc<Database>,
+    path: PathBuf,
 }
 
 impl LocalStore {
     pub fn new(path: impl AsRef<Path>) -> anyhow::Result<Self> {
-        let database = Database::create(path)?;
+        let path = path.as_ref().to_path_buf();
+        let database = Database::create(&path)?;
         let store = Self {
             db: Arc::new(database),
+            path,
         };
         store.ensure_initialized()?;
 
@@ -50,12 +136,39 @@ impl LocalStore {
         {
             let mut identity = txn.open_table(IDENTITY_TABLE)?;
             let _ = txn.open_table(ACTIVE_INBOXES_TABLE)?;
+            let _ = txn.open_table(SUBSCRIBED_TOPICS_TABLE)?;
+            let _ = txn.open_table(GROUP_INVITATIONS_TABLE)?;
+            let _ = txn.open_multimap_table(PROCESSED_OPS_TABLE)?;
+            let _ = txn.open_multimap_table(PENDING_OPS_TABLE)?;
+            let _ = txn.open_multimap_table(DELETED_MESSAGES_TABLE)?;
+            let _ = txn.open_table(PENDING_DELETIONS_TABLE)?;
+            let _ = txn.open_table(READ_RECEIPTS_DISABLED_TABLE)?;
+            let _ = txn.open_table(DEVICE_INBOXES_TABLE)?;
+            let _ = txn.open_table(REVOCATIONS_TABLE)?;
+            let _ = txn.open_table(DEVICE_GROUP_TOPICS_TABLE)?;
+            let _ = txn.open_table(INBOX_KEYS_TABLE)?;
+            let _ = txn.open_table(UNOPENED_OPS_TABLE)?;
+            let _ = txn.open_table(BACKFILLS_TABLE)?;
+            let _ = txn.open_table(PUBLISHED_OPS_TABLE)?;
             let uninitialized =
                 identity.get(PRIVATE_KEY_KEY)?.is_none() && identity.get(AGENT_ID_KEY)?.is_none();
             if uninitialized {
                 identity.insert(PRIVATE_KEY_KEY, private_key.as_bytes())?;
                 identity.insert(AGENT_ID_KEY, agent_id.as_bytes())?;
             }
+            // Stores created before payloads were encrypted don't have a key yet.
+            if identity.get(ENCRYPTION_KEY_KEY)?.is_none() {
+                identity.insert(ENCRYPTION_KEY_KEY, rand::random::<[u8; 32]>())?;
+            }
+            let mut key_managers = txn.open_table(KEY_MANAGERS_TABLE)?;
+            if key_managers.is_empty()? {
+                let secret = identity
+                    .get(ENCRYPTION_KEY_KEY)?
+                    .map(|secret| SecretKey::from_bytes(secret.value()))
+                    .ok_or(anyhow::anyhow!("Encryption key field not found"))?;
+                let keys = new_key_manager(&secret, DEVICE_PREKEY_LIFETIME)?;
+                key_managers.insert(Utc::now().timestamp() as u64, keys.as_bytes()?.as_slice())?;
+            }
         }
 
         txn.commit()?;
@@ -63,10 +176,24 @@ impl LocalStore {
         Ok(())
     }
 
+    /// The path of the operation store database, which lives next to this store's file.
+    pub fn op_store_path(&self) -> PathBuf {
+        self.path.with_extension("sqlite")
+    }
+
+    /// The directory of cached files, which lives next to this store's file.
+    pub fn blob_cache_path(&self) -> PathBuf {
+        self.path.with_extension("blobs")
+    }
+
+    /// The read model, which keeps its tables in this store's database.
+    pub fn read_model(&self) -> anyhow::Result<ReadModel> {
+        ReadModel::new(self.db.clone())
+    }
+
     pub fn node_data(&self) -> anyhow::Result<NodeData> {
         Ok(NodeData {
             private_key: self.private_key()?,
-            agent_id: self.agent_id()?,
         })
     }
 
@@ -83,6 +210,54 @@ impl LocalStore {
         Ok(DeviceId::from(self.private_key()?.public_key()))
     }
 
+    /// The current keys of this device: its identity key, which is its encryption key,
+    /// and the prekey signed by it which payloads for this device are sealed to.
+    pub fn key_manager(&self) -> anyhow::Result<KeyManagerState> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(KEY_MANAGERS_TABLE)?;
+        let (_, keys) = table
+            .last()?
+            .ok_or(anyhow::anyhow!("Key manager not found"))?;
+        Ok(KeyManagerState::from_bytes(keys.value())?)
+    }
+
+    /// All keys this device ever had, the current ones first,
+    /// to open payloads which were sealed to an earlier prekey.
+    pub fn key_managers(&self) -> anyhow::Result<Vec<KeyManagerState>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(KEY_MANAGERS_TABLE)?;
+        table
+            .iter()?
+            .rev()
+            .map(|entry| Ok(KeyManagerState::from_bytes(entry?.1.value())?))
+            .collect()
+    }
+
+    /// Replace the prekey of this device with a new one once it's old enough,
+    /// keeping the former keys. True if it was replaced, so that the new key bundle
+    /// has to be published.
+    pub fn rotate_prekey(&self, now: u64) -> anyhow::Result<bool> {
+        let txn = self.db.begin_write()?;
+        let rotated = {
+            let mut key_managers = txn.open_table(KEY_MANAGERS_TABLE)?;
+            let made_at = key_managers.last()?.map(|(made_at, _)| made_at.value());
+            if made_at.is_none_or(|made_at| now >= made_at.saturating_add(DEVICE_PREKEY_ROTATION)) {
+                let secret = txn
+                    .open_table(IDENTITY_TABLE)?
+                    .get(ENCRYPTION_KEY_KEY)?
+                    .map(|secret| SecretKey::from_bytes(secret.value()))
+                    .ok_or(anyhow::anyhow!("Encryption key field not found"))?;
+                let keys = new_key_manager(&secret, DEVICE_PREKEY_LIFETIME)?;
+                key_managers.insert(now, keys.as_bytes()?.as_slice())?;
+                true
+            } else {
+                false
+            }
+        };
+        txn.commit()?;
+        Ok(rotated)
+    }
+
     pub fn agent_id(&self) -> anyhow::Result<AgentId> {
         let txn = self.db.begin_read()?;
         let table = txn.open_table(IDENTITY_TABLE)?;
@@ -94,6 +269,68 @@ impl LocalStore {
         )?))
     }
 
+    /// The agent this device asked to be linked to, and the device it asked,
+    /// until that device has added this one.
+    pub fn pending_link(&self) -> anyhow::Result<Option<(AgentId, DeviceId)>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(IDENTITY_TABLE)?;
+        let (Some(agent_id), Some(device)) = (
+            table.get(LINKING_AGENT_KEY)?,
+            table.get(LINKING_DEVICE_KEY)?,
+        ) else {
+            return Ok(None);
+        };
+        Ok(Some((
+            AgentId::from(crate::ActorId::from_bytes(&agent_id.value())?),
+            DeviceId::from(p2panda_core::PublicKey::from_bytes(&device.value())?),
+        )))
+    }
+
+    pub fn set_pending_link(&self, agent_id: AgentId, device: DeviceId) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(IDENTITY_TABLE)?;
+            table.insert(LINKING_AGENT_KEY, agent_id.as_bytes())?;
+            table.insert(LINKING_DEVICE_KEY, *device.as_bytes())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Take on the agent of the pending link, once its device has added this one,
+    /// and trust that device from then on, see [`LocalStore::linked_from`].
+    /// Returns the agent, if a link was pending.
+    pub fn complete_link(&self) -> anyhow::Result<Option<AgentId>> {
+        let Some((agent_id, device)) = self.pending_link()? else {
+            return Ok(None);
+        };
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(IDENTITY_TABLE)?;
+            table.insert(AGENT_ID_KEY, agent_id.as_bytes())?;
+            table.insert(LINKED_FROM_KEY, *device.as_bytes())?;
+            table.remove(LINKING_AGENT_KEY)?;
+            table.remove(LINKING_DEVICE_KEY)?;
+        }
+        txn.commit()?;
+        Ok(Some(agent_id))
+    }
+
+    /// The device which linked this one to my agent, if any.
+    /// My devices are the ones it and this device added, and so on.
+    pub fn linked_from(&self) -> anyhow::Result<Option<DeviceId>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(IDENTITY_TABLE)?;
+        table
+            .get(LINKED_FROM_KEY)?
+            .map(|device| {
+                Ok(DeviceId::from(p2panda_core::PublicKey::from_bytes(
+                    &device.value(),
+                )?))
+            })
+            .transpose()
+    }
+
     pub fn get_active_inbox_topics(&self) -> anyhow::Result<BTreeSet<InboxTopic>> {
         let txn = self.db.begin_read()?;
         let table = txn.open_table(ACTIVE_INBOXES_TABLE)?;
@@ -125,7 +362,455 @@ impl LocalStore {
                 expires_at,
                 topic: Topic::new([0; 32]),
             };
-            table.retain_in(..limit, |_, _| false)?;
+            let mut pruned = vec![];
+            table.retain_in(..limit, |inbox, _| {
+                pruned.push(**inbox.topic);
+                false
+            })?;
+            let mut keys = txn.open_table(INBOX_KEYS_TABLE)?;
+            for topic in pruned {
+                keys.remove(topic)?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// The ephemeral keys of one of my inboxes, which payloads sent to it are sealed to.
+    pub fn inbox_key(&self, topic: TopicId) -> anyhow::Result<Option<KeyManagerState>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(INBOX_KEYS_TABLE)?;
+        table
+            .get(*topic)?
+            .map(|keys| Ok(KeyManagerState::from_bytes(keys.value())?))
+            .transpose()
+    }
+
+    /// Make new ephemeral keys for an inbox, whose prekey lives for the given number
+    /// of seconds. They're forgotten once the inbox expires.
+    pub fn add_inbox_key(&self, topic: TopicId, lifetime: u64) -> anyhow::Result<KeyManagerState> {
+        let secret = SecretKey::from_bytes(rand::random::<[u8; 32]>());
+        let keys = new_key_manager(&secret, lifetime)?;
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(INBOX_KEYS_TABLE)?;
+            table.insert(*topic, keys.as_bytes()?.as_slice())?;
+        }
+        txn.commit()?;
+        Ok(keys)
+    }
+
+    /// Remember an operation whose payload wasn't sealed for this device,
+    /// unless another of my devices already backfilled it.
+    pub fn add_unopened_op(&self, hash: &p2panda_core::Hash) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            if txn
+                .open_table(BACKFILLS_TABLE)?
+                .get(*hash.as_bytes())?
+                .is_none()
+            {
+                txn.open_table(UNOPENED_OPS_TABLE)?
+                    .insert(*hash.as_bytes(), ())?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Store the payload of an operation which another of my devices opened for this one.
+    /// True if the operation was waiting for it, and can be processed now.
+    pub fn add_backfill(
+        &self,
+        hash: &p2panda_core::Hash,
+        payload: &Payload,
+    ) -> anyhow::Result<bool> {
+        let txn = self.db.begin_write()?;
+        let unopened = {
+            txn.open_table(BACKFILLS_TABLE)?
+                .insert(*hash.as_bytes(), payload.as_bytes()?.as_slice())?;
+            txn.open_table(UNOPENED_OPS_TABLE)?
+                .remove(*hash.as_bytes())?
+                .is_some()
+        };
+        txn.commit()?;
+        Ok(unopened)
+    }
+
+    /// The payload of an operation which wasn't sealed for this device,
+    /// if another of my devices backfilled it.
+    pub fn get_backfill(&self, hash: &p2panda_core::Hash) -> anyhow::Result<Option<Payload>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(BACKFILLS_TABLE)?;
+        table
+            .get(*hash.as_bytes())?
+            .map(|payload| Ok(Payload::from_bytes(payload.value())?))
+            .transpose()
+    }
+
+    pub fn get_subscribed_topics(&self) -> anyhow::Result<BTreeMap<TopicId, SubscribedTopic>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(SUBSCRIBED_TOPICS_TABLE)?;
+        let topics = table
+            .iter()?
+            .map(|entry| {
+                let (topic, subscription) = entry?;
+                Ok((
+                    TopicId::from(topic.value()),
+                    SubscribedTopic::from_bytes(subscription.value())?,
+                ))
+            })
+            .collect::<anyhow::Result<BTreeMap<TopicId, SubscribedTopic>>>()?;
+        Ok(topics)
+    }
+
+    pub fn add_subscribed_topic(
+        &self,
+        topic: TopicId,
+        subscription: SubscribedTopic,
+    ) -> anyhow::Result<()> {
+        let bytes = subscription.as_bytes()?;
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(SUBSCRIBED_TOPICS_TABLE)?;
+            table.insert(*topic, bytes.as_slice())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    pub fn remove_subscribed_topic(&self, topic: TopicId) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(SUBSCRIBED_TOPICS_TABLE)?;
+            table.remove(*topic)?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    pub fn get_group_invitations(&self) -> anyhow::Result<Vec<GroupInvitation>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(GROUP_INVITATIONS_TABLE)?;
+        let invitations = table
+            .iter()?
+            .map(|entry| {
+                let (chat_id, inviter) = entry?;
+                Ok(GroupInvitation {
+                    chat_id: ChatId::new(chat_id.value()),
+                    inviter: AgentId::from(ActorId::from_bytes(&inviter.value())?),
+                })
+            })
+            .collect::<anyhow::Result<Vec<GroupInvitation>>>()?;
+        Ok(invitations)
+    }
+
+    pub fn add_group_invitation(&self, invitation: GroupInvitation) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(GROUP_INVITATIONS_TABLE)?;
+            table.insert(**invitation.chat_id, invitation.inviter.as_bytes())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Remember that an inbox belongs to an AddDevice code,
+    /// so that a device which asks to be linked through it is added.
+    pub fn add_device_inbox(&self, topic: TopicId) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(DEVICE_INBOXES_TABLE)?;
+            table.insert(*topic, ())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Whether an inbox belongs to an AddDevice code which no device was linked through yet.
+    pub fn is_device_inbox(&self, topic: TopicId) -> anyhow::Result<bool> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(DEVICE_INBOXES_TABLE)?;
+        Ok(table.get(*topic)?.is_some())
+    }
+
+    /// Forget an AddDevice inbox, so that each code links only one device.
+    /// Returns whether it was an AddDevice inbox which wasn't used yet.
+    pub fn take_device_inbox(&self, topic: TopicId) -> anyhow::Result<bool> {
+        let txn = self.db.begin_write()?;
+        let removed = {
+            let mut table = txn.open_table(DEVICE_INBOXES_TABLE)?;
+            table.remove(*topic)?.is_some()
+        };
+        txn.commit()?;
+        Ok(removed)
+    }
+
+    /// Returns whether there was an invitation to remove.
+    pub fn remove_group_invitation(&self, chat_id: ChatId) -> anyhow::Result<bool> {
+        let txn = self.db.begin_write()?;
+        let removed = {
+            let mut table = txn.open_table(GROUP_INVITATIONS_TABLE)?;
+            table.remove(**chat_id)?.is_some()
+        };
+        txn.commit()?;
+        Ok(removed)
+    }
+
+    pub fn get_processed_ops(&self) -> anyhow::Result<Vec<(TopicId, p2panda_core::Hash)>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_multimap_table(PROCESSED_OPS_TABLE)?;
+        let mut processed = vec![];
+        for entry in table.iter()? {
+            let (topic, hashes) = entry?;
+            let topic = TopicId::from(topic.value());
+            for hash in hashes {
+                processed.push((topic, p2panda_core::Hash::from_bytes(hash?.value())));
+            }
+        }
+        Ok(processed)
+    }
+
+    /// Remember that an operation was processed, which means it's no longer pending.
+    pub fn add_processed_op(
+        &self,
+        topic: TopicId,
+        hash: &p2panda_core::Hash,
+    ) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_multimap_table(PROCESSED_OPS_TABLE)?;
+            table.insert(*topic, *hash.as_bytes())?;
+            let mut pending = txn.open_multimap_table(PENDING_OPS_TABLE)?;
+            pending.remove(*topic, *hash.as_bytes())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// The operations which were received, and so are in the op store,
+    /// but haven't been processed yet.
+    pub fn get_pending_ops(&self) -> anyhow::Result<Vec<(TopicId, p2panda_core::Hash)>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_multimap_table(PENDING_OPS_TABLE)?;
+        let mut pending = vec![];
+        for entry in table.iter()? {
+            let (topic, hashes) = entry?;
+            let topic = TopicId::from(topic.value());
+            for hash in hashes {
+                pending.push((topic, p2panda_core::Hash::from_bytes(hash?.value())));
+            }
+        }
+        Ok(pending)
+    }
+
+    pub fn add_pending_op(&self, topic: TopicId, hash: &p2panda_core::Hash) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_multimap_table(PENDING_OPS_TABLE)?;
+            table.insert(*topic, *hash.as_bytes())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Whether an operation was published to at least one mailbox.
+    pub fn is_op_published(&self, hash: &p2panda_core::Hash) -> anyhow::Result<bool> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(PUBLISHED_OPS_TABLE)?;
+        Ok(table.get(*hash.as_bytes())?.is_some())
+    }
+
+    pub fn add_published_ops<'a>(
+        &self,
+        hashes: impl IntoIterator<Item = &'a p2panda_core::Hash>,
+    ) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(PUBLISHED_OPS_TABLE)?;
+            for hash in hashes {
+                table.insert(*hash.as_bytes(), ())?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// The devices which deleted a message.
+    /// Whether they were allowed to is up to the caller.
+    pub fn get_message_deleters(
+        &self,
+        message: &p2panda_core::Hash,
+    ) -> anyhow::Result<Vec<DeviceId>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_multimap_table(DELETED_MESSAGES_TABLE)?;
+        table
+            .get(*message.as_bytes())?
+            .map(|deleter| {
+                let deleter = p2panda_core::PublicKey::from_bytes(&deleter?.value())?;
+                Ok(DeviceId::from(deleter))
+            })
+            .collect()
+    }
+
+    /// Remember a deletion, which stays pending until [`Self::resolve_deletion`].
+    pub fn add_message_deleter(
+        &self,
+        topic: TopicId,
+        message: &p2panda_core::Hash,
+        deleter: DeviceId,
+    ) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_multimap_table(DELETED_MESSAGES_TABLE)?;
+            table.insert(*message.as_bytes(), *deleter.as_bytes())?;
+            let mut pending = txn.open_table(PENDING_DELETIONS_TABLE)?;
+            pending.insert(*message.as_bytes(), *topic)?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// The deleted messages, with their topic, for which it couldn't be decided yet
+    /// whether the deletion is valid, because the message or the agent of a device
+    /// isn't known yet.
+    pub fn get_pending_deletions(&self) -> anyhow::Result<Vec<(TopicId, p2panda_core::Hash)>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(PENDING_DELETIONS_TABLE)?;
+        table
+            .iter()?
+            .map(|entry| {
+                let (message, topic) = entry?;
+                Ok((
+                    TopicId::from(topic.value()),
+                    p2panda_core::Hash::from_bytes(message.value()),
+                ))
+            })
+            .collect()
+    }
+
+    /// The deletion of a message was either applied or rejected for good.
+    pub fn resolve_deletion(&self, message: &p2panda_core::Hash) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(PENDING_DELETIONS_TABLE)?;
+            table.remove(*message.as_bytes())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// The revoked devices, with which of their operations still count.
+    pub fn get_revoked_devices(&self) -> anyhow::Result<BTreeMap<DeviceId, Revocation>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(REVOCATIONS_TABLE)?;
+        table
+            .iter()?
+            .map(|entry| {
+                let (device, revocation) = entry?;
+                let device = p2panda_core::PublicKey::from_bytes(&device.value())?;
+                Ok((
+                    DeviceId::from(device),
+                    Revocation::from_bytes(revocation.value())?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Whether an operation authored by a device at a given position in its log
+    /// must be ignored.
+    pub fn is_revoked(
+        &self,
+        device: DeviceId,
+        topic: TopicId,
+        seq_num: u64,
+    ) -> anyhow::Result<bool> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(REVOCATIONS_TABLE)?;
+        Ok(match table.get(*device.as_bytes())? {
+            Some(revocation) => !Revocation::from_bytes(revocation.value())?.counts(topic, seq_num),
+            None => false,
+        })
+    }
+
+    /// Revoke a device. If it was already revoked, only the operations
+    /// which count for both revocations still count.
+    pub fn add_revoked_device(
+        &self,
+        device: DeviceId,
+        mut revocation: Revocation,
+    ) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(REVOCATIONS_TABLE)?;
+            let existing = table
+                .get(*device.as_bytes())?
+                .map(|existing| Revocation::from_bytes(existing.value()))
+                .transpose()?;
+            if let Some(existing) = existing {
+                revocation.merge(&existing);
+            }
+            table.insert(*device.as_bytes(), revocation.as_bytes()?.as_slice())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// The topics the device group of an agent moved to, in order,
+    /// after the one it started on.
+    pub fn get_device_group_topics(&self, agent_id: AgentId) -> anyhow::Result<Vec<TopicId>> {
+        let agent = *agent_id.as_bytes();
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(DEVICE_GROUP_TOPICS_TABLE)?;
+        table
+            .range((agent, 0)..=(agent, u64::MAX))?
+            .map(|entry| Ok(TopicId::from(entry?.1.value())))
+            .collect()
+    }
+
+    /// Record that the device group of an agent moved to a topic, unless it already did.
+    /// Returns whether it's new.
+    pub fn add_device_group_topic(
+        &self,
+        agent_id: AgentId,
+        topic: TopicId,
+    ) -> anyhow::Result<bool> {
+        let agent = *agent_id.as_bytes();
+        let txn = self.db.begin_write()?;
+        let added = {
+            let mut table = txn.open_table(DEVICE_GROUP_TOPICS_TABLE)?;
+            let mut next = 0;
+            let mut known = false;
+            for entry in table.range((agent, 0)..=(agent, u64::MAX))? {
+                let (key, existing) = entry?;
+                known |= existing.value() == *topic;
+                next = key.value().1 + 1;
+            }
+            if !known {
+                table.insert((agent, next), *topic)?;
+            }
+            !known
+        };
+        txn.commit()?;
+        Ok(added)
+    }
+
+    /// Read receipts are sent unless they were disabled for the chat.
+    pub fn read_receipts_enabled(&self, chat_id: ChatId) -> anyhow::Result<bool> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(READ_RECEIPTS_DISABLED_TABLE)?;
+        Ok(table.get(**chat_id)?.is_none())
+    }
+
+    pub fn set_read_receipts_enabled(&self, chat_id: ChatId, enabled: bool) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(READ_RECEIPTS_DISABLED_TABLE)?;
+            if enabled {
+                table.remove(**chat_id)?;
+            } else {
+                table.insert(**chat_id, ())?;
+            }
         }
         txn.commit()?;
         Ok(())
@@ -164,6 +849,22 @@ mod tests {
         assert_eq!(store.agent_id().unwrap(), agent_id);
     }
 
+    #[test]
+    fn test_published_ops_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_published_ops.db");
+        let store = LocalStore::new(&path).unwrap();
+        let published = p2panda_core::Hash::new(b"published");
+        let unpublished = p2panda_core::Hash::new(b"unpublished");
+        store.add_published_ops([&published]).unwrap();
+
+        drop(store);
+
+        let store = LocalStore::new(path).unwrap();
+        assert!(store.is_op_published(&published).unwrap());
+        assert!(!store.is_op_published(&unpublished).unwrap());
+    }
+
     #[test]
     fn test_prune_expired_active_inbox_topics() {
         let dir = tempfile::tempdir().unwrap();
@@ -203,17 +904,28 @@ mod tests {
             txn.commit().unwrap();
         }
 
+        for t in &topics {
+            store.add_inbox_key(t.topic.into(), 3600).unwrap();
+        }
+
         // Check all topics are present
         let loaded_topics = store.get_active_inbox_topics().unwrap();
         assert_eq!(loaded_topics, topics);
 
         // Prune topics expired before 'now'
         store.prune_expired_active_inbox_topics(now).unwrap();
-        topics.pop_first().unwrap();
+        let expired_topic = topics.pop_first().unwrap().topic;
 
-        // Only the expired one should be gone
+        // Only the expired one should be gone, along with its key
         let loaded_topics = store.get_active_inbox_topics().unwrap();
         assert_eq!(loaded_topics, topics);
+        assert!(store.inbox_key(expired_topic.into()).unwrap().is_none());
+        assert!(
+            store
+                .inbox_key(topics.first().unwrap().topic.into())
+                .unwrap()
+                .is_some()
+        );
 
         // Prune all topics before 'more_valid' (should leave only the last one)
         store.prune_expired_active_inbox_topics(more_valid).unwrap();
@@ -222,4 +934,188 @@ mod tests {
         let loaded_topics = store.get_active_inbox_topics().unwrap();
         assert_eq!(loaded_topics, topics);
     }
+
+    #[test]
+    fn test_processed_ops_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_processed_ops.db");
+        let store = LocalStore::new(&path).unwrap();
+
+        let topic = TopicId::from([1; 32]);
+        let hash1 = p2panda_core::Hash::new(b"one");
+        let hash2 = p2panda_core::Hash::new(b"two");
+        store.add_processed_op(topic, &hash1).unwrap();
+        store.add_processed_op(topic, &hash2).unwrap();
+        store.add_processed_op(topic, &hash1).unwrap();
+
+        drop(store);
+
+        let store = LocalStore::new(path).unwrap();
+        let mut processed = store.get_processed_ops().unwrap();
+        processed.sort();
+        let mut expected = vec![(topic, hash1), (topic, hash2)];
+        expected.sort();
+        assert_eq!(processed, expected);
+    }
+
+    #[test]
+    fn test_pending_ops_until_processed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_pending_ops.db");
+        let store = LocalStore::new(&path).unwrap();
+
+        let topic = TopicId::from([1; 32]);
+        let hash1 = p2panda_core::Hash::new(b"one");
+        let hash2 = p2panda_core::Hash::new(b"two");
+        store.add_pending_op(topic, &hash1).unwrap();
+        store.add_pending_op(topic, &hash2).unwrap();
+        store.add_processed_op(topic, &hash1).unwrap();
+
+        drop(store);
+
+        let store = LocalStore::new(path).unwrap();
+        assert_eq!(store.get_pending_ops().unwrap(), vec![(topic, hash2)]);
+    }
+
+    #[test]
+    fn test_pending_deletions_until_resolved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_pending_deletions.db");
+        let store = LocalStore::new(&path).unwrap();
+
+        let topic = TopicId::from([1; 32]);
+        let deleter = DeviceId::from(PrivateKey::new().public_key());
+        let message1 = p2panda_core::Hash::new(b"one");
+        let message2 = p2panda_core::Hash::new(b"two");
+        store
+            .add_message_deleter(topic, &message1, deleter)
+            .unwrap();
+        store
+            .add_message_deleter(topic, &message2, deleter)
+            .unwrap();
+        store.resolve_deletion(&message1).unwrap();
+
+        drop(store);
+
+        let store = LocalStore::new(path).unwrap();
+        assert_eq!(
+            store.get_pending_deletions().unwrap(),
+            vec![(topic, message2)]
+        );
+        assert_eq!(
+            store.get_message_deleters(&message1).unwrap(),
+            vec![deleter]
+        );
+    }
+
+    #[test]
+    fn test_device_group_topics() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_device_group_topics.db");
+        let store = LocalStore::new(&path).unwrap();
+
+        let [agent, other] =
+            std::array::from_fn(|_| AgentId::from(ActorId::from(PrivateKey::new().public_key())));
+        let [first, second] = [1, 2].map(|byte| TopicId::from([byte; 32]));
+        assert!(store.add_device_group_topic(agent, first).unwrap());
+        assert!(store.add_device_group_topic(agent, second).unwrap());
+        assert!(!store.add_device_group_topic(agent, first).unwrap());
+
+        drop(store);
+
+        let store = LocalStore::new(path).unwrap();
+        assert_eq!(
+            store.get_device_group_topics(agent).unwrap(),
+            vec![first, second]
+        );
+        assert!(store.get_device_group_topics(other).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rotate_prekey() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_rotate_prekey.db");
+        let store = LocalStore::new(&path).unwrap();
+        let private_key = store.private_key().unwrap();
+        let bundle = |keys: &KeyManagerState| KeyBundle::new(keys, &private_key).unwrap();
+
+        let now = Utc::now().timestamp() as u64;
+        let first = bundle(&store.key_manager().unwrap());
+        assert!(!store.rotate_prekey(now).unwrap());
+        assert!(store.rotate_prekey(now + DEVICE_PREKEY_ROTATION).unwrap());
+
+        drop(store);
+
+        // The new prekey is the current one, and the former one is kept.
+        let store = LocalStore::new(path).unwrap();
+        let current = bundle(&store.key_manager().unwrap());
+        assert_ne!(current, first);
+        let all = store
+            .key_managers()
+            .unwrap()
+            .iter()
+            .map(bundle)
+            .collect::<Vec<_>>();
+        assert_eq!(all, vec![current, first]);
+    }
+
+    #[test]
+    fn test_backfills() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_backfills.db");
+        let store = LocalStore::new(&path).unwrap();
+
+        let payload = Payload::Chat(ChatPayload::Message("hi".into()));
+        let unopened = p2panda_core::Hash::new(b"unopened");
+        let not_yet_received = p2panda_core::Hash::new(b"not yet received");
+
+        // A backfill for an operation which was waiting for it can be processed right away.
+        store.add_unopened_op(&unopened).unwrap();
+        assert!(store.add_backfill(&unopened, &payload).unwrap());
+        assert!(!store.add_backfill(&unopened, &payload).unwrap());
+
+        // One which arrives before the operation is kept for when it arrives.
+        assert!(!store.add_backfill(&not_yet_received, &payload).unwrap());
+        store.add_unopened_op(&not_yet_received).unwrap();
+        assert!(!store.add_backfill(&not_yet_received, &payload).unwrap());
+
+        assert!(store.get_backfill(&unopened).unwrap().is_some());
+        assert!(
+            store
+                .get_backfill(&p2panda_core::Hash::new(b"other"))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_subscribed_topics() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_subscribed_topics.db");
+        let store = LocalStore::new(&path).unwrap();
+
+        let agent = AgentId::from(ActorId::from(PrivateKey::new().public_key()));
+        let direct = TopicId::from([1; 32]);
+        let group = TopicId::from([2; 32]);
+        store
+            .add_subscribed_topic(direct, SubscribedTopic::DirectChat(agent))
+            .unwrap();
+        store
+            .add_subscribed_topic(group, SubscribedTopic::GroupChat)
+            .unwrap();
+        store
+            .add_subscribed_topic(group, SubscribedTopic::GroupChat)
+            .unwrap();
+
+        drop(store);
+
+        let store = LocalStore::new(path).unwrap();
+        assert_eq!(
+            store.get_subscribed_topics().unwrap(),
+            maplit::btreemap! {
+                direct => SubscribedTopic::DirectChat(agent),
+                group => SubscribedTopic::GroupChat,
+            }
+        );
+    }
 }
@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{DeviceId, Header, Operation, topic::TopicId};
+use crate::{DeviceId, Header, LocalStore, Operation, node::NodeOpStore, topic::TopicId};
 use mailbox_client::MailboxItem;
 use p2panda_core::{Body, PublicKey};
+use p2panda_store::LogStore;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct MailboxOperation {
@@ -85,6 +86,49 @@ impl From<(Header, Option<Body>)> for MailboxOperation {
     }
 }
 
+/// The store which the mailboxes sync from: operations come from the op store,
+/// and which of them were published is remembered in the local store,
+/// so that it's still known after a restart.
+#[derive(Clone)]
+pub struct NodeMailboxStore {
+    pub(crate) op_store: NodeOpStore,
+    pub(crate) local_store: LocalStore,
+}
+
+#[async_trait::async_trait]
+impl mailbox_client::store::MailboxStore<MailboxOperation> for NodeMailboxStore {
+    async fn get_log(
+        &self,
+        author: &DeviceId,
+        topic: &TopicId,
+        from: u64,
+    ) -> Result<Option<Vec<MailboxOperation>>, anyhow::Error> {
+        let log = self
+            .op_store
+            .store
+            .get_log(author, topic, Some(from))
+            .await
+            .map_err(|err| anyhow::anyhow!("failed to get log for {author:?}: {topic:?}: {err}"))?;
+        Ok(log.map(|log| {
+            log.into_iter()
+                .map(|(header, body)| MailboxOperation { header, body })
+                .collect()
+        }))
+    }
+
+    async fn get_log_heights(&self, topic: &TopicId) -> anyhow::Result<Vec<(DeviceId, u64)>> {
+        self.op_store.get_log_heights(topic).await
+    }
+
+    async fn add_published(&self, hashes: &[p2panda_core::Hash]) -> anyhow::Result<()> {
+        self.local_store.add_published_ops(hashes)
+    }
+
+    async fn is_published(&self, hash: &p2panda_core::Hash) -> anyhow::Result<bool> {
+        self.local_store.is_op_published(hash)
+    }
+}
+
 #[cfg(test)]
 
 mod tests {
@@ -1,8 +1,21 @@
+mod attachments;
 pub(crate) mod author_operation;
+mod avatars;
+mod devices;
+mod encryption;
+mod ephemeral;
+mod group_chat;
+mod history;
+mod messages;
+mod reactions;
+mod receipts;
+mod search;
 mod stream_processing;
+mod summaries;
 
-use std::collections::{BTreeSet, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
 
 use anyhow::Result;
 
@@ -13,36 +26,62 @@ use named_id::Rename;
 use named_id::*;
 use p2panda_core::Body;
 use p2panda_net::ResyncConfiguration;
-use p2panda_spaces::ActorId;
-use p2panda_store::{LogStore, MemoryStore};
+use p2panda_store::{LogStore, SqliteStore};
 use p2panda_stream::IngestExt;
 use p2panda_stream::partial::operations::PartialOrder;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 
 use mailbox_client::manager::{Mailboxes, MailboxesConfig};
 
+use crate::blobs::BlobCache;
 use crate::chat::ChatMessageContent;
-use crate::contact::{InboxTopic, QrCode, ShareIntent};
-use crate::local_store::NodeData;
-use crate::mailbox::MailboxOperation;
+use crate::contact::{
+    Contact, InboxTopic, PendingContactRequest, QrCode, ShareIntent, fold_contacts,
+    fold_rejected_contact_requests,
+};
+use crate::local_store::{NodeData, SubscribedTopic};
+use crate::mailbox::{MailboxOperation, NodeMailboxStore};
 use crate::payload::{
     AnnouncementsPayload, ChatPayload, Extensions, InboxPayload, Payload, Profile,
 };
+use crate::read_model::ReadModel;
 use crate::stores::OpStore;
 use crate::topic::{Topic, TopicId};
 use crate::{
     AgentId, AsBody, ChatId, ChatReaction, DeviceGroupId, DeviceGroupPayload, DeviceId,
-    DirectChatId, Header, Operation,
+    DirectChatId, Header, KeyBundle, Operation,
 };
 
 pub use crate::local_store::LocalStore;
-pub use stream_processing::Notification;
+pub use stream_processing::{Event, Notification};
+
+/// Where the node keeps its operations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OpStoreBackend {
+    /// Operations only live in memory and are lost when the node stops.
+    Memory,
+    /// Operations are stored in a sqlite database next to the LocalStore file.
+    #[default]
+    Sqlite,
+}
+
+/// What to do when a contact invites us to a group chat.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GroupInvitationPolicy {
+    /// Join the group chat right away.
+    #[default]
+    AutoJoin,
+    /// Keep the invitation pending until it is accepted or declined.
+    Manual,
+}
 
 #[derive(Clone, Debug)]
 pub struct NodeConfig {
     pub resync: ResyncConfiguration,
     pub contact_code_expiry: Duration,
     pub mailboxes_config: MailboxesConfig,
+    pub op_store: OpStoreBackend,
+    pub group_invitations: GroupInvitationPolicy,
 }
 
 impl NodeConfig {
@@ -55,6 +94,8 @@ impl NodeConfig {
             resync: ResyncConfiguration::new().interval(3).poll_interval(1),
             contact_code_expiry: Duration::days(7),
             mailboxes_config,
+            op_store: OpStoreBackend::Memory,
+            group_invitations: GroupInvitationPolicy::default(),
         }
     }
 }
@@ -66,6 +107,8 @@ impl Default for NodeConfig {
             resync,
             contact_code_expiry: Duration::days(7),
             mailboxes_config: MailboxesConfig::default(),
+            op_store: OpStoreBackend::default(),
+            group_invitations: GroupInvitationPolicy::default(),
         }
     }
 }
@@ -73,23 +116,42 @@ impl Default for NodeConfig {
 pub type Orderer<S> =
     PartialOrder<TopicId, Extensions, S, p2panda_stream::partial::MemoryStore<p2panda_core::Hash>>;
 
-pub type NodeOpStore = OpStore<MemoryStore<TopicId, Extensions>>;
+pub type NodeOpStore = OpStore<SqliteStore<TopicId, Extensions>>;
 
 #[derive(Clone)]
 pub struct Node {
     pub op_store: NodeOpStore,
 
-    pub mailboxes: Mailboxes<MailboxOperation, NodeOpStore>,
+    pub mailboxes: Mailboxes<MailboxOperation, NodeMailboxStore>,
 
     // groups: p2panda_auth::group::Groups,
     config: NodeConfig,
-    notification_tx: Option<mpsc::Sender<Notification>>,
+    notification_tx: Option<mpsc::Sender<Event>>,
 
     /// Add new subscription streams
     stream_tx: mpsc::Sender<Pin<Box<dyn Stream<Item = Operation> + Send + 'static>>>,
 
+    /// Messages to author a Delivered receipt for
+    receipt_tx: mpsc::Sender<(ChatId, Header)>,
+
+    /// Dropping a topic's sender ends its subscription stream
+    topic_streams: Arc<Mutex<HashMap<TopicId, oneshot::Sender<()>>>>,
+
+    /// Files which were shared in chats, by the hash of their content
+    blobs: BlobCache,
+
+    /// What's been processed so far, ready to be read
+    read_model: ReadModel,
+
     local_store: LocalStore,
     node_data: NodeData,
+
+    /// Changes only when this device is linked to another device's agent
+    agent_id: Arc<RwLock<AgentId>>,
+
+    /// The topic my device group is on now, which changes when it moves
+    /// after a device was revoked, or when this device is linked
+    device_group_topic: Arc<RwLock<DeviceGroupId>>,
 }
 
 impl Node {
@@ -97,28 +159,53 @@ impl Node {
     pub async fn new(
         local_store: LocalStore,
         config: NodeConfig,
-        notification_tx: Option<mpsc::Sender<Notification>>,
+        notification_tx: Option<mpsc::Sender<Event>>,
     ) -> Result<Self> {
         let node_data = local_store.node_data()?;
+        let agent_id = local_store.agent_id()?;
 
-        let op_store = OpStore::new_memory();
-        // let op_store = OpStore::new_sqlite().await?;
+        let op_store = match config.op_store {
+            OpStoreBackend::Memory => OpStore::new_sqlite_memory().await?,
+            OpStoreBackend::Sqlite => OpStore::new_sqlite(local_store.op_store_path()).await?,
+        };
+        op_store
+            .restore_processed_ops(local_store.get_processed_ops()?)
+            .await?;
 
         let (stream_tx, stream_rx) = mpsc::channel(100);
+        let (receipt_tx, receipt_rx) = mpsc::channel(100);
 
-        let mailboxes = Mailboxes::spawn(op_store.clone(), config.mailboxes_config.clone()).await?;
+        let mailbox_store = NodeMailboxStore {
+            op_store: op_store.clone(),
+            local_store: local_store.clone(),
+        };
+        let mailboxes = Mailboxes::spawn(mailbox_store, config.mailboxes_config.clone()).await?;
 
         let node = Self {
             op_store: op_store.clone(),
             mailboxes,
             config,
+            blobs: BlobCache::new(local_store.blob_cache_path()),
+            read_model: local_store.read_model()?,
             local_store: local_store.clone(),
             node_data,
+            agent_id: Arc::new(RwLock::new(agent_id)),
+            device_group_topic: Arc::new(RwLock::new(Topic::device_group(agent_id))),
             notification_tx,
             stream_tx,
+            receipt_tx,
+            topic_streams: Default::default(),
         };
 
+        if !node.read_model.is_current()? {
+            node.rebuild_read_model().await?;
+        }
+        node.read_model
+            .prune_pending_read_markers(Utc::now().timestamp() as u64)?;
+
         node.spawn_stream_process_loop(stream_rx);
+        node.spawn_receipt_loop(receipt_rx);
+        node.replay_pending_ops().await?;
 
         node.initialize_topic(
             Topic::announcements(node.agent_id())
@@ -126,6 +213,8 @@ impl Node {
             true,
         )
         .await?;
+        node.publish_key_bundle().await?;
+        node.spawn_prekey_rotation_loop();
 
         for topic in local_store.get_active_inbox_topics()?.iter() {
             node.initialize_topic(
@@ -138,7 +227,18 @@ impl Node {
             .await?;
         }
 
-        // TODO: locally store list of groups and initialize them when the node starts
+        node.follow_device_group_topics().await?;
+        node.resume_link().await?;
+
+        for (topic, subscription) in local_store.get_subscribed_topics()? {
+            let name = match subscription {
+                SubscribedTopic::Announcements(agent) => format!("announce({})", agent.renamed()),
+                SubscribedTopic::DirectChat(agent) => format!("direct({})", agent.renamed()),
+                SubscribedTopic::GroupChat => format!("group({})", topic.renamed()),
+            };
+            node.initialize_topic(Topic::untyped(*topic).with_name(&name), false)
+                .await?;
+        }
 
         Ok(node)
     }
@@ -148,14 +248,22 @@ impl Node {
         topic_id: TopicId,
         authors: Vec<DeviceId>,
     ) -> anyhow::Result<Vec<(Header, Option<Payload>)>> {
+        let revoked = self.local_store.get_revoked_devices()?;
         let mut logs = Vec::new();
         for author in authors {
             for (h, b) in self.get_log(topic_id, author).await? {
+                if revoked
+                    .get(&author)
+                    .is_some_and(|revocation| !revocation.counts(topic_id, h.seq_num))
+                {
+                    continue;
+                }
                 if let Some(body) = b {
-                    if let Ok(payload) = Payload::try_from_body(&body) {
-                        logs.push((h, Some(payload)));
-                    } else {
-                        tracing::error!("Failed to decode payload: {body:?}");
+                    match self.decode_body(&h, &body) {
+                        Ok(Some(payload)) => logs.push((h, Some(payload))),
+                        // Sealed for other devices only.
+                        Ok(None) => {}
+                        Err(_) => tracing::error!("Failed to decode payload: {body:?}"),
                     }
                 } else {
                     logs.push((h, None));
@@ -201,12 +309,15 @@ impl Node {
 
     /// Create a new contact QR code with configured expiry time,
     /// subscribe to the inbox topic for it, and register the topic as active.
+    ///
+    /// The inbox of an AddDevice code links one device which asks through it,
+    /// once it's approved, see [`Node::approve_device`].
     pub async fn new_qr_code(
         &self,
         share_intent: ShareIntent,
         inbox: bool,
     ) -> Result<QrCode, crate::Error> {
-        let inbox_topic = if inbox {
+        let (inbox_topic, inbox_key) = if inbox {
             let inbox_topic = InboxTopic {
                 topic: Topic::inbox().with_name(&format!("inbox({})", self.device_id().renamed())),
                 expires_at: Utc::now() + self.config.contact_code_expiry,
@@ -217,21 +328,38 @@ impl Node {
             self.local_store
                 .add_active_inbox_topic(inbox_topic.clone())
                 .map_err(|err| crate::Error::AddActiveInbox(format!("{err}")))?;
-            Some(inbox_topic)
+            if share_intent == ShareIntent::AddDevice {
+                self.local_store
+                    .add_device_inbox(inbox_topic.topic.into())
+                    .map_err(|err| crate::Error::AddActiveInbox(format!("{err}")))?;
+            }
+            let inbox_key = self
+                .local_store
+                .add_inbox_key(
+                    inbox_topic.topic.into(),
+                    self.config.contact_code_expiry.num_seconds().unsigned_abs(),
+                )
+                .and_then(|keys| KeyBundle::new(&keys, &self.node_data.private_key))
+                .map_err(|err| crate::Error::KeyBundle(format!("{err}")))?;
+            (Some(inbox_topic), Some(inbox_key))
         } else {
-            None
+            (None, None)
         };
 
         Ok(QrCode {
             device_pubkey: self.device_id(),
+            key_bundle: self
+                .key_bundle()
+                .map_err(|err| crate::Error::KeyBundle(format!("{err}")))?,
             inbox_topic,
-            agent_id: self.node_data.agent_id,
+            inbox_key,
+            agent_id: self.agent_id(),
             share_intent,
         })
     }
 
     pub fn agent_id(&self) -> AgentId {
-        self.node_data.agent_id
+        *self.agent_id.read().unwrap()
     }
 
     /// Get the topic for a direct chat between two public keys.
@@ -257,7 +385,8 @@ impl Node {
         let topic = self.direct_chat_topic(other);
 
         let my_actor = self.agent_id();
-        self.initialize_topic(topic, true).await?;
+        self.subscribe_topic(topic, SubscribedTopic::DirectChat(other))
+            .await?;
 
         tracing::info!(
             my_actor = ?my_actor.renamed(),
@@ -277,7 +406,9 @@ impl Node {
     #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, parent = None, fields(me = ?self.device_id().renamed())))]
     pub async fn join_group(&self, chat_id: ChatId) -> anyhow::Result<()> {
         tracing::info!(?chat_id, "joined group");
-        self.initialize_topic(chat_id, true).await
+        self.local_store.remove_group_invitation(chat_id)?;
+        self.subscribe_topic(chat_id, SubscribedTopic::GroupChat)
+            .await
     }
 
     pub async fn set_profile(&self, profile: Profile) -> Result<(), crate::Error> {
@@ -293,33 +424,45 @@ impl Node {
     }
 
     pub async fn my_profile(&self) -> anyhow::Result<Option<Profile>> {
-        let topic_id: TopicId = Topic::announcements(self.agent_id()).into();
-        let authors = self.get_authors(topic_id.clone()).await?;
-        let ops = self
-            .get_interleaved_logs(topic_id, authors.into_iter().collect())
-            .await?;
+        self.get_profile(self.agent_id()).await
+    }
 
-        let mut set_profile_ops: Vec<(u64, Profile)> = ops
+    /// The latest profile an agent has announced, if I have received any.
+    ///
+    /// Anyone can write to the announcements of an agent, so only profiles written by
+    /// devices which act for it count, and those of revoked devices only if they were
+    /// written before the revocation.
+    pub async fn get_profile(&self, agent_id: AgentId) -> anyhow::Result<Option<Profile>> {
+        let topic = TopicId::from(Topic::announcements(agent_id));
+        let devices = self.agent_devices(agent_id).await?;
+        let revoked = self.local_store.get_revoked_devices()?;
+        Ok(self
+            .read_model
+            .profiles(topic)?
             .into_iter()
-            .filter_map(|(header, payload)| match payload {
-                Some(Payload::Announcements(AnnouncementsPayload::SetProfile(profile))) => {
-                    Some((header.timestamp, profile))
-                }
-                _ => None,
+            .filter(|profile| {
+                devices.contains(&profile.device)
+                    && revoked
+                        .get(&profile.device)
+                        .is_none_or(|revocation| revocation.counts(topic, profile.seq_num))
             })
-            .collect();
-
-        set_profile_ops.sort_by_key(|(timestamp, _)| *timestamp);
+            .max_by_key(|profile| (profile.timestamp, profile.device, profile.seq_num))
+            .map(|profile| profile.profile))
+    }
 
-        let Some((_, profile)) = set_profile_ops.last() else {
-            return Ok(None);
-        };
-        Ok(Some(profile.clone()))
+    /// The agent whose announcements are on the given topic, if it's me or a contact.
+    pub(crate) async fn announcements_agent(
+        &self,
+        topic: TopicId,
+    ) -> anyhow::Result<Option<AgentId>> {
+        Ok(std::iter::once(self.agent_id())
+            .chain(self.get_contacts().await?)
+            .find(|agent_id| TopicId::from(Topic::announcements(*agent_id)) == topic))
     }
 
     /// Get all messages for a chat from the logs.
     ///
-    /// In the real app, the interleaving of logs happens on the front end.
+    /// The app pages through [`Node::message_history`] instead.
     #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
     #[cfg(feature = "testing")]
     pub async fn get_messages(
@@ -336,7 +479,11 @@ impl Node {
             .await?
         {
             if let Some(Payload::Chat(ChatPayload::Message(message))) = payload {
-                messages.push(crate::chat::testing::ChatMessage::new(message, &header));
+                let mut message = crate::chat::testing::ChatMessage::new(message, &header);
+                if let Some(reply_to) = message.content.reply_to {
+                    message.quoted = Some(self.quote_message(chat_id, reply_to).await?);
+                }
+                messages.push(message);
             }
         }
 
@@ -394,8 +541,30 @@ impl Node {
         self.node_data.device_id()
     }
 
+    /// The topic my device group is on now, which my devices write to.
     pub fn device_group_topic(&self) -> DeviceGroupId {
-        Topic::device_group(self.agent_id()).into()
+        *self.device_group_topic.read().unwrap()
+    }
+
+    /// The topics the device group of an agent was on, in the order it moved through them,
+    /// ending with the one it's on now.
+    pub fn device_group_topics(&self, agent_id: AgentId) -> anyhow::Result<Vec<DeviceGroupId>> {
+        Ok(std::iter::once(Topic::device_group(agent_id))
+            .chain(
+                self.local_store
+                    .get_device_group_topics(agent_id)?
+                    .into_iter()
+                    .map(|topic| DeviceGroupId::new(*topic)),
+            )
+            .collect())
+    }
+
+    /// Whether a topic is one my device group was on, or is on now.
+    pub(crate) fn is_device_group_topic(&self, topic: TopicId) -> anyhow::Result<bool> {
+        Ok(self
+            .device_group_topics(self.agent_id())?
+            .into_iter()
+            .any(|device_group| TopicId::from(device_group) == topic))
     }
 
     /// Store someone as a contact, and:
@@ -403,8 +572,14 @@ impl Node {
     /// - subscribe to their inbox
     /// - store them in the contacts map
     /// - send an invitation to them to do the same
+    ///
+    /// An AddDevice code links this device to the agent of the code instead,
+    /// see [`Node::link_device`].
     #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
     pub async fn add_contact(&self, contact: QrCode) -> Result<AgentId, AddContactError> {
+        if contact.share_intent == ShareIntent::AddDevice {
+            return self.link_device(contact).await;
+        }
         tracing::debug!("adding contact: {:?}", contact);
 
         // SPACES: Register the member in the spaces manager
@@ -412,9 +587,12 @@ impl Node {
         // Must subscribe to the new member's device group in order to receive their
         // group control messages.
         // TODO: is this idempotent? If not we must make sure to do this only once.
-        self.initialize_topic(Topic::announcements(contact.agent_id), false)
-            .await
-            .map_err(|e| Error::InitializeTopic(e.to_string()))?;
+        self.subscribe_topic(
+            Topic::announcements(contact.agent_id),
+            SubscribedTopic::Announcements(contact.agent_id),
+        )
+        .await
+        .map_err(|e| Error::InitializeTopic(e.to_string()))?;
 
         // TODO: use all of this commented out stuff when spaces are possible again
         // // XXX: there should be a better way to wait for the device group to be created,
@@ -462,7 +640,7 @@ impl Node {
 
         let agent = contact.agent_id;
         let direct_topic = self.direct_chat_topic(agent);
-        self.initialize_topic(direct_topic, true)
+        self.subscribe_topic(direct_topic, SubscribedTopic::DirectChat(agent))
             .await
             .map_err(|e| Error::InitializeTopic(e.to_string()))?;
 
@@ -489,9 +667,12 @@ impl Node {
             else {
                 return Err(AddContactError::ProfileNotCreated);
             };
+            let request = self
+                .seal_for_inbox(&contact, InboxPayload::ContactRequest { code, profile })
+                .map_err(|e| Error::AuthorOperation(e.to_string()))?;
             self.author_operation(
                 inbox_topic.topic,
-                Payload::Inbox(InboxPayload::ContactRequest { code, profile }),
+                request,
                 Some(&format!("add_contact/invitation({})", agent.renamed())),
             )
             .await
@@ -526,9 +707,144 @@ impl Node {
         Ok(())
     }
 
+    /// The contact requests in my unexpired inboxes which haven't been accepted
+    /// on any of my devices, nor rejected after they were made,
+    /// with the latest request per agent.
+    ///
+    /// Requests are sealed to the ephemeral key of the code they answer,
+    /// so only the device which issued the code lists them.
+    pub async fn pending_contact_requests(&self) -> anyhow::Result<Vec<PendingContactRequest>> {
+        let payloads = self.device_group_payloads().await?;
+        let contacts = fold_contacts(payloads.iter().map(|(_, payload)| payload.clone()));
+        let rejected = fold_rejected_contact_requests(payloads);
+
+        let now = Utc::now();
+        let mut requests = BTreeMap::new();
+        for inbox in self.get_active_inbox_topics()? {
+            if inbox.expires_at <= now {
+                continue;
+            }
+            let topic_id: TopicId = inbox.topic.into();
+            let authors = self.get_authors(topic_id).await?;
+            for (header, payload) in self
+                .get_interleaved_logs(topic_id, authors.into_iter().collect())
+                .await?
+            {
+                let Some(Payload::Inbox(InboxPayload::ContactRequest { code, profile })) = payload
+                else {
+                    continue;
+                };
+                let agent_id = code.agent_id;
+                // Timestamps are in seconds, so a request from the same second
+                // as a rejection counts as rejected.
+                let is_rejected = rejected
+                    .get(&agent_id)
+                    .is_some_and(|rejected_at| header.timestamp <= *rejected_at);
+                if agent_id == self.agent_id() || contacts.contains_key(&agent_id) || is_rejected {
+                    continue;
+                }
+                requests.insert(
+                    agent_id,
+                    PendingContactRequest {
+                        code,
+                        profile,
+                        expires_at: inbox.expires_at,
+                    },
+                );
+            }
+        }
+        Ok(requests.into_values().collect())
+    }
+
+    /// Accept a pending contact request from the given agent,
+    /// adding them as a contact with the code they sent.
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn accept_contact_request(&self, agent_id: AgentId) -> Result<(), AddContactError> {
+        tracing::debug!("accepting contact request from: {:?}", agent_id);
+
+        let request = self
+            .pending_contact_requests()
+            .await
+            .map_err(|e| Error::GetContactRequests(e.to_string()))?
+            .into_iter()
+            .find(|request| request.code.agent_id == agent_id)
+            .ok_or_else(|| AddContactError::NoPendingRequest(format!("{agent_id:?}")))?;
+        self.add_contact(request.code).await?;
+
+        Ok(())
+    }
+
+    /// Remove someone as a contact.
+    /// This creates a RemoveContact tombstone in the device group topic.
+    /// Processing the tombstone unsubscribes from the contact's announcements
+    /// and from our direct chat, on each of my devices.
     #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
-    pub async fn remove_contact(&self, _chat_actor_id: ActorId) -> anyhow::Result<()> {
-        // TODO: shutdown inbox task, etc.
-        todo!("add tombstone to contacts list");
+    pub async fn remove_contact(&self, agent_id: AgentId) -> Result<(), Error> {
+        tracing::debug!("removing contact: {:?}", agent_id);
+
+        self.author_operation(
+            self.device_group_topic(),
+            Payload::DeviceGroup(DeviceGroupPayload::RemoveContact(agent_id)),
+            Some(&format!("remove_contact({})", agent_id.renamed())),
+        )
+        .await
+        .map_err(|e| Error::AuthorOperation(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// The agents which are currently my contacts, according to the device group logs.
+    pub async fn get_contacts(&self) -> anyhow::Result<Vec<AgentId>> {
+        let payloads = self.device_group_payloads().await?;
+        Ok(
+            fold_contacts(payloads.into_iter().map(|(_, payload)| payload))
+                .into_keys()
+                .collect(),
+        )
+    }
+
+    /// My contacts, each with their latest profile.
+    pub async fn contacts(&self) -> anyhow::Result<Vec<Contact>> {
+        let mut contacts = vec![];
+        for agent_id in self.get_contacts().await? {
+            contacts.push(Contact {
+                agent_id,
+                profile: self.get_profile(agent_id).await?,
+            });
+        }
+        Ok(contacts)
+    }
+
+    /// The agents whose contact requests I rejected on any of my devices,
+    /// and haven't added as a contact since.
+    pub async fn get_rejected_contact_requests(&self) -> anyhow::Result<Vec<AgentId>> {
+        let payloads = self.device_group_payloads().await?;
+        Ok(fold_rejected_contact_requests(payloads)
+            .into_keys()
+            .collect())
+    }
+
+    /// The payloads in the device group logs of all my devices, in order,
+    /// with the timestamps they were authored at.
+    /// The topics my device group moved through are read one after the other.
+    async fn device_group_payloads(&self) -> anyhow::Result<Vec<(u64, DeviceGroupPayload)>> {
+        let authors = self
+            .my_authorized_devices()
+            .await?
+            .into_iter()
+            .collect::<Vec<_>>();
+        let mut payloads = vec![];
+        for topic in self.device_group_topics(self.agent_id())? {
+            payloads.extend(
+                self.get_interleaved_logs(topic.into(), authors.clone())
+                    .await?
+                    .into_iter()
+                    .filter_map(|(header, payload)| match payload {
+                        Some(Payload::DeviceGroup(payload)) => Some((header.timestamp, payload)),
+                        _ => None,
+                    }),
+            );
+        }
+        Ok(payloads)
     }
 }
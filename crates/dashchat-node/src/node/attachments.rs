@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use p2panda_core::Hash;
+use p2panda_store::OperationStore;
+
+use crate::blobs::{
+    CHUNK_SIZE, MAX_FILE_SIZE, chunk_count, chunk_file_id, decrypt_chunk, encrypt_chunk,
+};
+
+use super::*;
+
+impl Node {
+    /// Share a file in a chat:
+    /// - keep it in my blob cache
+    /// - upload its chunks to the mailboxes
+    /// - send an Attachment message which refers to it by hash
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn send_attachment(
+        &self,
+        chat_id: ChatId,
+        data: Vec<u8>,
+        mime: String,
+        name: String,
+    ) -> anyhow::Result<Header> {
+        if data.len() as u64 > MAX_FILE_SIZE {
+            bail!(
+                "file of {} bytes is larger than {MAX_FILE_SIZE} bytes",
+                data.len()
+            );
+        }
+        let hash = self.upload_blob(&data).await?;
+        self.author_operation(
+            chat_id,
+            Payload::Chat(ChatPayload::Attachment {
+                hash,
+                size: data.len() as u64,
+                mime,
+                name,
+            }),
+            None,
+        )
+        .await
+    }
+
+    /// The local path of the file of an Attachment message,
+    /// which is downloaded first if it isn't cached yet.
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn download_attachment(
+        &self,
+        chat_id: ChatId,
+        message: Hash,
+    ) -> anyhow::Result<PathBuf> {
+        let Some((header, Some(body))) = self.op_store.get_operation(message).await? else {
+            bail!("no attachment {message} in chat {chat_id}");
+        };
+        if header.extensions.topic != TopicId::from(chat_id) {
+            bail!("no attachment {message} in chat {chat_id}");
+        }
+        let Some(Payload::Chat(ChatPayload::Attachment { hash, size, .. })) =
+            self.decode_body(&header, &body)?
+        else {
+            bail!("message {message} is not an attachment");
+        };
+        self.download_blob(hash, size).await
+    }
+
+    /// Cache a file and upload its chunks to the mailboxes,
+    /// encrypted to a key only those who know the hash of the file can derive.
+    pub(crate) async fn upload_blob(&self, data: &[u8]) -> anyhow::Result<Hash> {
+        let hash = self.blobs.insert(data).await?;
+        for (index, chunk) in data.chunks(CHUNK_SIZE).enumerate() {
+            let index = index as u64;
+            self.mailboxes
+                .store_chunk(
+                    chunk_file_id(&hash),
+                    index,
+                    encrypt_chunk(&hash, index, chunk)?,
+                )
+                .await?;
+        }
+        tracing::info!(?hash, size = data.len(), "uploaded file");
+        Ok(hash)
+    }
+
+    /// Fetch the chunks of a file from the mailboxes into the blob cache,
+    /// with a DownloadProgress notification after each chunk.
+    /// Each chunk is checked as it arrives, so that a tampered one fails the download early.
+    pub(crate) async fn download_blob(&self, hash: Hash, size: u64) -> anyhow::Result<PathBuf> {
+        if self.blobs.contains(&hash).await {
+            return Ok(self.blobs.path(&hash));
+        }
+        if size > MAX_FILE_SIZE {
+            bail!("file {hash} of {size} bytes is larger than {MAX_FILE_SIZE} bytes");
+        }
+
+        let mut data = vec![];
+        for index in 0..chunk_count(size) {
+            let Some(chunk) = self
+                .mailboxes
+                .fetch_chunk(chunk_file_id(&hash), index)
+                .await?
+            else {
+                bail!("chunk {index} of file {hash} is missing from the mailboxes");
+            };
+            let chunk = decrypt_chunk(&hash, index, &chunk)
+                .map_err(|err| anyhow::anyhow!("chunk {index} of file {hash} is invalid: {err}"))?;
+            data.extend(chunk);
+            if data.len() as u64 > size {
+                bail!("file {hash} is larger than announced");
+            }
+            self.notify(Event::DownloadProgress {
+                hash,
+                received: data.len() as u64,
+                size,
+            })
+            .await;
+        }
+
+        if data.len() as u64 != size || Hash::new(&data) != hash {
+            bail!("downloaded file doesn't match its hash {hash}");
+        }
+        self.blobs.insert(&data).await?;
+        tracing::info!(?hash, size, "downloaded file");
+        Ok(self.blobs.path(&hash))
+    }
+}
@@ -9,6 +9,7 @@ impl Node {
         payload: Payload,
         alias: Option<&str>,
     ) -> Result<Header, anyhow::Error> {
+        let payload = self.seal_payload(topic.clone().into(), payload).await?;
         let (header, body) = self
             .op_store
             .author_operation(
@@ -30,6 +31,18 @@ impl Node {
         self.process_authored_ingested_operation(op).await
     }
 
+    /// Write a payload to any topic, whether this device may write there or not,
+    /// to test that other devices ignore it.
+    #[cfg(feature = "testing")]
+    pub async fn author_anywhere(
+        &self,
+        topic: TopicId,
+        payload: Payload,
+    ) -> anyhow::Result<Header> {
+        self.author_operation(Topic::untyped(*topic), payload, None)
+            .await
+    }
+
     pub(crate) async fn process_authored_ingested_operation(
         &self,
         op: Operation,
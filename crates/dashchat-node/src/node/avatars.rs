@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use tokio::task;
+
+use crate::Avatar;
+
+use super::*;
+
+/// The largest avatar which is fetched, in bytes.
+/// Avatars are fetched for every profile, so a larger one is most likely not a thumbnail.
+const MAX_AVATAR_SIZE: u64 = 1024 * 1024;
+
+impl Node {
+    /// Upload an image to refer to from my profile.
+    /// It should already be a thumbnail, since it's fetched by all my contacts.
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn upload_avatar(&self, image: Vec<u8>) -> Result<Avatar, Error> {
+        if image.len() as u64 > MAX_AVATAR_SIZE {
+            return Err(Error::UploadAvatar(format!(
+                "avatar of {} bytes is larger than {MAX_AVATAR_SIZE} bytes",
+                image.len()
+            )));
+        }
+        let hash = self
+            .upload_blob(&image)
+            .await
+            .map_err(|e| Error::UploadAvatar(e.to_string()))?;
+        Ok(Avatar {
+            hash,
+            size: image.len() as u64,
+        })
+    }
+
+    /// The local path of the avatar of an agent, according to their latest profile.
+    /// It's downloaded first if it isn't cached yet.
+    pub async fn get_avatar(&self, agent_id: AgentId) -> anyhow::Result<Option<PathBuf>> {
+        let Some(avatar) = self.get_profile(agent_id).await?.and_then(|p| p.avatar) else {
+            return Ok(None);
+        };
+        if avatar.size > MAX_AVATAR_SIZE {
+            anyhow::bail!("avatar of {} bytes is too large to fetch", avatar.size);
+        }
+        Ok(Some(self.download_blob(avatar.hash, avatar.size).await?))
+    }
+
+    /// Download an avatar into the blob cache in the background,
+    /// so that it's ready by the time it's displayed.
+    /// Avatars which are too large are left alone.
+    pub(crate) fn spawn_avatar_download(&self, avatar: Avatar) {
+        if avatar.size > MAX_AVATAR_SIZE {
+            tracing::warn!(
+                size = avatar.size,
+                hash = ?avatar.hash,
+                "avatar too large, not downloading"
+            );
+            return;
+        }
+        let node = self.clone();
+        task::spawn(async move {
+            if let Err(err) = node.download_blob(avatar.hash, avatar.size).await {
+                tracing::warn!(?err, hash = ?avatar.hash, "failed to download avatar");
+            }
+        });
+    }
+}
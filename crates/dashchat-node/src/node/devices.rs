@@ -0,0 +1,648 @@
+use anyhow::bail;
+use tokio::task;
+
+use crate::devices::{PendingDeviceRequest, Revocation, authorized_devices, verification_code};
+use crate::{KeyBundle, LogHeight, SealedPayload};
+
+use super::*;
+
+impl Node {
+    /// Link this device to the agent of another device, with the AddDevice code it shows:
+    /// - ask it through its inbox to add this device
+    /// - follow its device group, to learn when it did
+    ///
+    /// The other device only adds this one once the user approved it there,
+    /// after checking that both show the same [`Node::verification_code`].
+    /// Then this device takes on its agent ID, and my contacts and group chats arrive
+    /// through the device group, and my profile through my announcements.
+    /// Only a device without contacts or group chats can be linked.
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn link_device(&self, code: QrCode) -> Result<AgentId, AddContactError> {
+        if code.share_intent != ShareIntent::AddDevice {
+            return Err(AddContactError::LinkDevice("not an AddDevice code".into()));
+        }
+        let Some(inbox_topic) = code.inbox_topic.clone() else {
+            return Err(AddContactError::LinkDevice("the code has no inbox".into()));
+        };
+        let agent_id = code.agent_id;
+        if agent_id == self.agent_id() {
+            return Ok(agent_id);
+        }
+        let has_contacts = !self
+            .get_contacts()
+            .await
+            .map_err(|e| AddContactError::LinkDevice(e.to_string()))?
+            .is_empty();
+        let has_group_chats = !self
+            .get_group_chats()
+            .map_err(|e| AddContactError::LinkDevice(e.to_string()))?
+            .is_empty();
+        if has_contacts || has_group_chats {
+            return Err(AddContactError::LinkDevice(
+                "only a device without contacts or group chats can be linked".into(),
+            ));
+        }
+
+        self.local_store
+            .set_pending_link(agent_id, code.device_pubkey)
+            .map_err(|e| AddContactError::LinkDevice(e.to_string()))?;
+        self.initialize_topic(
+            Topic::device_group(agent_id)
+                .with_name(&format!("device_group({})", agent_id.renamed())),
+            false,
+        )
+        .await
+        .map_err(|e| Error::InitializeTopic(e.to_string()))?;
+        self.initialize_topic(inbox_topic.topic, true)
+            .await
+            .map_err(|e| Error::InitializeTopic(e.to_string()))?;
+        let key_bundle = self
+            .key_bundle()
+            .map_err(|e| AddContactError::LinkDevice(e.to_string()))?;
+        let request = self
+            .seal_for_inbox(&code, InboxPayload::DeviceRequest { key_bundle })
+            .map_err(|e| AddContactError::LinkDevice(e.to_string()))?;
+        self.author_operation(
+            inbox_topic.topic,
+            request,
+            Some(&format!("link_device({})", agent_id.renamed())),
+        )
+        .await
+        .map_err(|e| Error::AuthorOperation(e.to_string()))?;
+
+        tracing::info!(agent = ?agent_id.renamed(), "asked to be linked");
+        Ok(agent_id)
+    }
+
+    /// The code to show while this device is being linked,
+    /// which the device approving it shows next to its request.
+    pub fn verification_code(&self) -> String {
+        verification_code(self.device_id())
+    }
+
+    /// The devices which asked to be linked through the inbox of one of my
+    /// unexpired AddDevice codes, and which are waiting for [`Node::approve_device`].
+    pub async fn pending_device_requests(&self) -> anyhow::Result<Vec<PendingDeviceRequest>> {
+        Ok(self
+            .device_requests()
+            .await?
+            .into_iter()
+            .map(|(_, request, _)| request)
+            .collect())
+    }
+
+    /// Link a device which asked to be linked to my agent,
+    /// once the user checked that it shows the same verification code.
+    /// No other device can be linked with the code it used afterwards.
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn approve_device(&self, device: DeviceId) -> anyhow::Result<()> {
+        let Some((inbox, _, key_bundle)) = self
+            .device_requests()
+            .await?
+            .into_iter()
+            .find(|(_, request, _)| request.device == device)
+        else {
+            bail!("no pending request from device {device:?}");
+        };
+        if !self.local_store.take_device_inbox(inbox)? {
+            bail!("the code was already used to link another device");
+        }
+        // The device waits on the topic my device group started on,
+        // so it has to be told where it moved to before it's added.
+        let topics = self.device_group_topics(self.agent_id())?;
+        for hop in topics.windows(2) {
+            self.author_move_device_group(hop[0], hop[1], [(device, key_bundle.clone())])
+                .await?;
+        }
+        self.add_device(device).await
+    }
+
+    /// The unexpired requests to be linked, with the inbox each arrived in
+    /// and the key of the device which asked.
+    async fn device_requests(
+        &self,
+    ) -> anyhow::Result<Vec<(TopicId, PendingDeviceRequest, KeyBundle)>> {
+        let now = Utc::now();
+        let mut requests = BTreeMap::new();
+        for inbox in self.get_active_inbox_topics()? {
+            let topic_id: TopicId = inbox.topic.into();
+            if inbox.expires_at <= now || !self.local_store.is_device_inbox(topic_id)? {
+                continue;
+            }
+            let authors = self.get_authors(topic_id).await?;
+            for (header, payload) in self
+                .get_interleaved_logs(topic_id, authors.into_iter().collect())
+                .await?
+            {
+                let device = DeviceId::from(header.public_key);
+                let Some(Payload::Inbox(InboxPayload::DeviceRequest { key_bundle })) = payload
+                else {
+                    continue;
+                };
+                if device == self.device_id() {
+                    continue;
+                }
+                if let Err(err) = key_bundle.verify(device) {
+                    tracing::warn!(
+                        device = ?device.renamed(),
+                        ?err,
+                        "bad key bundle in device request, ignoring"
+                    );
+                    continue;
+                }
+                requests.insert(
+                    device,
+                    (
+                        topic_id,
+                        PendingDeviceRequest {
+                            device,
+                            verification_code: verification_code(device),
+                            expires_at: inbox.expires_at,
+                        },
+                        key_bundle,
+                    ),
+                );
+            }
+        }
+        Ok(requests.into_values().collect())
+    }
+
+    /// All devices of my agent, including this one, except those which were revoked.
+    pub async fn my_devices(&self) -> anyhow::Result<BTreeSet<DeviceId>> {
+        let mut devices = self.my_authorized_devices().await?;
+        let revoked = self.local_store.get_revoked_devices()?;
+        devices.retain(|device| !revoked.contains_key(device));
+        Ok(devices)
+    }
+
+    /// The devices which act for my agent, whether they were revoked since or not:
+    /// this device and the device which linked it, and the devices they added, and so on.
+    ///
+    /// Anyone can write to my device group and announcements, so only what these
+    /// devices write there counts.
+    pub(crate) async fn my_authorized_devices(&self) -> anyhow::Result<BTreeSet<DeviceId>> {
+        let mut additions = self
+            .read_model
+            .device_additions(Topic::announcements(self.agent_id()).into())?;
+        for topic in self.device_group_topics(self.agent_id())? {
+            let topic_id: TopicId = topic.into();
+            let authors = self.get_authors(topic_id).await?;
+            for (header, payload) in self
+                .get_interleaved_logs(topic_id, authors.into_iter().collect())
+                .await?
+            {
+                if let Some(Payload::DeviceGroup(DeviceGroupPayload::AddDevice(device))) = payload {
+                    additions.push((header.public_key.into(), device));
+                }
+            }
+        }
+        let anchors = std::iter::once(self.device_id()).chain(self.local_store.linked_from()?);
+        Ok(authorized_devices(anchors, additions))
+    }
+
+    /// The devices which act for an agent, whether they were revoked since or not:
+    /// mine, see [`Node::my_authorized_devices`], or those of a contact,
+    /// see [`Node::contact_devices`]. None for anyone else.
+    pub(crate) async fn agent_devices(
+        &self,
+        agent_id: AgentId,
+    ) -> anyhow::Result<BTreeSet<DeviceId>> {
+        if agent_id == self.agent_id() {
+            return self.my_authorized_devices().await;
+        }
+        let codes = fold_contacts(
+            self.device_group_payloads()
+                .await?
+                .into_iter()
+                .map(|(_, payload)| payload),
+        );
+        match codes.get(&agent_id) {
+            Some(code) => self.contact_devices(code),
+            None => Ok(BTreeSet::new()),
+        }
+    }
+
+    /// The devices which act for a contact, whether they were revoked since or not:
+    /// the device whose code I added them with, and the devices it announced, and so on.
+    pub(crate) fn contact_devices(&self, code: &QrCode) -> anyhow::Result<BTreeSet<DeviceId>> {
+        let additions = self
+            .read_model
+            .device_additions(Topic::announcements(code.agent_id).into())?;
+        Ok(authorized_devices([code.device_pubkey], additions))
+    }
+
+    /// Revoke another of my devices, for example because it was lost:
+    /// - record it in my device group, so that my other devices stop acting on its behalf
+    /// - announce it, so that my contacts ignore what it writes from now on
+    /// - move my device group to a new topic, which only my remaining devices are told about
+    ///
+    /// Its operations only count up to the latest ones this device has seen of it.
+    ///
+    /// Only the device group moves. The other topics the revoked device knew stay where
+    /// they are, and no one accepts its operations there anymore:
+    /// - direct chat topics are derived from the agent IDs of both sides, so it could
+    ///   find them again anyway, but their payloads are sealed for each device separately,
+    ///   so it can't read new ones
+    /// - group chats aren't encrypted, so it can still read those it knows
+    /// - inboxes and their keys stay on the device which issued their code,
+    ///   so it never knew those of my other devices
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn remove_device(&self, device: DeviceId) -> anyhow::Result<()> {
+        if device == self.device_id() {
+            bail!("a device can only be revoked by another device");
+        }
+        if !self.my_devices().await?.contains(&device) {
+            bail!("not one of my devices: {device:?}");
+        }
+        let up_to = self.log_heights(device).await?;
+        self.author_operation(
+            self.device_group_topic(),
+            Payload::DeviceGroup(DeviceGroupPayload::RemoveDevice {
+                device,
+                up_to: up_to.clone(),
+            }),
+            Some(&format!("remove_device({})", device.renamed())),
+        )
+        .await?;
+        self.author_operation(
+            Topic::announcements(self.agent_id()),
+            Payload::Announcements(AnnouncementsPayload::RemoveDevice { device, up_to }),
+            Some(&format!("revoke_device({})", device.renamed())),
+        )
+        .await?;
+        self.rotate_device_group(device).await?;
+
+        tracing::info!(device = ?device.renamed(), "removed device");
+        Ok(())
+    }
+
+    /// The topics whose operations count for the read model: those of my device group,
+    /// my announcements and the topics I subscribed to.
+    fn followed_topics(&self) -> anyhow::Result<Vec<TopicId>> {
+        let mut topics = self
+            .device_group_topics(self.agent_id())?
+            .into_iter()
+            .map(TopicId::from)
+            .collect::<Vec<_>>();
+        topics.push(Topic::announcements(self.agent_id()).into());
+        topics.extend(self.local_store.get_subscribed_topics()?.into_keys());
+        Ok(topics)
+    }
+
+    /// The seq_num of the latest operation of a device in each topic I follow
+    /// which it wrote to.
+    async fn log_heights(&self, device: DeviceId) -> anyhow::Result<Vec<LogHeight>> {
+        let mut heights = vec![];
+        for topic in self.followed_topics()? {
+            if let Some((_, seq_num)) = self
+                .op_store
+                .get_log_heights(&topic)
+                .await?
+                .into_iter()
+                .find(|(author, _)| *author == device)
+            {
+                heights.push(LogHeight { topic, seq_num });
+            }
+        }
+        Ok(heights)
+    }
+
+    /// Move my device group to a new random topic after revoking a device,
+    /// and tell my remaining devices where it went, sealed so that the revoked device
+    /// can't follow.
+    async fn rotate_device_group(&self, revoked: DeviceId) -> anyhow::Result<()> {
+        let from = self.device_group_topic();
+        let to = DeviceGroupId::random()
+            .with_name(&format!("device_group({})", self.agent_id().renamed()));
+
+        let remaining = self.my_devices().await?;
+        let mut recipients = self.key_bundles(self.agent_id()).await?;
+        recipients.retain(|device, _| *device != revoked && *device != self.device_id());
+        for device in &remaining {
+            if *device != revoked && *device != self.device_id() && !recipients.contains_key(device)
+            {
+                tracing::warn!(
+                    device = ?device.renamed(),
+                    "no key for device, it can't follow my device group"
+                );
+            }
+        }
+        if !recipients.is_empty() {
+            self.author_move_device_group(from, to, recipients).await?;
+        }
+        self.move_device_group(self.agent_id(), to).await
+    }
+
+    /// Tell some devices, and only them, that my device group moved from one topic to another.
+    async fn author_move_device_group(
+        &self,
+        from: DeviceGroupId,
+        to: DeviceGroupId,
+        recipients: impl IntoIterator<Item = (DeviceId, KeyBundle)>,
+    ) -> anyhow::Result<()> {
+        let payload = Payload::DeviceGroup(DeviceGroupPayload::MoveDeviceGroup(to));
+        self.author_operation(
+            from,
+            Payload::Sealed(SealedPayload::seal(&payload, recipients)?),
+            Some(&format!("move_device_group({})", to.renamed())),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Follow the device group of an agent to the topic it moved to:
+    /// mine, or the one of the agent I'm being linked to.
+    pub(crate) async fn move_device_group(
+        &self,
+        agent_id: AgentId,
+        to: DeviceGroupId,
+    ) -> anyhow::Result<()> {
+        if !self
+            .local_store
+            .add_device_group_topic(agent_id, to.into())?
+        {
+            return Ok(());
+        }
+        if agent_id == self.agent_id() {
+            *self.device_group_topic.write().unwrap() = to;
+        }
+        tracing::info!(agent = ?agent_id.renamed(), topic = ?to.renamed(), "device group moved");
+        self.initialize_topic(
+            to.with_name(&format!("device_group({})", agent_id.renamed())),
+            true,
+        )
+        .await
+    }
+
+    /// Follow all the topics my device group moved through, and write to the latest one.
+    pub(crate) async fn follow_device_group_topics(&self) -> anyhow::Result<()> {
+        let agent_id = self.agent_id();
+        let topics = self.device_group_topics(agent_id)?;
+        if let Some(latest) = topics.last() {
+            *self.device_group_topic.write().unwrap() = *latest;
+        }
+        for topic in topics {
+            self.initialize_topic(
+                topic.with_name(&format!("device_group({})", agent_id.renamed())),
+                true,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Revoke a device of an agent, so that only its operations up to the given
+    /// positions in its logs count, and forget what it wrote beyond them.
+    ///
+    /// Only another device which acts for the same agent, and whose revoking operation
+    /// still counts itself, can revoke a device.
+    /// The operations which no longer count are dropped from the read model,
+    /// and I stop following the contacts they added. Bodies which they deleted can't be
+    /// brought back though.
+    pub(crate) async fn record_revocation(
+        &self,
+        agent_id: AgentId,
+        header: &Header,
+        device: DeviceId,
+        up_to: &[LogHeight],
+    ) -> anyhow::Result<()> {
+        let revoker = DeviceId::from(header.public_key);
+        let devices = self.agent_devices(agent_id).await?;
+        if revoker == device
+            || !devices.contains(&revoker)
+            || !devices.contains(&device)
+            || self
+                .local_store
+                .is_revoked(revoker, header.extensions.topic, header.seq_num)?
+        {
+            tracing::warn!(
+                device = ?device.renamed(),
+                revoker = ?revoker.renamed(),
+                "revocation by a device which can't revoke it, ignoring"
+            );
+            return Ok(());
+        }
+
+        self.local_store
+            .add_revoked_device(device, Revocation::new(up_to.iter().copied()))?;
+        self.forget_revoked_operations(device).await?;
+        if agent_id == self.agent_id() {
+            self.unsubscribe_former_contacts().await?;
+        }
+        tracing::info!(device = ?device.renamed(), agent = ?agent_id.renamed(), "revoked device");
+        Ok(())
+    }
+
+    /// Drop the operations of a revoked device which no longer count from the read model.
+    /// Its operations which still count are applied again in the topics where some were
+    /// dropped, since they may have been replaced, like its earlier reactions.
+    async fn forget_revoked_operations(&self, device: DeviceId) -> anyhow::Result<()> {
+        let Some(revocation) = self.local_store.get_revoked_devices()?.remove(&device) else {
+            return Ok(());
+        };
+        for topic in self.followed_topics()? {
+            let log = self
+                .op_store
+                .get_log(&device, &topic, None)
+                .await?
+                .unwrap_or_default();
+            if log
+                .iter()
+                .all(|(header, _)| revocation.counts(topic, header.seq_num))
+            {
+                continue;
+            }
+            let mut counting = vec![];
+            for (header, body) in log {
+                let Some(body) = body else {
+                    continue;
+                };
+                let payload = match self.decode_body(&header, &body) {
+                    Ok(Some(payload)) => payload,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        tracing::warn!(
+                            ?err,
+                            hash = ?header.hash().renamed(),
+                            "can't decode payload, skipping"
+                        );
+                        continue;
+                    }
+                };
+                if revocation.counts(topic, header.seq_num) {
+                    counting.push((header, payload));
+                } else {
+                    self.read_model.remove_operation(&header, &payload)?;
+                }
+            }
+            for (header, payload) in counting {
+                if self.accepts_payload(&header, &payload).await? {
+                    self.read_model.apply(&header, &payload)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop following the announcements and direct chats of agents
+    /// which aren't my contacts anymore.
+    async fn unsubscribe_former_contacts(&self) -> anyhow::Result<()> {
+        let contacts = self.get_contacts().await?;
+        for (topic, subscription) in self.local_store.get_subscribed_topics()? {
+            if let SubscribedTopic::Announcements(agent_id) | SubscribedTopic::DirectChat(agent_id) =
+                subscription
+                && !contacts.contains(&agent_id)
+            {
+                self.unsubscribe_topic(Topic::untyped(*topic)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Take on the agent of the device which added this one, in the background,
+    /// since the device group payload which says so is received while processing
+    /// operations, and switching agents authors operations.
+    pub(crate) fn spawn_complete_link(&self) {
+        let node = self.clone();
+        task::spawn(async move {
+            if let Err(err) = node.complete_link().await {
+                tracing::error!(?err, "failed to complete linking");
+            }
+        });
+    }
+
+    /// Complete a link which is still pending from before a restart,
+    /// or keep waiting for it.
+    pub(crate) async fn resume_link(&self) -> anyhow::Result<()> {
+        let Some((agent_id, linking_device)) = self.local_store.pending_link()? else {
+            return Ok(());
+        };
+        let topics = self.device_group_topics(agent_id)?;
+        for topic in &topics {
+            let added = self
+                .get_interleaved_logs((*topic).into(), vec![linking_device])
+                .await?
+                .into_iter()
+                .any(|(_, payload)| {
+                    matches!(
+                        payload,
+                        Some(Payload::DeviceGroup(DeviceGroupPayload::AddDevice(device)))
+                            if device == self.device_id()
+                    )
+                });
+            if added {
+                return self.complete_link().await;
+            }
+        }
+        for topic in topics {
+            self.initialize_topic(
+                topic.with_name(&format!("device_group({})", agent_id.renamed())),
+                false,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn complete_link(&self) -> anyhow::Result<()> {
+        let Some(agent_id) = self.local_store.complete_link()? else {
+            return Ok(());
+        };
+        self.switch_agent(agent_id).await?;
+        // Vouch for the device which linked me, so that a contact who added me
+        // with a code from this device trusts that one too.
+        if let Some(device) = self.local_store.linked_from()? {
+            self.author_operation(
+                Topic::announcements(agent_id),
+                Payload::Announcements(AnnouncementsPayload::AddDevice(device)),
+                Some(&format!("announce_device({})", device.renamed())),
+            )
+            .await?;
+        }
+        self.follow_device_group().await?;
+        tracing::info!(agent = ?agent_id.renamed(), "linked device");
+        Ok(())
+    }
+
+    /// Stop following the topics of my current agent, and follow those of another,
+    /// which the local store already switched to.
+    async fn switch_agent(&self, agent_id: AgentId) -> anyhow::Result<()> {
+        let old = self.agent_id();
+        self.unsubscribe_topic(Topic::announcements(old)).await?;
+        for topic in self.device_group_topics(old)? {
+            self.unsubscribe_topic(topic).await?;
+        }
+
+        *self.agent_id.write().unwrap() = agent_id;
+
+        self.initialize_topic(
+            Topic::announcements(agent_id).with_name(&format!("announce({})", agent_id.renamed())),
+            true,
+        )
+        .await?;
+        self.follow_device_group_topics().await?;
+        self.publish_key_bundle().await
+    }
+
+    /// Follow the contacts and group chats in my device group, which were ignored
+    /// while it wasn't my device group yet.
+    async fn follow_device_group(&self) -> anyhow::Result<()> {
+        for agent_id in self.get_contacts().await? {
+            self.subscribe_topic(
+                Topic::announcements(agent_id),
+                SubscribedTopic::Announcements(agent_id),
+            )
+            .await?;
+            self.subscribe_topic(
+                self.direct_chat_topic(agent_id),
+                SubscribedTopic::DirectChat(agent_id),
+            )
+            .await?;
+        }
+        for (_, payload) in self.device_group_payloads().await? {
+            if let DeviceGroupPayload::JoinGroup(chat_id) = payload
+                && !self.get_group_chats()?.contains(&chat_id)
+            {
+                self.join_group(chat_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a device which asked to be linked through the inbox of my AddDevice code,
+    /// once it was approved:
+    /// - record it in my device group, where it finds my contacts
+    /// - announce it, so that my contacts know it acts for me
+    /// - tell it about my group chats
+    pub(crate) async fn add_device(&self, device: DeviceId) -> anyhow::Result<()> {
+        self.author_operation(
+            self.device_group_topic(),
+            Payload::DeviceGroup(DeviceGroupPayload::AddDevice(device)),
+            Some(&format!("add_device({})", device.renamed())),
+        )
+        .await?;
+        self.author_operation(
+            Topic::announcements(self.agent_id()),
+            Payload::Announcements(AnnouncementsPayload::AddDevice(device)),
+            Some(&format!("announce_device({})", device.renamed())),
+        )
+        .await?;
+        for chat_id in self.get_group_chats()? {
+            self.record_group_chat(chat_id).await?;
+        }
+
+        tracing::info!(device = ?device.renamed(), "added device");
+        Ok(())
+    }
+
+    /// Record a group chat I joined in my device group, so that my other devices join it too.
+    pub(crate) async fn record_group_chat(&self, chat_id: ChatId) -> anyhow::Result<()> {
+        self.author_operation(
+            self.device_group_topic(),
+            Payload::DeviceGroup(DeviceGroupPayload::JoinGroup(chat_id)),
+            Some(&format!("record_group({})", chat_id.renamed())),
+        )
+        .await?;
+        Ok(())
+    }
+}
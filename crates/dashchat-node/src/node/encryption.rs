@@ -0,0 +1,293 @@
+use p2panda_core::Hash;
+use tokio::task;
+
+use crate::{KeyBundle, SealedPayload};
+
+/// How often a running node checks whether the prekey of its device is due to be replaced.
+const PREKEY_ROTATION_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+use super::*;
+
+impl Node {
+    /// The key bundle of this device, which direct chat payloads for it are sealed to.
+    pub fn key_bundle(&self) -> anyhow::Result<KeyBundle> {
+        KeyBundle::new(
+            &self.local_store.key_manager()?,
+            &self.node_data.private_key,
+        )
+    }
+
+    /// Publish the key bundle of this device on the announcements of my agent,
+    /// unless it's already there, after replacing its prekey if it's due.
+    pub(crate) async fn publish_key_bundle(&self) -> anyhow::Result<()> {
+        if self
+            .local_store
+            .rotate_prekey(Utc::now().timestamp() as u64)?
+        {
+            tracing::info!("replaced the prekey of this device");
+        }
+        let topic = Topic::announcements(self.agent_id());
+        let bundle = self.key_bundle()?;
+        if self
+            .read_model
+            .key_bundles(topic.into())?
+            .contains(&(self.device_id(), bundle))
+        {
+            return Ok(());
+        }
+        self.author_operation(
+            topic,
+            Payload::Announcements(AnnouncementsPayload::KeyBundle(bundle)),
+            Some(&format!("key_bundle({})", self.device_id().renamed())),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Keep replacing the prekey of this device before it expires, and publishing
+    /// the new key bundle, for as long as the node runs.
+    pub(crate) fn spawn_prekey_rotation_loop(&self) {
+        let node = self.clone();
+        task::spawn(async move {
+            loop {
+                tokio::time::sleep(PREKEY_ROTATION_CHECK_INTERVAL).await;
+                if let Err(err) = node.publish_key_bundle().await {
+                    tracing::error!(?err, "failed to publish key bundle");
+                }
+            }
+        });
+    }
+
+    /// Seal a chat payload for the devices of both agents, if it's for a direct chat.
+    /// Other payloads are returned as they are.
+    pub(crate) async fn seal_payload(
+        &self,
+        topic: TopicId,
+        payload: Payload,
+    ) -> anyhow::Result<Payload> {
+        if !matches!(payload, Payload::Chat(_)) {
+            return Ok(payload);
+        }
+        let Some(SubscribedTopic::DirectChat(agent_id)) =
+            self.local_store.get_subscribed_topics()?.remove(&topic)
+        else {
+            return Ok(payload);
+        };
+        let recipients = self.direct_chat_recipients(agent_id).await?;
+        Ok(Payload::Sealed(SealedPayload::seal(&payload, recipients)?))
+    }
+
+    /// Seal a payload for the inbox of a code, to the ephemeral key of the inbox,
+    /// once it checks out as signed by the device which issued the code.
+    ///
+    /// Only that device holds the key, and the sender doesn't know the issuer's other
+    /// devices yet, so they can't read it: only the issuing device lists the request.
+    pub(crate) fn seal_for_inbox(
+        &self,
+        code: &QrCode,
+        payload: InboxPayload,
+    ) -> anyhow::Result<Payload> {
+        let Some(inbox_key) = code.inbox_key.clone() else {
+            anyhow::bail!("the code has no inbox key");
+        };
+        Ok(Payload::Sealed(SealedPayload::seal(
+            &Payload::Inbox(payload),
+            [(code.device_pubkey, inbox_key)],
+        )?))
+    }
+
+    /// Open a sealed payload with the key of this device, or the key of one of my inboxes.
+    /// Other payloads are returned as they are, including inbox payloads which weren't sealed,
+    /// which older nodes still send.
+    /// None if the payload wasn't sealed for this device, for example because
+    /// it was linked after the payload was written, and no other device of mine
+    /// backfilled it yet, see [`Node::backfill`].
+    pub(crate) fn open_payload(
+        &self,
+        header: &Header,
+        payload: Payload,
+    ) -> anyhow::Result<Option<Payload>> {
+        match payload {
+            Payload::Sealed(sealed) => {
+                if let Some(inbox_key) = self.local_store.inbox_key(header.extensions.topic)? {
+                    let opened = sealed.open(self.device_id(), &[inbox_key])?;
+                    if opened
+                        .as_ref()
+                        .is_some_and(|payload| !matches!(payload, Payload::Inbox(_)))
+                    {
+                        anyhow::bail!("only inbox payloads are sealed to an inbox key");
+                    }
+                    return Ok(opened);
+                }
+                let opened = sealed.open(self.device_id(), &self.local_store.key_managers()?)?;
+                if opened.as_ref().is_some_and(|payload| {
+                    !matches!(
+                        payload,
+                        Payload::Chat(_)
+                            | Payload::DeviceGroup(
+                                DeviceGroupPayload::MoveDeviceGroup(_)
+                                    | DeviceGroupPayload::Backfill { .. }
+                            )
+                    )
+                }) {
+                    anyhow::bail!(
+                        "only chat payloads, device group moves and backfills are sealed"
+                    );
+                }
+                match opened {
+                    Some(payload) => Ok(Some(payload)),
+                    None => self.local_store.get_backfill(&header.hash()),
+                }
+            }
+            // Nodes from before inbox payloads were sealed still send them as they are.
+            // TODO: reject unsealed inbox payloads from the next release on.
+            payload @ Payload::Inbox(_) => {
+                tracing::warn!(
+                    hash = ?header.hash().renamed(),
+                    "inbox payload which wasn't sealed, accepting it for this release"
+                );
+                Ok(Some(payload))
+            }
+            payload => Ok(Some(payload)),
+        }
+    }
+
+    /// Decode the body of an operation, and open it if it's sealed.
+    pub(crate) fn decode_body(
+        &self,
+        header: &Header,
+        body: &Body,
+    ) -> anyhow::Result<Option<Payload>> {
+        self.open_payload(header, Payload::try_from_body(body)?)
+    }
+
+    /// The key bundles of the devices of my agent and of a contact.
+    async fn direct_chat_recipients(
+        &self,
+        agent_id: AgentId,
+    ) -> anyhow::Result<BTreeMap<DeviceId, KeyBundle>> {
+        let mut recipients = self.key_bundles(self.agent_id()).await?;
+        recipients.extend(self.key_bundles(agent_id).await?);
+        Ok(recipients)
+    }
+
+    /// The key bundles of the devices of an agent, from its announcements,
+    /// and from the code it was added with if it's a contact.
+    /// Only bundles of devices which act for the agent, and which they signed themselves,
+    /// count. Revoked devices are left out.
+    pub(crate) async fn key_bundles(
+        &self,
+        agent_id: AgentId,
+    ) -> anyhow::Result<BTreeMap<DeviceId, KeyBundle>> {
+        let mut bundles = self
+            .read_model
+            .key_bundles(Topic::announcements(agent_id).into())?
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        if agent_id == self.agent_id() {
+            bundles.insert(self.device_id(), self.key_bundle()?);
+        } else {
+            let codes = fold_contacts(
+                self.device_group_payloads()
+                    .await?
+                    .into_iter()
+                    .map(|(_, payload)| payload),
+            );
+            if let Some(code) = codes.get(&agent_id) {
+                bundles
+                    .entry(code.device_pubkey)
+                    .or_insert(code.key_bundle.clone());
+            }
+        }
+
+        let devices = self.agent_devices(agent_id).await?;
+        let revoked = self.local_store.get_revoked_devices()?;
+        bundles.retain(|device, bundle| {
+            if !devices.contains(device) || revoked.contains_key(device) {
+                return false;
+            }
+            match bundle.verify(*device) {
+                Ok(()) => true,
+                Err(err) => {
+                    tracing::warn!(device = ?device.renamed(), ?err, "bad key bundle, ignoring");
+                    false
+                }
+            }
+        });
+        Ok(bundles)
+    }
+
+    /// Reseal the payload of a direct chat operation for those of my devices it wasn't
+    /// sealed for, for example because they were linked after it was written.
+    /// Only the first of my devices which it was sealed for does this,
+    /// so that the others don't backfill it too.
+    pub(crate) async fn backfill(&self, header: &Header, body: &Body) -> anyhow::Result<()> {
+        let Payload::Sealed(sealed) = Payload::try_from_body(body)? else {
+            return Ok(());
+        };
+        let my_devices = self.my_devices().await?;
+        let sealed_for = sealed
+            .recipients
+            .iter()
+            .map(|(device, _)| *device)
+            .collect::<BTreeSet<_>>();
+        if sealed_for.iter().find(|device| my_devices.contains(device)) != Some(&self.device_id()) {
+            return Ok(());
+        }
+        let mut missing = self.key_bundles(self.agent_id()).await?;
+        missing.retain(|device, _| !sealed_for.contains(device));
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let Some(Payload::Chat(payload)) =
+            sealed.open(self.device_id(), &self.local_store.key_managers()?)?
+        else {
+            return Ok(());
+        };
+
+        let op = header.hash();
+        let backfill = Payload::DeviceGroup(DeviceGroupPayload::Backfill { op, payload });
+        self.author_operation(
+            self.device_group_topic(),
+            Payload::Sealed(SealedPayload::seal(&backfill, missing)?),
+            Some(&format!("backfill({})", op.renamed())),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Backfill everything in my direct chats for those of my devices it wasn't sealed for.
+    pub(crate) async fn backfill_direct_chats(&self) -> anyhow::Result<()> {
+        for (topic, subscription) in self.local_store.get_subscribed_topics()? {
+            if !matches!(subscription, SubscribedTopic::DirectChat(_)) {
+                continue;
+            }
+            for author in self.get_authors(topic).await? {
+                for (header, body) in self.get_log(topic, author).await? {
+                    if let Some(body) = body {
+                        self.backfill(&header, &body).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Process a direct chat payload which another of my devices opened for this one,
+    /// if its operation already arrived, or keep it until it does.
+    pub(crate) async fn receive_backfill(
+        &self,
+        op: Hash,
+        payload: ChatPayload,
+    ) -> anyhow::Result<()> {
+        let payload = Payload::Chat(payload);
+        if !self.local_store.add_backfill(&op, &payload)? {
+            return Ok(());
+        }
+        let Some((header, _)) = self.op_store.get_operation(op).await? else {
+            return Ok(());
+        };
+        Box::pin(self.process_payload(&header, Some(&payload), false)).await?;
+        self.notify_payload(&header, &payload).await
+    }
+}
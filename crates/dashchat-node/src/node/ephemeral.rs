@@ -0,0 +1,120 @@
+use p2panda_core::Signature;
+use p2panda_core::cbor::encode_cbor;
+use serde::{Deserialize, Serialize};
+
+use crate::{EphemeralPayload, timestamp_now};
+
+use super::*;
+
+/// Ephemeral messages older than this many seconds are dropped, so that they
+/// can't be replayed long after they were sent.
+#[cfg(feature = "p2p")]
+const EPHEMERAL_MAX_AGE: u64 = 30;
+
+/// An ephemeral payload as it is gossiped to the devices I'm connected to.
+/// It's not part of any log, so it carries its own author and signature.
+#[derive(Serialize, Deserialize)]
+struct EphemeralMessage {
+    author: DeviceId,
+    timestamp: u64,
+    payload: EphemeralPayload,
+    signature: Signature,
+}
+
+/// The bytes which are signed, which include the chat so that a message
+/// can't be replayed into another chat.
+fn signed_bytes(
+    chat_id: ChatId,
+    author: DeviceId,
+    timestamp: u64,
+    payload: &EphemeralPayload,
+) -> anyhow::Result<Vec<u8>> {
+    Ok(encode_cbor(&(
+        TopicId::from(chat_id),
+        author,
+        timestamp,
+        payload,
+    ))?)
+}
+
+impl Node {
+    /// Send a payload to the members of a chat who are connected to this device right now.
+    ///
+    /// It's only gossiped directly: it's never written to a log, nor passed through
+    /// the mailboxes, so without a direct connection it isn't delivered at all.
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn send_ephemeral(
+        &self,
+        chat_id: ChatId,
+        payload: EphemeralPayload,
+    ) -> anyhow::Result<()> {
+        let author = self.device_id();
+        let timestamp = timestamp_now();
+        let signature = self
+            .node_data
+            .private_key
+            .sign(&signed_bytes(chat_id, author, timestamp, &payload)?);
+        let message = EphemeralMessage {
+            author,
+            timestamp,
+            payload,
+            signature,
+        };
+        let bytes = encode_cbor(&message)?;
+
+        #[cfg(feature = "p2p")]
+        match self
+            .initialized_topics
+            .read()
+            .await
+            .get(&TopicId::from(chat_id))
+        {
+            Some(gossip) => {
+                gossip.send(ToNetwork::Message { bytes }).await?;
+            }
+            None => {
+                tracing::debug!(
+                    ?chat_id,
+                    "no gossip channel for chat, dropping ephemeral payload"
+                );
+            }
+        }
+        #[cfg(not(feature = "p2p"))]
+        tracing::debug!(
+            ?chat_id,
+            size = bytes.len(),
+            "no direct connections, dropping ephemeral payload"
+        );
+
+        Ok(())
+    }
+
+    /// Turn an ephemeral message gossiped in a chat into a notification.
+    #[cfg(feature = "p2p")]
+    pub(crate) async fn receive_ephemeral(
+        &self,
+        chat_id: ChatId,
+        bytes: &[u8],
+    ) -> anyhow::Result<()> {
+        let message: EphemeralMessage = p2panda_core::cbor::decode_cbor(bytes)?;
+        if message.author == self.device_id() {
+            return Ok(());
+        }
+        let signed = signed_bytes(chat_id, message.author, message.timestamp, &message.payload)?;
+        if !message.author.verify(&signed, &message.signature) {
+            anyhow::bail!("bad signature from {:?}", message.author.renamed());
+        }
+        if timestamp_now().saturating_sub(message.timestamp) > EPHEMERAL_MAX_AGE {
+            tracing::debug!("dropping stale ephemeral message");
+            return Ok(());
+        }
+
+        self.notify(Event::Ephemeral {
+            chat_id,
+            author: message.author,
+            payload: message.payload,
+        })
+        .await;
+        Ok(())
+    }
+}
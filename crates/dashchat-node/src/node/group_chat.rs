@@ -0,0 +1,183 @@
+use anyhow::bail;
+
+use crate::GroupInvitation;
+use crate::chat::fold_group_members;
+
+use super::*;
+
+impl Node {
+    /// Create a new group chat on a random topic, with me as its first member.
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn create_group_chat(&self) -> anyhow::Result<ChatId> {
+        let chat_id = ChatId::random().with_name(&format!("group({})", self.agent_id().renamed()));
+
+        self.join_group(chat_id).await?;
+        self.author_operation(
+            chat_id,
+            Payload::Chat(ChatPayload::AddMember {
+                agent_id: self.agent_id(),
+                devices: self.my_devices().await?.into_iter().collect(),
+            }),
+            Some(&format!("create_group({})", self.agent_id().renamed())),
+        )
+        .await?;
+        self.record_group_chat(chat_id).await?;
+
+        tracing::info!(?chat_id, "created group chat");
+        Ok(chat_id)
+    }
+
+    /// Invite a contact into a group chat:
+    /// - record them as a member in the group chat topic
+    /// - send them a JoinGroup through our direct chat, so that they subscribe
+    ///   to the group chat topic
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn invite_to_group(&self, chat_id: ChatId, agent_id: AgentId) -> anyhow::Result<()> {
+        if !self.get_contacts().await?.contains(&agent_id) {
+            bail!("only contacts can be invited to a group chat");
+        }
+
+        if !self
+            .get_group_members(chat_id)
+            .await?
+            .contains(&self.agent_id())
+        {
+            bail!("only members can invite to group chat {chat_id}");
+        }
+
+        let devices = self
+            .device_agents()
+            .await?
+            .into_iter()
+            .filter(|(_, agent)| *agent == agent_id)
+            .map(|(device, _)| device)
+            .collect();
+        self.author_operation(
+            chat_id,
+            Payload::Chat(ChatPayload::AddMember { agent_id, devices }),
+            Some(&format!("add_member({})", agent_id.renamed())),
+        )
+        .await?;
+
+        self.author_operation(
+            self.direct_chat_topic(agent_id),
+            Payload::Chat(ChatPayload::JoinGroup(chat_id)),
+            Some(&format!("invite_to_group({})", agent_id.renamed())),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// The group chats I have created or joined.
+    pub fn get_group_chats(&self) -> anyhow::Result<Vec<ChatId>> {
+        Ok(self
+            .local_store
+            .get_subscribed_topics()?
+            .into_iter()
+            .filter(|(_, subscription)| *subscription == SubscribedTopic::GroupChat)
+            .map(|(topic, _)| ChatId::new(*topic))
+            .collect())
+    }
+
+    /// The members of a group chat, in the order they were added
+    /// by devices of members.
+    pub async fn get_group_members(&self, chat_id: ChatId) -> anyhow::Result<Vec<AgentId>> {
+        let authors = self.get_authors(chat_id.into()).await?;
+        let payloads = self
+            .get_interleaved_logs(chat_id.into(), authors.into_iter().collect())
+            .await?
+            .into_iter()
+            .filter_map(|(header, payload)| match payload {
+                Some(Payload::Chat(payload)) => Some((DeviceId::from(header.public_key), payload)),
+                _ => None,
+            });
+        Ok(fold_group_members(&self.device_agents().await?, payloads))
+    }
+
+    /// Leave a group chat:
+    /// - tell the other members that I left
+    /// - push that to the mailboxes while still subscribed
+    /// - unsubscribe, so that the group chat isn't received anymore,
+    ///   also after restarting
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn leave_group(&self, chat_id: ChatId) -> anyhow::Result<()> {
+        if !self.get_group_chats()?.contains(&chat_id) {
+            bail!("not a member of group chat {chat_id}");
+        }
+
+        self.author_operation(
+            chat_id,
+            Payload::Chat(ChatPayload::LeaveGroup(self.agent_id())),
+            Some(&format!("leave_group({})", self.agent_id().renamed())),
+        )
+        .await?;
+
+        if let Err(err) = self
+            .mailboxes
+            .sync_topics_with_all(std::iter::once(chat_id.into()))
+            .await
+        {
+            tracing::warn!(?err, "failed to publish leaving the group chat");
+        }
+
+        self.unsubscribe_topic(chat_id).await?;
+
+        tracing::info!(?chat_id, "left group chat");
+        Ok(())
+    }
+
+    /// Group chat invitations which are waiting to be accepted or declined.
+    /// There are only ever pending invitations with [`GroupInvitationPolicy::Manual`].
+    pub fn pending_group_invitations(&self) -> anyhow::Result<Vec<GroupInvitation>> {
+        self.local_store.get_group_invitations()
+    }
+
+    /// Join the group chat of a pending invitation.
+    pub async fn accept_group_invitation(&self, chat_id: ChatId) -> anyhow::Result<()> {
+        if !self
+            .pending_group_invitations()?
+            .iter()
+            .any(|invitation| invitation.chat_id == chat_id)
+        {
+            bail!("no pending invitation for group chat {chat_id}");
+        }
+        self.join_group(chat_id).await?;
+        self.record_group_chat(chat_id).await
+    }
+
+    /// Forget a pending invitation without joining the group chat.
+    pub fn decline_group_invitation(&self, chat_id: ChatId) -> anyhow::Result<()> {
+        if !self.local_store.remove_group_invitation(chat_id)? {
+            bail!("no pending invitation for group chat {chat_id}");
+        }
+        Ok(())
+    }
+
+    /// Handle a JoinGroup sent to me by a contact, according to the configured policy.
+    pub(crate) async fn receive_group_invitation(
+        &self,
+        invitation: GroupInvitation,
+    ) -> anyhow::Result<()> {
+        if self.get_group_chats()?.contains(&invitation.chat_id) {
+            return Ok(());
+        }
+        tracing::info!(?invitation, "received group invitation");
+        match self.config.group_invitations {
+            GroupInvitationPolicy::AutoJoin => self.join_group(invitation.chat_id).await,
+            GroupInvitationPolicy::Manual => self.local_store.add_group_invitation(invitation),
+        }
+    }
+
+    /// The contact whose direct chat with me is on the given topic, if any.
+    pub(crate) async fn direct_chat_contact(
+        &self,
+        topic: TopicId,
+    ) -> anyhow::Result<Option<AgentId>> {
+        Ok(self
+            .get_contacts()
+            .await?
+            .into_iter()
+            .find(|agent_id| TopicId::from(self.direct_chat_topic(*agent_id)) == topic))
+    }
+}
@@ -0,0 +1,56 @@
+use crate::read_model::IndexedContent;
+use crate::{HistoryContent, HistoryMessage, HistoryPage, HistoryQuery, MessageVersion};
+
+use super::*;
+
+impl Node {
+    /// A page of a chat's history, with the edits, quotes and reactions
+    /// of each message applied. Deleted messages are left out.
+    ///
+    /// Messages are ordered by timestamp, with the author and sequence number
+    /// breaking ties, so that every node shows them in the same order.
+    pub async fn message_history(
+        &self,
+        chat_id: ChatId,
+        query: HistoryQuery,
+    ) -> anyhow::Result<HistoryPage> {
+        let (indexed, has_more) = self.read_model.messages(chat_id, &query)?;
+        let device_agents = self.device_agents().await?;
+        let mut reactions =
+            self.reaction_counts(chat_id, indexed.iter().map(|m| m.hash), &device_agents)?;
+
+        let mut messages = vec![];
+        for message in indexed {
+            let (content, quoted) = match message.content {
+                IndexedContent::Text(content) => {
+                    let quoted = match content.reply_to {
+                        Some(reply_to) => Some(self.quote_message(chat_id, reply_to).await?),
+                        None => None,
+                    };
+                    let original = MessageVersion {
+                        content,
+                        author: message.device,
+                        timestamp: message.timestamp,
+                        hash: message.hash,
+                    };
+                    let edited = self.apply_edits(chat_id, original, &device_agents)?;
+                    (HistoryContent::Text(edited), quoted)
+                }
+                IndexedContent::Attachment(attachment) => {
+                    (HistoryContent::Attachment(attachment), None)
+                }
+            };
+            messages.push(HistoryMessage {
+                hash: message.hash,
+                author: device_agents.get(&message.device).copied(),
+                device: message.device,
+                timestamp: message.timestamp,
+                content,
+                quoted,
+                reactions: reactions.remove(&message.hash).unwrap_or_default(),
+            });
+        }
+
+        Ok(HistoryPage { messages, has_more })
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+use p2panda_core::Hash;
+use p2panda_store::OperationStore;
+
+use crate::{EditedMessage, MessageVersion, QuotedMessage};
+
+use super::*;
+
+impl Node {
+    /// Replace the content of one of my messages.
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn edit_message(
+        &self,
+        chat_id: ChatId,
+        target: Hash,
+        content: ChatMessageContent,
+    ) -> anyhow::Result<Header> {
+        let Some(message) = self.get_edited_message(chat_id, target).await? else {
+            bail!("no message {target} in chat {chat_id}");
+        };
+        if self.device_agents().await?.get(&message.author()) != Some(&self.agent_id()) {
+            bail!("only the author of a message can edit it");
+        }
+
+        self.author_operation(
+            chat_id,
+            Payload::Chat(ChatPayload::Edit { target, content }),
+            None,
+        )
+        .await
+    }
+
+    /// Delete one of my messages for everyone in the chat.
+    ///
+    /// Only the header of the message and its edits is kept, so the logs stay intact.
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn delete_message(&self, chat_id: ChatId, target: Hash) -> anyhow::Result<Header> {
+        let Some(message) = self.get_edited_message(chat_id, target).await? else {
+            bail!("no message {target} in chat {chat_id}");
+        };
+        if self.device_agents().await?.get(&message.author()) != Some(&self.agent_id()) {
+            bail!("only the author of a message can delete it");
+        }
+
+        self.author_operation(chat_id, Payload::Chat(ChatPayload::Delete { target }), None)
+            .await
+    }
+
+    /// A message with its latest content, and the history of all its versions.
+    /// Deleted messages are not returned.
+    ///
+    /// Edits from devices of other agents than the message's author are ignored.
+    pub async fn get_edited_message(
+        &self,
+        chat_id: ChatId,
+        hash: Hash,
+    ) -> anyhow::Result<Option<EditedMessage>> {
+        let Some(original) = self
+            .read_model
+            .message(chat_id, hash)?
+            .and_then(|message| message.text_version())
+        else {
+            return Ok(None);
+        };
+        self.apply_edits(chat_id, original, &self.device_agents().await?)
+            .map(Some)
+    }
+
+    /// The edits of a message of a chat applied to its original version.
+    /// The edits are already in the order they apply in, so that every node
+    /// agrees on the latest version.
+    pub(crate) fn apply_edits(
+        &self,
+        chat_id: ChatId,
+        original: MessageVersion,
+        device_agents: &BTreeMap<DeviceId, AgentId>,
+    ) -> anyhow::Result<EditedMessage> {
+        let edits = self
+            .read_model
+            .edits(chat_id, original.hash)?
+            .into_iter()
+            .map(|edit| edit.version)
+            .filter(|edit| {
+                let valid = same_agent(device_agents, edit.author, original.author);
+                if !valid {
+                    tracing::warn!(edit = ?edit.hash.renamed(), "edit by another agent, ignoring");
+                }
+                valid
+            })
+            .collect::<Vec<_>>();
+        Ok(EditedMessage::new(original, edits))
+    }
+
+    /// The message a reply refers to, with an excerpt of its latest version.
+    pub async fn quote_message(
+        &self,
+        chat_id: ChatId,
+        hash: Hash,
+    ) -> anyhow::Result<QuotedMessage> {
+        let message = self.get_edited_message(chat_id, hash).await?;
+        Ok(QuotedMessage {
+            hash,
+            author: message.as_ref().map(|message| message.author()),
+            excerpt: message.map(|message| message.content.excerpt()),
+        })
+    }
+
+    /// Remove the body of a deleted message and of its edits from the store,
+    /// if the message was deleted by a device of its author.
+    ///
+    /// The deletion and the message can arrive in any order, so this is called
+    /// whenever either of them, or an edit of the message, is processed.
+    /// Likewise, the agent of the deleting device may only be learned later,
+    /// so the deletion stays pending until then, see [`Node::retry_pending_deletions`].
+    pub(crate) async fn prune_deleted_message(
+        &self,
+        topic: TopicId,
+        target: Hash,
+    ) -> anyhow::Result<()> {
+        let deleters = self.local_store.get_message_deleters(&target)?;
+        if deleters.is_empty() {
+            return Ok(());
+        }
+        let Some((header, _)) = self.op_store.get_operation(target).await? else {
+            // The message hasn't arrived yet.
+            return Ok(());
+        };
+
+        let device_agents = self.device_agents().await?;
+        let author = DeviceId::from(header.public_key);
+        if !deleters
+            .iter()
+            .any(|deleter| same_agent(&device_agents, *deleter, author))
+        {
+            let known = std::iter::once(&author)
+                .chain(&deleters)
+                .all(|device| device_agents.contains_key(device));
+            if known {
+                tracing::warn!(message = ?target.renamed(), "deletion by another agent, ignoring");
+                self.local_store.resolve_deletion(&target)?;
+            } else {
+                tracing::debug!(
+                    message = ?target.renamed(),
+                    "deletion by a device whose agent isn't known yet, keeping it pending"
+                );
+            }
+            return Ok(());
+        }
+
+        let edits = self
+            .get_interleaved_logs(topic, self.get_authors(topic).await?.into_iter().collect())
+            .await?
+            .into_iter()
+            .filter_map(|(header, payload)| match payload {
+                Some(Payload::Chat(ChatPayload::Edit { target: t, .. })) if t == target => {
+                    Some(header.hash())
+                }
+                _ => None,
+            });
+
+        let mut op_store = self.op_store.clone();
+        for hash in std::iter::once(target).chain(edits) {
+            op_store.delete_payload(hash).await?;
+        }
+        self.read_model
+            .remove_message(ChatId::new(*topic), target)?;
+        self.local_store.resolve_deletion(&target)?;
+        tracing::info!(message = ?target.renamed(), "pruned deleted message");
+
+        Ok(())
+    }
+
+    /// Check the pending deletions again, after learning which agent a device acts for.
+    pub(crate) async fn retry_pending_deletions(&self) -> anyhow::Result<()> {
+        for (topic, target) in self.local_store.get_pending_deletions()? {
+            self.prune_deleted_message(topic, target).await?;
+        }
+        Ok(())
+    }
+
+    /// The agent of every device I know of, whether it was revoked since or not:
+    /// - my own devices
+    /// - the devices of my contacts: the device whose code I added them with,
+    ///   and the devices which their devices announced, and so on
+    pub(crate) async fn device_agents(&self) -> anyhow::Result<BTreeMap<DeviceId, AgentId>> {
+        let mut agents = BTreeMap::new();
+        let codes = fold_contacts(
+            self.device_group_payloads()
+                .await?
+                .into_iter()
+                .map(|(_, payload)| payload),
+        );
+        for (agent_id, code) in codes {
+            for device in self.contact_devices(&code)? {
+                agents.insert(device, agent_id);
+            }
+        }
+
+        for device in self.my_authorized_devices().await? {
+            agents.insert(device, self.agent_id());
+        }
+
+        Ok(agents)
+    }
+}
+
+/// Whether two devices belong to the same agent, as far as I know.
+fn same_agent(device_agents: &BTreeMap<DeviceId, AgentId>, a: DeviceId, b: DeviceId) -> bool {
+    a == b
+        || device_agents
+            .get(&a)
+            .is_some_and(|agent| device_agents.get(&b) == Some(agent))
+}
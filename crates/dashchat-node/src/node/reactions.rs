@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+use p2panda_core::Hash;
+
+use crate::ReactionCount;
+use crate::read_model::IndexedReaction;
+
+use super::*;
+
+/// Who a reaction counts for: the agent of the device which wrote it,
+/// or the device itself if I don't know its agent.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Reactor {
+    Agent(AgentId),
+    Device(DeviceId),
+}
+
+/// The current reactions to a message, given the latest reaction of each device.
+///
+/// Only the latest reaction of each agent counts, and a reaction without an emoji
+/// removes the earlier one. Timestamps only have a resolution of seconds, so the
+/// device and sequence number break ties, and every node agrees on the latest one.
+fn fold_reactions(
+    reactions: impl IntoIterator<Item = (DeviceId, IndexedReaction)>,
+    device_agents: &BTreeMap<DeviceId, AgentId>,
+) -> Vec<ReactionCount> {
+    let mut reactions = reactions.into_iter().collect::<Vec<_>>();
+    reactions.sort_by_key(|(device, reaction)| (reaction.timestamp, *device, reaction.seq_num));
+
+    let mut latest = BTreeMap::new();
+    for (device, reaction) in reactions {
+        let reactor = match device_agents.get(&device) {
+            Some(agent) => Reactor::Agent(*agent),
+            None => Reactor::Device(device),
+        };
+        latest.insert(reactor, reaction.emoji);
+    }
+
+    let mut emojis: BTreeMap<String, ReactionCount> = BTreeMap::new();
+    for (reactor, emoji) in latest {
+        let Some(emoji) = emoji else {
+            continue;
+        };
+        let count = emojis
+            .entry(emoji.clone())
+            .or_insert_with(|| ReactionCount {
+                emoji,
+                count: 0,
+                agents: vec![],
+            });
+        count.count += 1;
+        if let Reactor::Agent(agent) = reactor {
+            count.agents.push(agent);
+        }
+    }
+    emojis.into_values().collect()
+}
+
+impl Node {
+    /// The current reactions to a message, with the number of agents
+    /// who reacted with each emoji.
+    pub async fn reactions(
+        &self,
+        chat_id: ChatId,
+        target: Hash,
+    ) -> anyhow::Result<Vec<ReactionCount>> {
+        if self.read_model.message(chat_id, target)?.is_none() {
+            bail!("no message {target} in chat {chat_id}");
+        }
+        let device_agents = self.device_agents().await?;
+        Ok(fold_reactions(
+            self.read_model.reactions(chat_id, target)?,
+            &device_agents,
+        ))
+    }
+
+    /// The current reactions to each of a page of messages.
+    /// Messages which aren't in the chat, or were deleted, are left out.
+    pub async fn reactions_batch(
+        &self,
+        chat_id: ChatId,
+        targets: &[Hash],
+    ) -> anyhow::Result<HashMap<Hash, Vec<ReactionCount>>> {
+        let mut in_chat = vec![];
+        for target in targets {
+            if self.read_model.message(chat_id, *target)?.is_some() {
+                in_chat.push(*target);
+            }
+        }
+        let device_agents = self.device_agents().await?;
+        self.reaction_counts(chat_id, in_chat, &device_agents)
+    }
+
+    pub(crate) fn reaction_counts(
+        &self,
+        chat_id: ChatId,
+        targets: impl IntoIterator<Item = Hash>,
+        device_agents: &BTreeMap<DeviceId, AgentId>,
+    ) -> anyhow::Result<HashMap<Hash, Vec<ReactionCount>>> {
+        targets
+            .into_iter()
+            .map(|target| {
+                let reactions = self.read_model.reactions(chat_id, target)?;
+                Ok((target, fold_reactions(reactions, device_agents)))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,178 @@
+use p2panda_core::Hash;
+use p2panda_store::OperationStore;
+use tokio::task;
+use tracing::Instrument;
+
+use crate::{MessageStatus, Receipt};
+
+use super::*;
+
+impl Node {
+    /// Mark all messages of a chat up to and including the given one as read
+    /// on all my devices, and tell the other members of the chat,
+    /// unless read receipts are disabled for the chat.
+    #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me = ?self.device_id().renamed())))]
+    pub async fn mark_read(&self, chat_id: ChatId, up_to: Hash) -> anyhow::Result<()> {
+        self.author_operation(
+            self.device_group_topic(),
+            Payload::DeviceGroup(DeviceGroupPayload::MarkRead { chat_id, up_to }),
+            None,
+        )
+        .await?;
+
+        if !self.local_store.read_receipts_enabled(chat_id)? {
+            return Ok(());
+        }
+        let receipt = Receipt::Read { up_to };
+        self.author_operation(chat_id, Payload::Chat(ChatPayload::Receipt(receipt)), None)
+            .await?;
+        Ok(())
+    }
+
+    /// Whether read receipts are sent for a chat. They are enabled by default.
+    pub fn read_receipts_enabled(&self, chat_id: ChatId) -> anyhow::Result<bool> {
+        self.local_store.read_receipts_enabled(chat_id)
+    }
+
+    pub fn set_read_receipts_enabled(&self, chat_id: ChatId, enabled: bool) -> anyhow::Result<()> {
+        self.local_store.set_read_receipts_enabled(chat_id, enabled)
+    }
+
+    /// How far a message has made it:
+    /// - whether it was published to a mailbox
+    /// - which devices received it
+    /// - which agents read it
+    pub async fn message_status(
+        &self,
+        chat_id: ChatId,
+        hash: Hash,
+    ) -> anyhow::Result<MessageStatus> {
+        let Some((message, _)) = self.op_store.get_operation(hash).await? else {
+            anyhow::bail!("no message {hash} in chat {chat_id}");
+        };
+
+        let authors = self.get_authors(chat_id.into()).await?;
+        let mut delivered_up_to = vec![];
+        let mut read_up_to = vec![];
+        for (header, payload) in self
+            .get_interleaved_logs(chat_id.into(), authors.into_iter().collect())
+            .await?
+        {
+            let device = DeviceId::from(header.public_key);
+            match payload {
+                Some(Payload::Chat(ChatPayload::Receipt(Receipt::Delivered { up_to }))) => {
+                    delivered_up_to.push((device, up_to));
+                }
+                Some(Payload::Chat(ChatPayload::Receipt(Receipt::Read { up_to }))) => {
+                    read_up_to.push((device, up_to));
+                }
+                _ => {}
+            }
+        }
+
+        let mut delivered_to = BTreeSet::new();
+        for (device, up_to) in delivered_up_to {
+            if self.reaches(chat_id, up_to, &message).await? {
+                delivered_to.insert(device);
+            }
+        }
+
+        // Readers are the agents of the devices which authored the receipts,
+        // so that a device can't claim to read on behalf of another agent.
+        let device_agents = self.device_agents().await?;
+        let mut read_by = BTreeSet::new();
+        for (device, up_to) in read_up_to {
+            if let Some(agent) = device_agents.get(&device)
+                && self.reaches(chat_id, up_to, &message).await?
+            {
+                read_by.insert(*agent);
+            }
+        }
+
+        // Anything another device received must have been published.
+        let published = self.mailboxes.is_published(&hash).await?
+            || delivered_to
+                .iter()
+                .any(|device| *device != DeviceId::from(message.public_key));
+
+        Ok(MessageStatus {
+            published,
+            delivered_to,
+            read_by,
+        })
+    }
+
+    /// Whether a receipt up to the given message covers a message of the chat.
+    /// Messages are ordered like edits, since timestamps only have a resolution of seconds.
+    async fn reaches(
+        &self,
+        chat_id: ChatId,
+        up_to: Hash,
+        message: &Header,
+    ) -> anyhow::Result<bool> {
+        let Some((up_to, _)) = self.op_store.get_operation(up_to).await? else {
+            return Ok(false);
+        };
+        Ok(up_to.extensions.topic == TopicId::from(chat_id)
+            && receipt_order(&up_to) >= receipt_order(message))
+    }
+
+    /// Author the Delivered receipts for messages as they are received.
+    ///
+    /// Receipts are authored outside of processing the message, because authoring
+    /// an operation processes it, which would make processing recursive.
+    /// All messages which are waiting are covered by a single receipt per chat,
+    /// up to the latest of them, so a sync of many messages doesn't author as many receipts.
+    pub(crate) fn spawn_receipt_loop(&self, mut receipt_rx: mpsc::Receiver<(ChatId, Header)>) {
+        let node = self.clone();
+
+        task::spawn(
+            async move {
+                let mut sent = HashMap::new();
+                while let Some(first) = receipt_rx.recv().await {
+                    let mut latest = HashMap::<ChatId, Header>::new();
+                    let mut next = Some(first);
+                    while let Some((chat_id, header)) = next {
+                        let order = receipt_order(&header);
+                        if sent.get(&chat_id).is_none_or(|sent| order > *sent)
+                            && latest
+                                .get(&chat_id)
+                                .is_none_or(|latest| order > receipt_order(latest))
+                        {
+                            latest.insert(chat_id, header);
+                        }
+                        next = receipt_rx.try_recv().ok();
+                    }
+
+                    for (chat_id, header) in latest {
+                        let order = receipt_order(&header);
+                        let receipt = Receipt::Delivered {
+                            up_to: header.hash(),
+                        };
+                        match node
+                            .author_operation(
+                                chat_id,
+                                Payload::Chat(ChatPayload::Receipt(receipt)),
+                                None,
+                            )
+                            .await
+                        {
+                            Ok(_) => {
+                                sent.insert(chat_id, order);
+                            }
+                            Err(err) => {
+                                tracing::error!(?err, "failed to send delivered receipt");
+                            }
+                        }
+                    }
+                }
+            }
+            .instrument(tracing::info_span!("receipt_loop")),
+        );
+    }
+}
+
+/// The order in which receipts cover messages.
+fn receipt_order(header: &Header) -> (u64, p2panda_core::PublicKey, u64) {
+    (header.timestamp, header.public_key, header.seq_num)
+}
@@ -0,0 +1,49 @@
+use crate::SearchResult;
+use crate::chat::{matches, snippet, tokenize};
+
+use super::*;
+
+impl Node {
+    /// Messages whose latest version has a word starting with each word of the query,
+    /// newest first, in one chat or in all chats.
+    ///
+    /// Case and diacritics are ignored, so "cafe" finds "Café".
+    pub async fn search_messages(
+        &self,
+        query: &str,
+        chat_id: Option<ChatId>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        let tokens = tokenize(query);
+        if tokens.is_empty() {
+            return Ok(vec![]);
+        }
+        let device_agents = self.device_agents().await?;
+
+        let mut results = vec![];
+        for (chat_id, message) in self.read_model.search(&tokens, chat_id)? {
+            if results.len() == limit {
+                break;
+            }
+            let Some(original) = message.text_version() else {
+                continue;
+            };
+            // Older versions may match where the latest one doesn't.
+            let edited = self.apply_edits(chat_id, original, &device_agents)?;
+            if !matches(&edited.content.text, &tokens) {
+                continue;
+            }
+            let Some(snippet) = snippet(&edited.content.text, &tokens) else {
+                continue;
+            };
+            results.push(SearchResult {
+                chat_id,
+                hash: message.hash,
+                author: message.device,
+                timestamp: message.timestamp,
+                snippet,
+            });
+        }
+        Ok(results)
+    }
+}
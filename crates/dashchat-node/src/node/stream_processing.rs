@@ -5,12 +5,18 @@ use futures::StreamExt;
 use futures::stream::SelectAll;
 use mailbox_client::MailboxItem;
 use p2panda_core::Operation;
+use p2panda_store::OperationStore;
 use serde::{Deserialize, Serialize};
 use tokio::task;
 use tokio_stream::wrappers::ReceiverStream;
 use tracing::Instrument;
 
-use crate::{payload::InboxPayload, topic::TopicKind};
+use crate::{
+    GroupInvitation,
+    local_store::SubscribedTopic,
+    payload::{DeviceGroupPayload, EphemeralPayload, InboxPayload},
+    topic::TopicKind,
+};
 
 use super::*;
 
@@ -20,6 +26,28 @@ pub struct Notification {
     pub payload: Payload,
 }
 
+/// What the node tells the app about as it happens.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Event {
+    /// An operation was processed.
+    Notification(Notification),
+    /// Another device sent an ephemeral payload to a chat.
+    /// These are never stored, so they are only seen while online.
+    Ephemeral {
+        chat_id: ChatId,
+        author: DeviceId,
+        payload: EphemeralPayload,
+    },
+    /// Another chunk of a file was downloaded.
+    DownloadProgress {
+        /// The hash of the file's content
+        hash: p2panda_core::Hash,
+        /// How many bytes have been downloaded so far
+        received: u64,
+        size: u64,
+    },
+}
+
 impl Node {
     /// Internal function to start the necessary tasks for processing group chat
     /// network activity.
@@ -34,6 +62,12 @@ impl Node {
     ) -> anyhow::Result<()> {
         {
             let mailbox_rx = self.mailboxes.subscribe(topic.into()).await?;
+            // Replacing an existing sender also ends the topic's previous stream.
+            let (stop_tx, stop_rx) = oneshot::channel::<()>();
+            self.topic_streams
+                .lock()
+                .unwrap()
+                .insert(topic.into(), stop_tx);
             let stream = ReceiverStream::new(mailbox_rx).filter_map(async |op| {
                     let hash = op.hash();
                     if hash == op.header.hash() {
@@ -51,7 +85,7 @@ impl Node {
                             None
                         }
                     }
-                });
+                }).take_until(stop_rx);
 
             self.stream_tx
                 .send(Pin::from(Box::new(stream)))
@@ -62,6 +96,34 @@ impl Node {
         Ok(())
     }
 
+    /// Initialize a topic and remember it, so that it gets initialized again
+    /// whenever the node starts.
+    pub(crate) async fn subscribe_topic<K: TopicKind>(
+        &self,
+        topic: Topic<K>,
+        subscription: SubscribedTopic,
+    ) -> anyhow::Result<()> {
+        self.local_store
+            .add_subscribed_topic(topic.into(), subscription)?;
+        self.initialize_topic(topic, false).await
+    }
+
+    /// Stop receiving operations for a topic, and forget it so that it isn't
+    /// initialized again when the node starts.
+    ///
+    /// This also ends the topic's stream, which removes it from the processing loop.
+    pub(crate) async fn unsubscribe_topic<K: TopicKind>(
+        &self,
+        topic: Topic<K>,
+    ) -> anyhow::Result<()> {
+        self.local_store.remove_subscribed_topic(topic.into())?;
+        self.topic_streams
+            .lock()
+            .unwrap()
+            .remove(&TopicId::from(topic));
+        self.mailboxes.unsubscribe(topic.into()).await
+    }
+
     pub fn spawn_stream_process_loop(
         &self,
         mut stream_rx: mpsc::Receiver<
@@ -106,6 +168,12 @@ impl Node {
         let hash = operation.hash;
         let topic = operation.header.extensions.topic;
 
+        // Remember the operation until it's processed, because the orderer only keeps it
+        // in memory, and it won't be fetched again once it's in the op store.
+        if !self.op_store.is_op_processed(&topic, &hash) {
+            self.local_store.add_pending_op(topic, &hash)?;
+        }
+
         if let Err(err) = self.op_store.process_ordering(operation).await {
             tracing::error!(?err, "process ordering error");
         }
@@ -137,6 +205,25 @@ impl Node {
         Ok(())
     }
 
+    /// Process the operations which were received but still waiting for their
+    /// dependencies when the node stopped.
+    pub(crate) async fn replay_pending_ops(&self) -> anyhow::Result<()> {
+        let pending = self
+            .op_store
+            .load_in_causal_order(self.local_store.get_pending_ops()?)
+            .await?;
+        tracing::info!(pending = pending.len(), "replaying pending operations");
+        for operation in pending {
+            if !self
+                .op_store
+                .is_op_processed(&operation.header.extensions.topic, &operation.hash)
+            {
+                self.process_stream_item(operation).await?;
+            }
+        }
+        Ok(())
+    }
+
     pub async fn process_operation(
         &self,
         // topic: Topic<K>,
@@ -154,7 +241,34 @@ impl Node {
 
         tracing::info!(topic = ?topic.renamed(), hash = ?hash.renamed(), "PROC: processing operation");
 
-        let payload = body.map(|body| Payload::try_from_body(&body)).transpose()?;
+        // A revoked device's operations beyond where it was revoked are ignored.
+        if self
+            .local_store
+            .is_revoked(header.public_key.into(), topic, header.seq_num)?
+        {
+            tracing::warn!(hash = ?hash.renamed(), "operation by a revoked device, ignoring");
+            self.op_store.mark_op_processed(topic, &hash);
+            self.local_store.add_processed_op(topic, &hash)?;
+            return Ok(());
+        }
+
+        let payload = match &body {
+            Some(body) => match self.decode_body(&header, body)? {
+                Some(payload) => Some(payload),
+                None => {
+                    // Kept, so that another of my devices can backfill it.
+                    tracing::debug!(
+                        hash = ?hash.renamed(),
+                        "sealed for other devices, waiting for a backfill"
+                    );
+                    self.local_store.add_unopened_op(&hash)?;
+                    self.op_store.mark_op_processed(topic, &hash);
+                    self.local_store.add_processed_op(topic, &hash)?;
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
 
         tracing::trace!(?payload, "RECEIVED PAYLOAD");
 
@@ -178,25 +292,128 @@ impl Node {
             self.notify_payload(&header, payload).await?;
         }
 
+        // Some of my devices may not be able to open it.
+        if let (Some(body), Some(Payload::Chat(_))) = (&body, &payload)
+            && !is_author
+            && let Err(err) = Box::pin(self.backfill(&header, body)).await
+        {
+            tracing::error!(hash = ?hash.renamed(), ?err, "backfill error");
+        }
+
         // XXX: don't repair this often.
         // Box::pin(self.repair_spaces_and_publish()).await?;
 
         self.op_store.mark_op_processed(topic, &hash);
+        self.local_store.add_processed_op(topic, &hash)?;
 
         anyhow::Ok(())
     }
 
+    /// Rebuild the read model from all processed operations in the op store.
+    /// This happens when the node starts with a read model from an older version.
+    pub async fn rebuild_read_model(&self) -> anyhow::Result<()> {
+        self.read_model.clear()?;
+        let processed = self.local_store.get_processed_ops()?;
+        for (_, hash) in &processed {
+            let Some((header, Some(body))) = self.op_store.get_operation(*hash).await? else {
+                continue;
+            };
+            if self.local_store.is_revoked(
+                header.public_key.into(),
+                header.extensions.topic,
+                header.seq_num,
+            )? {
+                continue;
+            }
+            match self.decode_body(&header, &body) {
+                Ok(Some(payload)) => {
+                    if self.accepts_payload(&header, &payload).await? {
+                        self.read_model.apply(&header, &payload)?;
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::warn!(?err, hash = ?hash.renamed(), "can't decode payload, skipping")
+                }
+            }
+        }
+        tracing::info!(ops = processed.len(), "rebuilt read model");
+        Ok(())
+    }
+
     pub async fn notify_payload(&self, header: &Header, payload: &Payload) -> anyhow::Result<()> {
-        if let Some((notification_tx, payload)) = self.notification_tx.clone().zip(Some(payload)) {
+        self.notify(Event::Notification(Notification {
+            header: header.clone(),
+            payload: payload.clone(),
+        }))
+        .await;
+        Ok(())
+    }
+
+    pub(crate) async fn notify(&self, event: Event) {
+        if let Some(notification_tx) = self.notification_tx.as_ref() {
             notification_tx
-                .send(Notification {
-                    header: header.clone(),
-                    payload: payload.clone(),
-                })
+                .send(event)
                 .await
                 .unwrap_or_else(|_| tracing::warn!("notification channel closed"));
         }
-        Ok(())
+    }
+
+    /// Whether a payload counts at all, so that it's indexed and acted on.
+    /// Payloads which only count in certain topics, or from certain devices,
+    /// are ignored anywhere else.
+    pub(crate) async fn accepts_payload(
+        &self,
+        header: &Header,
+        payload: &Payload,
+    ) -> anyhow::Result<bool> {
+        let topic = header.extensions.topic;
+        let author = DeviceId::from(header.public_key);
+        let reason = match payload {
+            // Anyone can write to a device group topic, since it starts out derived
+            // from the agent ID, so only what my devices write there counts.
+            Payload::DeviceGroup(_) if !self.my_authorized_devices().await?.contains(&author) => {
+                "device group payload from a device which isn't mine"
+            }
+            Payload::DeviceGroup(DeviceGroupPayload::MoveDeviceGroup(_))
+                if topic != TopicId::from(self.device_group_topic()) =>
+            {
+                "MoveDeviceGroup outside of my current device group"
+            }
+            Payload::DeviceGroup(_) if !self.is_device_group_topic(topic)? => {
+                "device group payload outside of my device group"
+            }
+            Payload::Inbox(_)
+                if !self
+                    .local_store
+                    .get_active_inbox_topics()?
+                    .iter()
+                    .any(|inbox| **inbox.topic == *topic) =>
+            {
+                // Not for me.
+                return Ok(false);
+            }
+            Payload::Inbox(InboxPayload::DeviceRequest { .. })
+                if !self.local_store.is_device_inbox(topic)? =>
+            {
+                "DeviceRequest outside of an unused AddDevice inbox"
+            }
+            Payload::Chat(ChatPayload::JoinGroup(_)) => {
+                let inviter = self.direct_chat_contact(topic).await?;
+                match (inviter, self.device_agents().await?.get(&author)) {
+                    (None, _) => "JoinGroup outside of a direct chat with a contact",
+                    (Some(inviter), Some(agent))
+                        if *agent == inviter || *agent == self.agent_id() =>
+                    {
+                        return Ok(true);
+                    }
+                    _ => "JoinGroup from a device which isn't the contact's or mine",
+                }
+            }
+            _ => return Ok(true),
+        };
+        tracing::warn!(?topic, author = ?author.renamed(), "{reason}, ignoring");
+        Ok(false)
     }
 
     #[cfg_attr(feature = "instrument", tracing::instrument(skip_all, fields(me=?self.device_id().renamed())))]
@@ -205,21 +422,64 @@ impl Node {
         // topic: Topic<K>,
         header: &Header,
         payload: Option<&Payload>,
-        _is_author: bool,
+        is_author: bool,
     ) -> anyhow::Result<()> {
         let topic = header.extensions.topic;
+
+        // Until this device is linked, only the device linking it writes
+        // to the device group of the agent it's joining for it.
+        if let Some(Payload::DeviceGroup(device_group_payload)) = payload {
+            let author = DeviceId::from(header.public_key);
+            if let Some((agent_id, linking_device)) = self.local_store.pending_link()?
+                && author == linking_device
+                && self
+                    .device_group_topics(agent_id)?
+                    .into_iter()
+                    .any(|device_group| TopicId::from(device_group) == topic)
+            {
+                match device_group_payload {
+                    DeviceGroupPayload::AddDevice(device) if *device == self.device_id() => {
+                        // The device I asked to link me approved it.
+                        self.spawn_complete_link();
+                        return Ok(());
+                    }
+                    DeviceGroupPayload::MoveDeviceGroup(to) => {
+                        // It told me where its device group moved to, before adding me there.
+                        return self.move_device_group(agent_id, *to).await;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Index the operation first, so that handling it below can still amend the index,
+        // like removing a message whose deletion already arrived.
+        if let Some(payload) = payload {
+            if !self.accepts_payload(header, payload).await? {
+                return Ok(());
+            }
+            self.read_model.apply(header, payload)?;
+        }
+
         // TODO: maybe have different loops for the different kinds of topics and the different payloads in each
         match &payload {
-            Some(Payload::Chat(ChatPayload::JoinGroup(_chat_id))) => {
-                // TODO: maybe close down the chat tasks if we are kicked out?
+            Some(Payload::Chat(ChatPayload::JoinGroup(chat_id))) => {
+                let author = DeviceId::from(header.public_key);
+                let Some(inviter) = self.direct_chat_contact(topic).await? else {
+                    return Ok(());
+                };
+                if is_author || self.device_agents().await?.get(&author) != Some(&inviter) {
+                    // I or another of my devices sent this invitation.
+                    return Ok(());
+                }
+                self.receive_group_invitation(GroupInvitation {
+                    chat_id: *chat_id,
+                    inviter,
+                })
+                .await?;
             }
 
             Some(Payload::Inbox(invitation)) => {
-                let active_topics = self.local_store.get_active_inbox_topics()?;
-                if !active_topics.iter().any(|it| **it.topic == *topic) {
-                    // not for me, ignore
-                    return Ok(());
-                }
                 tracing::info!(
                     ?invitation,
                     from = ?header.public_key.renamed(),
@@ -229,25 +489,170 @@ impl Node {
                     InboxPayload::ContactRequest { .. } => {
                         // Nothing to do.
                     }
+                    InboxPayload::DeviceRequest { .. } => {
+                        let device = DeviceId::from(header.public_key);
+                        if is_author || device == self.device_id() {
+                            return Ok(());
+                        }
+                        tracing::info!(
+                            device = ?device.renamed(),
+                            "device asked to be linked, waiting for approval"
+                        );
+                    }
                 }
             }
 
-            Some(Payload::Chat(ChatPayload::Message(_) | ChatPayload::Reaction(_))) => {
+            Some(Payload::Chat(ChatPayload::Message(_) | ChatPayload::Attachment { .. })) => {
+                self.prune_deleted_message(topic, header.hash()).await?;
+                if !is_author && DeviceId::from(header.public_key) != self.device_id() {
+                    self.receipt_tx
+                        .send((ChatId::new(*topic), header.clone()))
+                        .await
+                        .map_err(|_| anyhow::anyhow!("receipt channel closed"))?;
+                }
+            }
+
+            Some(Payload::Chat(ChatPayload::Edit { target, .. })) => {
+                self.prune_deleted_message(topic, *target).await?;
+            }
+
+            Some(Payload::Chat(ChatPayload::Delete { target })) => {
+                self.local_store
+                    .add_message_deleter(topic, target, header.public_key.into())?;
+                self.prune_deleted_message(topic, *target).await?;
+            }
+
+            Some(Payload::Chat(ChatPayload::LeaveGroup(agent_id))) => {
+                // Another of my devices left the group chat.
+                let device = DeviceId::from(header.public_key);
+                if *agent_id == self.agent_id()
+                    && !is_author
+                    && device != self.device_id()
+                    && self.my_devices().await?.contains(&device)
+                {
+                    self.unsubscribe_topic(ChatId::new(*topic)).await?;
+                }
+            }
+
+            Some(Payload::Chat(
+                ChatPayload::AddMember { .. } | ChatPayload::Reaction(_) | ChatPayload::Receipt(_),
+            )) => {
                 // Nothing to do.
             }
 
-            Some(Payload::Announcements(_)) => {
+            Some(Payload::Announcements(AnnouncementsPayload::SetProfile(profile))) => {
+                // Only the avatar of a profile which counts is worth fetching,
+                // see [`Node::get_profile`].
+                if let Some(avatar) = profile.avatar
+                    && let Some(agent_id) = self.announcements_agent(topic).await?
+                    && self.get_profile(agent_id).await?.as_ref() == Some(profile)
+                {
+                    self.spawn_avatar_download(avatar);
+                }
+            }
+
+            Some(Payload::Announcements(AnnouncementsPayload::AddDevice(_))) => {
                 // Nothing to do.
             }
 
+            Some(Payload::Announcements(AnnouncementsPayload::KeyBundle(_))) => {
+                // Another of my devices can be sealed for from now on,
+                // but what was written in my direct chats before wasn't sealed for it.
+                let author = DeviceId::from(header.public_key);
+                if topic == TopicId::from(Topic::announcements(self.agent_id()))
+                    && !is_author
+                    && author != self.device_id()
+                    && self.my_devices().await?.contains(&author)
+                {
+                    Box::pin(self.backfill_direct_chats()).await?;
+                }
+            }
+
+            Some(Payload::Announcements(AnnouncementsPayload::RemoveDevice { device, up_to })) => {
+                let Some(agent_id) = self.announcements_agent(topic).await? else {
+                    tracing::warn!(
+                        ?topic,
+                        "RemoveDevice outside of the announcements of me or a contact, ignoring"
+                    );
+                    return Ok(());
+                };
+                self.record_revocation(agent_id, header, *device, up_to)
+                    .await?;
+            }
+
+            // Another of my devices added a contact or joined a group chat,
+            // so this device follows them too.
+            Some(Payload::DeviceGroup(
+                DeviceGroupPayload::AddContact(_) | DeviceGroupPayload::JoinGroup(_),
+            )) if is_author || DeviceId::from(header.public_key) == self.device_id() => {
+                // I already did this when authoring it.
+            }
+
+            Some(Payload::DeviceGroup(DeviceGroupPayload::AddContact(contact))) => {
+                let agent_id = contact.agent_id;
+                self.subscribe_topic(
+                    Topic::announcements(agent_id),
+                    SubscribedTopic::Announcements(agent_id),
+                )
+                .await?;
+                self.subscribe_topic(
+                    self.direct_chat_topic(agent_id),
+                    SubscribedTopic::DirectChat(agent_id),
+                )
+                .await?;
+            }
+
+            Some(Payload::DeviceGroup(DeviceGroupPayload::JoinGroup(chat_id))) => {
+                if !self.get_group_chats()?.contains(chat_id) {
+                    self.join_group(*chat_id).await?;
+                }
+            }
+
+            Some(Payload::DeviceGroup(DeviceGroupPayload::RemoveContact(agent_id))) => {
+                self.unsubscribe_topic(Topic::announcements(*agent_id))
+                    .await?;
+                self.unsubscribe_topic(self.direct_chat_topic(*agent_id))
+                    .await?;
+            }
+
+            Some(Payload::DeviceGroup(DeviceGroupPayload::RemoveDevice { device, up_to })) => {
+                self.record_revocation(self.agent_id(), header, *device, up_to)
+                    .await?;
+            }
+
+            Some(Payload::DeviceGroup(DeviceGroupPayload::MoveDeviceGroup(to))) => {
+                self.move_device_group(self.agent_id(), *to).await?;
+            }
+
+            Some(Payload::DeviceGroup(DeviceGroupPayload::Backfill { op, payload })) => {
+                self.receive_backfill(*op, payload.clone()).await?;
+            }
+
             Some(Payload::DeviceGroup(_)) => {
                 // Nothing to do.
             }
 
+            Some(Payload::Sealed(_)) => {
+                tracing::warn!(?topic, "sealed payload which wasn't opened, ignoring");
+            }
+
             None => {
                 tracing::error!(?topic, "no payload");
             }
         }
+
+        // I may have just learned which agent a device acts for.
+        if matches!(
+            payload,
+            Some(
+                Payload::Announcements(AnnouncementsPayload::AddDevice(_))
+                    | Payload::DeviceGroup(
+                        DeviceGroupPayload::AddContact(_) | DeviceGroupPayload::AddDevice(_)
+                    )
+            )
+        ) {
+            self.retry_pending_deletions().await?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,75 @@
+use crate::read_model::IndexedContent;
+use crate::{ChatSummary, HistoryQuery, MessagePreview};
+
+use super::*;
+
+impl Node {
+    /// The direct chats with my contacts and the group chats I'm in,
+    /// with the most recently active first.
+    pub async fn chat_summaries(&self) -> anyhow::Result<Vec<ChatSummary>> {
+        let device_agents = self.device_agents().await?;
+        let last_activity = self
+            .read_model
+            .chats()?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        let device_group = self
+            .device_group_topics(self.agent_id())?
+            .into_iter()
+            .map(TopicId::from)
+            .collect::<Vec<_>>();
+        let mut summaries = vec![];
+        for (topic, subscription) in self.local_store.get_subscribed_topics()? {
+            if !matches!(
+                subscription,
+                SubscribedTopic::DirectChat(_) | SubscribedTopic::GroupChat
+            ) {
+                continue;
+            }
+            let chat_id = ChatId::new(*topic);
+
+            let unread = self
+                .read_model
+                .unread_authors(&device_group, chat_id)?
+                .into_iter()
+                .filter(|device| device_agents.get(device) != Some(&self.agent_id()))
+                .count();
+
+            let query = HistoryQuery {
+                limit: 1,
+                ..Default::default()
+            };
+            let (latest, _) = self.read_model.messages(chat_id, &query)?;
+            let last_message = match latest.into_iter().next() {
+                Some(message) => {
+                    let excerpt = match (&message.content, message.text_version()) {
+                        (IndexedContent::Text(_), Some(original)) => self
+                            .apply_edits(chat_id, original, &device_agents)?
+                            .content
+                            .excerpt(),
+                        (IndexedContent::Text(content), None) => content.excerpt(),
+                        (IndexedContent::Attachment(attachment), _) => attachment.name.clone(),
+                    };
+                    Some(MessagePreview {
+                        hash: message.hash,
+                        author: device_agents.get(&message.device).copied(),
+                        device: message.device,
+                        excerpt,
+                    })
+                }
+                None => None,
+            };
+
+            summaries.push(ChatSummary {
+                chat_id,
+                unread,
+                last_message,
+                last_activity: last_activity.get(&chat_id).copied(),
+            });
+        }
+
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.last_activity));
+        Ok(summaries)
+    }
+}
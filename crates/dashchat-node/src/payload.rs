@@ -1,12 +1,13 @@
 use named_id::{RenameAll, RenameNone};
 use p2panda_core::cbor::{DecodeError, EncodeError, decode_cbor, encode_cbor};
-use p2panda_core::{Body, Extension, PruneFlag};
+use p2panda_core::{Body, Extension, Hash, PruneFlag};
 use serde::{Deserialize, Serialize};
 
-use crate::chat::ChatId;
+use crate::chat::{ChatId, DeviceGroupId};
 use crate::contact::QrCode;
+use crate::encryption::{KeyBundle, SealedPayload};
 use crate::topic::TopicId;
-use crate::{AgentId, AsBody, Cbor, ChatMessageContent, ChatReaction, Topic};
+use crate::{AgentId, AsBody, Cbor, ChatMessageContent, ChatReaction, DeviceId, Topic};
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Extensions {
@@ -22,20 +23,52 @@ impl Extensions {
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameNone)]
 pub struct Profile {
     pub name: String,
-    pub avatar: Option<String>,
+    pub avatar: Option<Avatar>,
+}
+
+/// A thumbnail image, which is transferred like the files of attachments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, RenameNone)]
+pub struct Avatar {
+    /// The blake3 hash of the image.
+    pub hash: Hash,
+    /// The size of the image in bytes.
+    pub size: u64,
+}
+
+/// The seq_num of the latest operation of a device in a topic which some device had seen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, RenameNone)]
+pub struct LogHeight {
+    pub topic: TopicId,
+    pub seq_num: u64,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
 #[serde(tag = "type", content = "payload")]
 pub enum AnnouncementsPayload {
     SetProfile(Profile),
+    /// Another device now acts for the agent, so its operations are the agent's.
+    AddDevice(DeviceId),
+    /// A device no longer acts for the agent. Only its operations up to the given
+    /// position in each of its logs still count, see [`crate::Revocation`].
+    RemoveDevice {
+        device: DeviceId,
+        up_to: Vec<LogHeight>,
+    },
+    /// The signed keys which payloads for the authoring device are sealed to.
+    /// Only valid from a device which acts for the agent.
+    KeyBundle(KeyBundle),
 }
 
+/// Only valid sealed to the inbox key of the code which named the inbox.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
 #[serde(tag = "type", content = "payload")]
 pub enum InboxPayload {
     /// Invites the recipient to add the sender as a contact.
     ContactRequest { code: QrCode, profile: Profile },
+    /// Asks the device which showed an AddDevice code to link the sender to its agent.
+    /// Carries the key of the sender, so that it can be told where my device group
+    /// moved to before it's linked, see [`DeviceGroupPayload::MoveDeviceGroup`].
+    DeviceRequest { key_bundle: KeyBundle },
 }
 
 // TODO: consolidate into something else
@@ -52,9 +85,80 @@ pub enum ChatPayload {
     /// long-lasting, so using an Inbox is not an option.
     JoinGroup(ChatId),
 
+    /// Records that an agent is a member of this group chat,
+    /// along with the devices of the agent which the adding device knows of,
+    /// so that members who aren't contacts can tell which agent a device acts for.
+    /// The creator of a group adds themselves first, and then
+    /// adds each contact they invite.
+    /// Only valid in group chats, when authored by a device of a member,
+    /// or by one of the listed devices when it's the first member.
+    AddMember {
+        agent_id: AgentId,
+        devices: Vec<DeviceId>,
+    },
+
+    /// The given agent has left the group chat, and no longer receives its messages.
+    /// Authored by the leaving agent, so that the remaining members stop
+    /// counting them as a member.
+    /// Only valid in group chats, when authored by a device of the leaving agent.
+    LeaveGroup(AgentId),
+
     Message(ChatMessageContent),
 
+    /// A file shared in the chat. Only its metadata is part of the operation:
+    /// the content is transferred separately, in chunks through the mailboxes.
+    Attachment {
+        /// The blake3 hash of the file's content.
+        hash: Hash,
+        /// The size of the file in bytes.
+        size: u64,
+        mime: String,
+        name: String,
+    },
+
+    /// Replaces the content of an earlier message.
+    /// Only valid when authored by a device of the same agent as the target message,
+    /// otherwise it is ignored.
+    Edit {
+        /// The hash of the header of the message being edited.
+        /// Always the original message, never another edit.
+        target: Hash,
+        content: ChatMessageContent,
+    },
+
+    /// Deletes an earlier message for everyone: the bodies of the message and
+    /// its edits are removed from the store, only their headers are kept.
+    /// Only valid when authored by a device of the same agent as the target message,
+    /// otherwise it is ignored.
+    Delete {
+        /// The hash of the header of the message being deleted.
+        target: Hash,
+    },
+
     Reaction(ChatReaction),
+
+    /// Acknowledges messages in this chat. Receipts are never acknowledged themselves.
+    Receipt(Receipt),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+#[serde(tag = "type", content = "payload")]
+pub enum Receipt {
+    /// The authoring device has received all messages up to and including the message
+    /// with this header hash. Sent automatically as messages from other devices arrive.
+    Delivered { up_to: Hash },
+    /// The agent of the authoring device has read all messages up to and including
+    /// the message with this header hash.
+    Read { up_to: Hash },
+}
+
+/// Chat activity which is only relayed to the members who are online right now.
+/// It's never written to a log or stored by a mailbox.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, RenameAll)]
+#[serde(tag = "type", content = "payload")]
+pub enum EphemeralPayload {
+    /// The sender is typing a message.
+    Typing,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, RenameAll)]
@@ -62,6 +166,35 @@ pub enum ChatPayload {
 pub enum DeviceGroupPayload {
     AddContact(QrCode),
     RejectContactRequest(AgentId),
+    /// Tombstone for a contact added earlier.
+    /// The contact can be added again with a later AddContact.
+    RemoveContact(AgentId),
+    /// I have read all messages of a chat up to and including the message
+    /// with this header hash, on any of my devices.
+    MarkRead {
+        chat_id: ChatId,
+        up_to: Hash,
+    },
+    /// A device was linked to my agent.
+    AddDevice(DeviceId),
+    /// A device was revoked by another of my devices. Only its operations up to
+    /// the given position in each of its logs still count, see [`crate::Revocation`].
+    RemoveDevice {
+        device: DeviceId,
+        up_to: Vec<LogHeight>,
+    },
+    /// My device group continues on a new topic, which a revoked device doesn't know.
+    /// Only valid sealed for my remaining devices, in the topic the device group moves from.
+    MoveDeviceGroup(DeviceGroupId),
+    /// The payload of a direct chat operation which wasn't sealed for some of my devices,
+    /// for example because they were linked after it was written, opened for them by
+    /// a device it was sealed for. Only valid sealed for the devices it's for.
+    Backfill {
+        op: Hash,
+        payload: ChatPayload,
+    },
+    /// I joined a group chat, so my other devices should join it too.
+    JoinGroup(ChatId),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, RenameAll)]
@@ -79,6 +212,11 @@ pub enum Payload {
     /// Data only seen within your private device group.
     /// No other person sees these.
     DeviceGroup(DeviceGroupPayload),
+
+    /// Another payload, encrypted for the devices which may read it.
+    /// Direct chat payloads are always sealed, and so are inbox payloads,
+    /// to the ephemeral key of the inbox, except those sent by older nodes.
+    Sealed(SealedPayload),
 }
 
 impl Cbor for Payload {}
@@ -0,0 +1,814 @@
+//! A materialized view of the processed operations, so that reads don't have
+//! to decode every body in every author's log.
+//!
+//! It's updated as each operation is processed. Updates don't depend on the order
+//! in which operations arrive, and applying an operation twice changes nothing,
+//! so the whole model can be rebuilt from the op store at any time.
+//!
+//! Validation which depends on what else is known, like whether an edit was made
+//! by a device of the message's author, happens when reading.
+
+use std::collections::{BTreeSet, HashSet};
+use std::ops::Bound;
+use std::sync::Arc;
+
+use p2panda_core::{Hash, PublicKey};
+use redb::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AnnouncementsPayload, Attachment, Cbor, ChatId, ChatMessageContent, ChatPayload,
+    DeviceGroupPayload, DeviceId, Header, HistoryQuery, KeyBundle, MessageVersion, Payload,
+    Profile, chat::tokenize, topic::TopicId,
+};
+
+/// Bump this whenever the tables or their encoding change,
+/// so that the model is rebuilt when the node starts.
+const VERSION: u64 = 12;
+const VERSION_KEY: &str = "version";
+
+const META_TABLE: TableDefinition<&'static str, u64> = TableDefinition::new("read_model_meta");
+/// Chat ID -> timestamp of the latest message
+const CHATS_TABLE: TableDefinition<[u8; 32], u64> = TableDefinition::new("chats");
+/// (chat ID, timestamp, device, seq num) -> CBOR-encoded IndexedMessage
+const MESSAGES_TABLE: TableDefinition<MessageKey, &'static [u8]> = TableDefinition::new("messages");
+/// Message hash -> key of the message in the messages table
+const MESSAGE_KEYS_TABLE: TableDefinition<[u8; 32], MessageKey> =
+    TableDefinition::new("message_keys");
+/// (chat ID, message hash) -> CBOR-encoded IndexedEdits of the message written in the chat,
+/// by any device
+const EDITS_TABLE: MultimapTableDefinition<([u8; 32], [u8; 32]), &'static [u8]> =
+    MultimapTableDefinition::new("edits");
+/// (chat ID, message hash, device) -> CBOR-encoded latest IndexedReaction of the device
+const REACTIONS_TABLE: TableDefinition<([u8; 32], [u8; 32], [u8; 32]), &'static [u8]> =
+    TableDefinition::new("reactions");
+/// Normalized word -> hashes of the messages with the word in any of their versions
+const SEARCH_TABLE: MultimapTableDefinition<&'static str, [u8; 32]> =
+    MultimapTableDefinition::new("search");
+/// (device group topic ID, chat ID, device) -> key of the latest message the device
+/// marked as read
+const READ_MARKERS_TABLE: TableDefinition<ReadMarker, MessageKey> =
+    TableDefinition::new("read_markers");
+/// Message hash -> (device group topic ID, chat ID, device, timestamp of the marker)
+/// of read markers for the message, which wait for the message to arrive
+const PENDING_READ_MARKERS_TABLE: MultimapTableDefinition<
+    [u8; 32],
+    ([u8; 32], [u8; 32], [u8; 32], u64),
+> = MultimapTableDefinition::new("pending_read_markers");
+/// How long a read marker waits for its message, in seconds.
+/// The message may never arrive, for example because it was deleted before it was synced.
+const PENDING_READ_MARKER_EXPIRY: u64 = 30 * 24 * 60 * 60;
+/// Announcements topic ID -> (announcing device, device which was announced to act for the agent)
+const DEVICES_TABLE: MultimapTableDefinition<[u8; 32], ([u8; 32], [u8; 32])> =
+    MultimapTableDefinition::new("devices");
+/// (announcements topic ID, device) -> CBOR-encoded latest IndexedKeyBundle which the device
+/// published
+const KEY_BUNDLES_TABLE: TableDefinition<([u8; 32], [u8; 32]), &'static [u8]> =
+    TableDefinition::new("key_bundles");
+/// (announcements topic ID, device) -> CBOR-encoded latest IndexedProfile which the device wrote
+const PROFILES_TABLE: TableDefinition<([u8; 32], [u8; 32]), &'static [u8]> =
+    TableDefinition::new("profiles");
+
+/// Messages are ordered by timestamp, with the author and sequence number
+/// breaking ties, so that every node shows them in the same order.
+type MessageKey = ([u8; 32], u64, [u8; 32], u64);
+
+/// (device group topic ID, chat ID, device)
+type ReadMarker = ([u8; 32], [u8; 32], [u8; 32]);
+
+fn message_key(header: &Header) -> MessageKey {
+    (
+        *header.extensions.topic,
+        header.timestamp,
+        *header.public_key.as_bytes(),
+        header.seq_num,
+    )
+}
+
+/// A message as it was written, before edits and reactions are applied.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedMessage {
+    /// The hash of the header of the message.
+    pub hash: Hash,
+    pub device: DeviceId,
+    pub timestamp: u64,
+    pub content: IndexedContent,
+}
+
+impl Cbor for IndexedMessage {}
+
+impl IndexedMessage {
+    /// The original version of a text message, before edits are applied.
+    /// None for other messages.
+    pub fn text_version(&self) -> Option<MessageVersion> {
+        let IndexedContent::Text(content) = &self.content else {
+            return None;
+        };
+        Some(MessageVersion {
+            content: content.clone(),
+            author: self.device,
+            timestamp: self.timestamp,
+            hash: self.hash,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndexedContent {
+    Text(ChatMessageContent),
+    Attachment(Attachment),
+}
+
+/// An edit of a message, which may or may not be valid.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedEdit {
+    pub version: MessageVersion,
+    pub seq_num: u64,
+}
+
+impl Cbor for IndexedEdit {}
+
+/// The latest reaction of a device to a message.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedReaction {
+    pub emoji: Option<String>,
+    pub timestamp: u64,
+    pub seq_num: u64,
+}
+
+impl Cbor for IndexedReaction {}
+
+/// The latest key bundle a device published, which replaces its earlier ones
+/// as its prekey is rotated.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct IndexedKeyBundle {
+    bundle: KeyBundle,
+    seq_num: u64,
+}
+
+impl Cbor for IndexedKeyBundle {}
+
+/// The latest profile a device wrote in an announcements topic.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedProfile {
+    pub profile: Profile,
+    pub timestamp: u64,
+    pub device: DeviceId,
+    pub seq_num: u64,
+}
+
+impl Cbor for IndexedProfile {}
+
+#[derive(Clone)]
+pub struct ReadModel {
+    db: Arc<Database>,
+}
+
+impl ReadModel {
+    /// The tables live in the given database, next to other tables.
+    ///
+    /// Tables from another version are dropped, since their types may have changed,
+    /// which leaves the model to be rebuilt.
+    pub fn new(db: Arc<Database>) -> anyhow::Result<Self> {
+        let txn = db.begin_write()?;
+        let version = txn
+            .open_table(META_TABLE)?
+            .get(VERSION_KEY)?
+            .map(|v| v.value());
+        if version != Some(VERSION) {
+            delete_tables(&txn)?;
+        }
+        open_tables(&txn)?;
+        txn.commit()?;
+        Ok(Self { db })
+    }
+
+    /// Whether the model was built with the current version of the tables.
+    /// If not, it needs to be rebuilt.
+    pub fn is_current(&self) -> anyhow::Result<bool> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(META_TABLE)?;
+        Ok(table.get(VERSION_KEY)?.map(|v| v.value()) == Some(VERSION))
+    }
+
+    /// Empty all tables, and mark the model as current.
+    /// Operations must be applied again afterwards.
+    pub fn clear(&self) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        delete_tables(&txn)?;
+        open_tables(&txn)?;
+        txn.open_table(META_TABLE)?.insert(VERSION_KEY, VERSION)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Update the model with a processed operation.
+    /// Only operations which the node accepted are applied, so this doesn't check
+    /// where they were written or by whom.
+    pub fn apply(&self, header: &Header, payload: &Payload) -> anyhow::Result<()> {
+        match payload {
+            Payload::Chat(ChatPayload::Message(content)) => {
+                self.insert_message(header, IndexedContent::Text(content.clone()))
+            }
+            Payload::Chat(ChatPayload::Attachment {
+                hash,
+                size,
+                mime,
+                name,
+            }) => self.insert_message(
+                header,
+                IndexedContent::Attachment(Attachment {
+                    hash: *hash,
+                    size: *size,
+                    mime: mime.clone(),
+                    name: name.clone(),
+                }),
+            ),
+            Payload::Chat(ChatPayload::Edit { target, content }) => {
+                let edit = IndexedEdit {
+                    version: MessageVersion::new(content.clone(), header),
+                    seq_num: header.seq_num,
+                };
+                let txn = self.db.begin_write()?;
+                {
+                    let mut table = txn.open_multimap_table(EDITS_TABLE)?;
+                    table.insert(
+                        (*header.extensions.topic, *target.as_bytes()),
+                        edit.as_bytes()?.as_slice(),
+                    )?;
+                    let mut search = txn.open_multimap_table(SEARCH_TABLE)?;
+                    for word in tokenize(&content.text) {
+                        search.insert(word.as_str(), *target.as_bytes())?;
+                    }
+                }
+                txn.commit()?;
+                Ok(())
+            }
+            Payload::Chat(ChatPayload::Reaction(reaction)) => {
+                let new = IndexedReaction {
+                    emoji: reaction.emoji.clone(),
+                    timestamp: header.timestamp,
+                    seq_num: header.seq_num,
+                };
+                let key = (
+                    *header.extensions.topic,
+                    *reaction.target.as_bytes(),
+                    *header.public_key.as_bytes(),
+                );
+                let txn = self.db.begin_write()?;
+                {
+                    let mut table = txn.open_table(REACTIONS_TABLE)?;
+                    let old = table
+                        .get(key)?
+                        .map(|v| IndexedReaction::from_bytes(v.value()))
+                        .transpose()?;
+                    if old.is_none_or(|old| {
+                        (old.timestamp, old.seq_num) < (new.timestamp, new.seq_num)
+                    }) {
+                        table.insert(key, new.as_bytes()?.as_slice())?;
+                    }
+                }
+                txn.commit()?;
+                Ok(())
+            }
+            Payload::Announcements(AnnouncementsPayload::SetProfile(profile)) => {
+                let new = IndexedProfile {
+                    profile: profile.clone(),
+                    timestamp: header.timestamp,
+                    device: header.public_key.into(),
+                    seq_num: header.seq_num,
+                };
+                let key = (*header.extensions.topic, *header.public_key.as_bytes());
+                let txn = self.db.begin_write()?;
+                {
+                    let mut table = txn.open_table(PROFILES_TABLE)?;
+                    let old = table
+                        .get(key)?
+                        .map(|v| IndexedProfile::from_bytes(v.value()))
+                        .transpose()?;
+                    if old.is_none_or(|old| {
+                        (old.timestamp, old.seq_num) < (new.timestamp, new.seq_num)
+                    }) {
+                        table.insert(key, new.as_bytes()?.as_slice())?;
+                    }
+                }
+                txn.commit()?;
+                Ok(())
+            }
+            Payload::Announcements(AnnouncementsPayload::AddDevice(device)) => {
+                let txn = self.db.begin_write()?;
+                txn.open_multimap_table(DEVICES_TABLE)?.insert(
+                    *header.extensions.topic,
+                    (*header.public_key.as_bytes(), *device.as_bytes()),
+                )?;
+                txn.commit()?;
+                Ok(())
+            }
+            Payload::Announcements(AnnouncementsPayload::KeyBundle(bundle)) => {
+                let new = IndexedKeyBundle {
+                    bundle: bundle.clone(),
+                    seq_num: header.seq_num,
+                };
+                let key = (*header.extensions.topic, *header.public_key.as_bytes());
+                let txn = self.db.begin_write()?;
+                {
+                    let mut table = txn.open_table(KEY_BUNDLES_TABLE)?;
+                    let old = table
+                        .get(key)?
+                        .map(|v| IndexedKeyBundle::from_bytes(v.value()))
+                        .transpose()?;
+                    if old.is_none_or(|old| old.seq_num < new.seq_num) {
+                        table.insert(key, new.as_bytes()?.as_slice())?;
+                    }
+                }
+                txn.commit()?;
+                Ok(())
+            }
+            Payload::DeviceGroup(DeviceGroupPayload::MarkRead { chat_id, up_to }) => {
+                let marker = (
+                    *header.extensions.topic,
+                    **chat_id,
+                    *header.public_key.as_bytes(),
+                );
+                let txn = self.db.begin_write()?;
+                {
+                    let keys = txn.open_table(MESSAGE_KEYS_TABLE)?;
+                    match keys.get(*up_to.as_bytes())?.map(|v| v.value()) {
+                        Some(key) => advance_read_marker(&txn, marker, key)?,
+                        None => {
+                            txn.open_multimap_table(PENDING_READ_MARKERS_TABLE)?
+                                .insert(
+                                    *up_to.as_bytes(),
+                                    (marker.0, marker.1, marker.2, header.timestamp),
+                                )?;
+                        }
+                    }
+                }
+                txn.commit()?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn insert_message(&self, header: &Header, content: IndexedContent) -> anyhow::Result<()> {
+        let key = message_key(header);
+        let message = IndexedMessage {
+            hash: header.hash(),
+            device: header.public_key.into(),
+            timestamp: header.timestamp,
+            content,
+        };
+        let txn = self.db.begin_write()?;
+        {
+            let mut messages = txn.open_table(MESSAGES_TABLE)?;
+            messages.insert(key, message.as_bytes()?.as_slice())?;
+            let mut keys = txn.open_table(MESSAGE_KEYS_TABLE)?;
+            keys.insert(*message.hash.as_bytes(), key)?;
+            let mut chats = txn.open_table(CHATS_TABLE)?;
+            let last_activity = chats.get(key.0)?.map(|v| v.value()).unwrap_or(0);
+            chats.insert(key.0, last_activity.max(header.timestamp))?;
+            if let IndexedContent::Text(content) = &message.content {
+                let mut search = txn.open_multimap_table(SEARCH_TABLE)?;
+                for word in tokenize(&content.text) {
+                    search.insert(word.as_str(), *message.hash.as_bytes())?;
+                }
+            }
+            let mut pending = txn.open_multimap_table(PENDING_READ_MARKERS_TABLE)?;
+            for marker in pending.remove_all(*message.hash.as_bytes())? {
+                let (device_group, chat, device, _) = marker?.value();
+                advance_read_marker(&txn, (device_group, chat, device), key)?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Forget a deleted message of a chat and its edits.
+    pub fn remove_message(&self, chat_id: ChatId, hash: Hash) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        remove_message(&txn, chat_id, hash)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Undo what applying an operation did, for an operation which no longer counts,
+    /// like one of a revoked device.
+    ///
+    /// Where only the latest operation of a device is kept, like its reaction to a message,
+    /// its entry is removed whichever operation it came from. The operations of the device
+    /// which still count have to be applied again afterwards.
+    pub fn remove_operation(&self, header: &Header, payload: &Payload) -> anyhow::Result<()> {
+        let topic = *header.extensions.topic;
+        let device = *header.public_key.as_bytes();
+        let txn = self.db.begin_write()?;
+        match payload {
+            Payload::Chat(ChatPayload::Message(_) | ChatPayload::Attachment { .. }) => {
+                remove_message(&txn, ChatId::new(topic), header.hash())?;
+            }
+            Payload::Chat(ChatPayload::Edit { target, content }) => {
+                let edit = IndexedEdit {
+                    version: MessageVersion::new(content.clone(), header),
+                    seq_num: header.seq_num,
+                };
+                txn.open_multimap_table(EDITS_TABLE)?
+                    .remove((topic, *target.as_bytes()), edit.as_bytes()?.as_slice())?;
+            }
+            Payload::Chat(ChatPayload::Reaction(reaction)) => {
+                txn.open_table(REACTIONS_TABLE)?.remove((
+                    topic,
+                    *reaction.target.as_bytes(),
+                    device,
+                ))?;
+            }
+            Payload::Announcements(AnnouncementsPayload::SetProfile(_)) => {
+                txn.open_table(PROFILES_TABLE)?.remove((topic, device))?;
+            }
+            Payload::Announcements(AnnouncementsPayload::AddDevice(added)) => {
+                txn.open_multimap_table(DEVICES_TABLE)?
+                    .remove(topic, (device, *added.as_bytes()))?;
+            }
+            Payload::Announcements(AnnouncementsPayload::KeyBundle(_)) => {
+                txn.open_table(KEY_BUNDLES_TABLE)?.remove((topic, device))?;
+            }
+            Payload::DeviceGroup(DeviceGroupPayload::MarkRead { chat_id, up_to }) => {
+                txn.open_table(READ_MARKERS_TABLE)?
+                    .remove((topic, **chat_id, device))?;
+                txn.open_multimap_table(PENDING_READ_MARKERS_TABLE)?
+                    .remove(
+                        *up_to.as_bytes(),
+                        (topic, **chat_id, device, header.timestamp),
+                    )?;
+            }
+            _ => {}
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Forget the read markers which have waited too long for their message.
+    pub fn prune_pending_read_markers(&self, now: u64) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut pending = txn.open_multimap_table(PENDING_READ_MARKERS_TABLE)?;
+            let mut expired = vec![];
+            for entry in pending.iter()? {
+                let (hash, markers) = entry?;
+                for marker in markers {
+                    let marker = marker?.value();
+                    if marker.3.saturating_add(PENDING_READ_MARKER_EXPIRY) < now {
+                        expired.push((hash.value(), marker));
+                    }
+                }
+            }
+            for (hash, marker) in expired {
+                pending.remove(hash, marker)?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// The devices which wrote the messages of a chat after the latest one
+    /// marked as read by any device in a device group, on any of the topics it was on.
+    pub fn unread_authors(
+        &self,
+        device_group: &[TopicId],
+        chat_id: ChatId,
+    ) -> anyhow::Result<Vec<DeviceId>> {
+        let txn = self.db.begin_read()?;
+        let markers = txn.open_table(READ_MARKERS_TABLE)?;
+        let mut latest = None;
+        for topic in device_group {
+            let (topic, chat) = (**topic, **chat_id);
+            for entry in markers.range((topic, chat, [0; 32])..=(topic, chat, [u8::MAX; 32]))? {
+                latest = latest.max(Some(entry?.1.value()));
+            }
+        }
+        let lower = match latest {
+            Some(key) => Bound::Excluded(key),
+            None => Bound::Included((**chat_id, 0, [0; 32], 0)),
+        };
+        let upper = Bound::Included((**chat_id, u64::MAX, [u8::MAX; 32], u64::MAX));
+        let messages = txn.open_table(MESSAGES_TABLE)?;
+        messages
+            .range::<MessageKey>((lower, upper))?
+            .map(|entry| {
+                let (key, _) = entry?;
+                Ok(DeviceId::from(PublicKey::from_bytes(&key.value().2)?))
+            })
+            .collect()
+    }
+
+    /// The chats with messages, with the timestamp of their latest message.
+    pub fn chats(&self) -> anyhow::Result<Vec<(ChatId, u64)>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(CHATS_TABLE)?;
+        table
+            .iter()?
+            .map(|entry| {
+                let (chat_id, last_activity) = entry?;
+                Ok((ChatId::new(chat_id.value()), last_activity.value()))
+            })
+            .collect()
+    }
+
+    /// A page of messages of a chat, oldest first,
+    /// and whether there are more in the direction of the query.
+    pub fn messages(
+        &self,
+        chat_id: ChatId,
+        query: &HistoryQuery,
+    ) -> anyhow::Result<(Vec<IndexedMessage>, bool)> {
+        let txn = self.db.begin_read()?;
+        let keys = txn.open_table(MESSAGE_KEYS_TABLE)?;
+        let cursor = |hash: Option<Hash>| -> anyhow::Result<Option<MessageKey>> {
+            let Some(hash) = hash else {
+                return Ok(None);
+            };
+            match keys.get(*hash.as_bytes())?.map(|v| v.value()) {
+                Some(key) if key.0 == **chat_id => Ok(Some(key)),
+                _ => anyhow::bail!("no message {hash} in chat {chat_id}"),
+            }
+        };
+        let after = cursor(query.after)?;
+        let before = cursor(query.before)?;
+        if let (Some(after), Some(before)) = (after, before)
+            && after >= before
+        {
+            return Ok((vec![], false));
+        }
+
+        let lower = match after {
+            Some(key) => Bound::Excluded(key),
+            None => Bound::Included((**chat_id, 0, [0; 32], 0)),
+        };
+        let upper = match before {
+            Some(key) => Bound::Excluded(key),
+            None => Bound::Included((**chat_id, u64::MAX, [u8::MAX; 32], u64::MAX)),
+        };
+        let table = txn.open_table(MESSAGES_TABLE)?;
+        let range = table.range::<MessageKey>((lower, upper))?;
+
+        // Take one more than asked for, to know whether there are more.
+        let forward = after.is_some() && before.is_none();
+        let entries = if forward {
+            range.take(query.limit + 1).collect::<Vec<_>>()
+        } else {
+            range.rev().take(query.limit + 1).collect::<Vec<_>>()
+        };
+        let mut messages = entries
+            .into_iter()
+            .map(|entry| Ok(IndexedMessage::from_bytes(entry?.1.value())?))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let has_more = messages.len() > query.limit;
+        messages.truncate(query.limit);
+        if !forward {
+            messages.reverse();
+        }
+        Ok((messages, has_more))
+    }
+
+    pub fn message(&self, chat_id: ChatId, hash: Hash) -> anyhow::Result<Option<IndexedMessage>> {
+        let txn = self.db.begin_read()?;
+        let keys = txn.open_table(MESSAGE_KEYS_TABLE)?;
+        let Some(key) = keys.get(*hash.as_bytes())?.map(|v| v.value()) else {
+            return Ok(None);
+        };
+        if key.0 != **chat_id {
+            return Ok(None);
+        }
+        let messages = txn.open_table(MESSAGES_TABLE)?;
+        messages
+            .get(key)?
+            .map(|v| Ok(IndexedMessage::from_bytes(v.value())?))
+            .transpose()
+    }
+
+    /// All edits of a message by any device, written in the chat of the message,
+    /// in the order they apply in. None if the message isn't in the chat.
+    pub fn edits(&self, chat_id: ChatId, hash: Hash) -> anyhow::Result<Vec<IndexedEdit>> {
+        let txn = self.db.begin_read()?;
+        let keys = txn.open_table(MESSAGE_KEYS_TABLE)?;
+        if keys
+            .get(*hash.as_bytes())?
+            .is_none_or(|key| key.value().0 != **chat_id)
+        {
+            return Ok(vec![]);
+        }
+        let table = txn.open_multimap_table(EDITS_TABLE)?;
+        let mut edits = table
+            .get((**chat_id, *hash.as_bytes()))?
+            .map(|v| Ok(IndexedEdit::from_bytes(v?.value())?))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        edits.sort_by_key(|edit| (edit.version.timestamp, edit.version.author, edit.seq_num));
+        Ok(edits)
+    }
+
+    /// The latest reaction of each device to a message, written in the chat of the message.
+    pub fn reactions(
+        &self,
+        chat_id: ChatId,
+        target: Hash,
+    ) -> anyhow::Result<Vec<(DeviceId, IndexedReaction)>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(REACTIONS_TABLE)?;
+        let (chat, target) = (*chat_id, *target.as_bytes());
+        table
+            .range((chat, target, [0; 32])..=(chat, target, [u8::MAX; 32]))?
+            .map(|entry| {
+                let (key, reaction) = entry?;
+                let device = DeviceId::from(PublicKey::from_bytes(&key.value().2)?);
+                Ok((device, IndexedReaction::from_bytes(reaction.value())?))
+            })
+            .collect()
+    }
+
+    /// The messages with a word starting with each of the tokens, in any of their versions,
+    /// newest first. Whether their latest version still matches has to be checked.
+    pub fn search(
+        &self,
+        tokens: &BTreeSet<String>,
+        chat_id: Option<ChatId>,
+    ) -> anyhow::Result<Vec<(ChatId, IndexedMessage)>> {
+        let txn = self.db.begin_read()?;
+        let search = txn.open_multimap_table(SEARCH_TABLE)?;
+
+        let mut candidates: Option<HashSet<[u8; 32]>> = None;
+        for token in tokens {
+            let mut hashes = HashSet::new();
+            for entry in search.range::<&str>(token.as_str()..)? {
+                let (word, values) = entry?;
+                if !word.value().starts_with(token.as_str()) {
+                    break;
+                }
+                for hash in values {
+                    hashes.insert(hash?.value());
+                }
+            }
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.intersection(&hashes).copied().collect(),
+                None => hashes,
+            });
+        }
+
+        let keys = txn.open_table(MESSAGE_KEYS_TABLE)?;
+        let mut found = vec![];
+        for hash in candidates.unwrap_or_default() {
+            let Some(key) = keys.get(hash)?.map(|v| v.value()) else {
+                continue;
+            };
+            if chat_id.is_none_or(|chat_id| key.0 == **chat_id) {
+                found.push(key);
+            }
+        }
+        found.sort_by_key(|(_, timestamp, device, seq_num)| {
+            std::cmp::Reverse((*timestamp, *device, *seq_num))
+        });
+
+        let messages = txn.open_table(MESSAGES_TABLE)?;
+        let mut results = vec![];
+        for key in found {
+            if let Some(message) = messages.get(key)? {
+                results.push((
+                    ChatId::new(key.0),
+                    IndexedMessage::from_bytes(message.value())?,
+                ));
+            }
+        }
+        Ok(results)
+    }
+
+    /// The devices which were announced in an announcements topic,
+    /// each with the device which announced it.
+    /// Whether the announcing device could do so is up to the caller.
+    pub fn device_additions(
+        &self,
+        announcements: TopicId,
+    ) -> anyhow::Result<Vec<(DeviceId, DeviceId)>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_multimap_table(DEVICES_TABLE)?;
+        table
+            .get(*announcements)?
+            .map(|entry| {
+                let (author, device) = entry?.value();
+                Ok((
+                    DeviceId::from(PublicKey::from_bytes(&author)?),
+                    DeviceId::from(PublicKey::from_bytes(&device)?),
+                ))
+            })
+            .collect()
+    }
+
+    /// The latest key bundle which each device published in an announcements topic.
+    pub fn key_bundles(
+        &self,
+        announcements: TopicId,
+    ) -> anyhow::Result<Vec<(DeviceId, KeyBundle)>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(KEY_BUNDLES_TABLE)?;
+        let topic = *announcements;
+        table
+            .range((topic, [0; 32])..=(topic, [u8::MAX; 32]))?
+            .map(|entry| {
+                let (key, bundle) = entry?;
+                let device = DeviceId::from(PublicKey::from_bytes(&key.value().1)?);
+                Ok((device, IndexedKeyBundle::from_bytes(bundle.value())?.bundle))
+            })
+            .collect()
+    }
+
+    /// The latest profile each device announced in an announcements topic.
+    /// Whether the device could do so is up to the caller.
+    pub fn profiles(&self, announcements: TopicId) -> anyhow::Result<Vec<IndexedProfile>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(PROFILES_TABLE)?;
+        let topic = *announcements;
+        table
+            .range((topic, [0; 32])..=(topic, [u8::MAX; 32]))?
+            .map(|entry| Ok(IndexedProfile::from_bytes(entry?.1.value())?))
+            .collect()
+    }
+}
+
+/// Forget a message of a chat, its edits and the read markers waiting for it,
+/// within a write transaction.
+fn remove_message(txn: &WriteTransaction, chat_id: ChatId, hash: Hash) -> anyhow::Result<()> {
+    txn.open_multimap_table(PENDING_READ_MARKERS_TABLE)?
+        .remove_all(*hash.as_bytes())?;
+    let mut texts = vec![];
+    let mut keys = txn.open_table(MESSAGE_KEYS_TABLE)?;
+    let key = keys.get(*hash.as_bytes())?.map(|v| v.value());
+    if let Some(key) = key
+        && key.0 == **chat_id
+    {
+        keys.remove(*hash.as_bytes())?;
+        let message = txn
+            .open_table(MESSAGES_TABLE)?
+            .remove(key)?
+            .map(|v| IndexedMessage::from_bytes(v.value()))
+            .transpose()?;
+        if let Some(IndexedContent::Text(content)) = message.map(|m| m.content) {
+            texts.push(content.text);
+        }
+    }
+    let mut edits = txn.open_multimap_table(EDITS_TABLE)?;
+    for edit in edits.remove_all((**chat_id, *hash.as_bytes()))? {
+        texts.push(IndexedEdit::from_bytes(edit?.value())?.version.content.text);
+    }
+    let mut search = txn.open_multimap_table(SEARCH_TABLE)?;
+    for word in texts.iter().flat_map(|text| tokenize(text)) {
+        search.remove(word.as_str(), *hash.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Move a read marker forward to a message, unless it's already further along.
+/// Markers for messages of other chats are ignored.
+fn advance_read_marker(
+    txn: &WriteTransaction,
+    marker: ReadMarker,
+    key: MessageKey,
+) -> anyhow::Result<()> {
+    if key.0 != marker.1 {
+        return Ok(());
+    }
+    let mut markers = txn.open_table(READ_MARKERS_TABLE)?;
+    let current = markers.get(marker)?.map(|v| v.value());
+    if current.is_none_or(|current| current < key) {
+        markers.insert(marker, key)?;
+    }
+    Ok(())
+}
+
+fn delete_tables(txn: &WriteTransaction) -> anyhow::Result<()> {
+    txn.delete_table(META_TABLE)?;
+    txn.delete_table(CHATS_TABLE)?;
+    txn.delete_table(MESSAGES_TABLE)?;
+    txn.delete_table(MESSAGE_KEYS_TABLE)?;
+    txn.delete_multimap_table(EDITS_TABLE)?;
+    txn.delete_table(REACTIONS_TABLE)?;
+    txn.delete_table(PROFILES_TABLE)?;
+    txn.delete_multimap_table(SEARCH_TABLE)?;
+    txn.delete_table(READ_MARKERS_TABLE)?;
+    txn.delete_multimap_table(PENDING_READ_MARKERS_TABLE)?;
+    txn.delete_multimap_table(DEVICES_TABLE)?;
+    txn.delete_table(KEY_BUNDLES_TABLE)?;
+    Ok(())
+}
+
+fn open_tables(txn: &WriteTransaction) -> anyhow::Result<()> {
+    let _ = txn.open_table(META_TABLE)?;
+    let _ = txn.open_table(CHATS_TABLE)?;
+    let _ = txn.open_table(MESSAGES_TABLE)?;
+    let _ = txn.open_table(MESSAGE_KEYS_TABLE)?;
+    let _ = txn.open_multimap_table(EDITS_TABLE)?;
+    let _ = txn.open_table(REACTIONS_TABLE)?;
+    let _ = txn.open_table(PROFILES_TABLE)?;
+    let _ = txn.open_multimap_table(SEARCH_TABLE)?;
+    let _ = txn.open_table(READ_MARKERS_TABLE)?;
+    let _ = txn.open_multimap_table(PENDING_READ_MARKERS_TABLE)?;
+    let _ = txn.open_multimap_table(DEVICES_TABLE)?;
+    let _ = txn.open_table(KEY_BUNDLES_TABLE)?;
+    Ok(())
+}
@@ -1,16 +1,15 @@
 use std::{
     collections::{HashMap, HashSet},
+    path::Path,
     sync::{Arc, RwLock},
 };
 
 use p2panda_core::{Body, Hash, Operation, PublicKey, RawOperation};
 use p2panda_store::{LogStore, MemoryStore, OperationStore, SqliteStore};
 use p2panda_stream::operation::IngestResult;
-use rand::distr::Distribution;
 use tokio::sync::Mutex;
 
 use crate::{
-    mailbox::MailboxOperation,
     node::Orderer,
     payload::{Extensions, Payload},
     topic::{Topic, TopicId, TopicKind},
@@ -39,13 +38,9 @@ impl OpStore<MemoryStore<TopicId, Extensions>> {
 }
 
 impl OpStore<SqliteStore<TopicId, Extensions>> {
-    pub async fn new_sqlite() -> anyhow::Result<Self> {
-        let rand = rand::distr::Alphanumeric
-            .sample_iter(&mut rand::rng())
-            .take(10)
-            .map(char::from)
-            .collect::<String>();
-        let filename = format!("/tmp/dashchat-{rand}.db");
+    /// Open the sqlite database at the given path, creating it if necessary.
+    pub async fn new_sqlite(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let filename = path.as_ref().display().to_string();
         let url = format!("sqlite://{filename}");
         p2panda_store::sqlite::store::create_database(&url).await?;
 
@@ -53,12 +48,27 @@ impl OpStore<SqliteStore<TopicId, Extensions>> {
             .await
             .map_err(|e| anyhow::anyhow!("failed to connect to sqlite at '{filename}': {e}"))?;
 
-        if p2panda_store::sqlite::store::run_pending_migrations(&pool)
+        Self::from_pool(pool).await
+    }
+
+    /// A sqlite database which lives only in memory.
+    ///
+    /// A single connection is used, because every new connection to `sqlite::memory:`
+    /// would otherwise get its own empty database.
+    pub async fn new_sqlite_memory() -> anyhow::Result<Self> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
             .await
-            .is_err()
-        {
+            .map_err(|e| anyhow::anyhow!("failed to create in-memory sqlite: {e}"))?;
+
+        Self::from_pool(pool).await
+    }
+
+    async fn from_pool(pool: sqlx::SqlitePool) -> anyhow::Result<Self> {
+        if let Err(err) = p2panda_store::sqlite::store::run_pending_migrations(&pool).await {
             pool.close().await;
-            panic!("Database migration failed");
+            anyhow::bail!("database migration failed: {err}");
         }
         let store = SqliteStore::new(pool);
 
@@ -226,6 +236,54 @@ where
             .insert(hash.clone());
     }
 
+    /// Re-seed the orderer and the processed set with operations which were
+    /// already processed before a restart, so that operations arriving later
+    /// can have their dependencies met.
+    ///
+    /// Operations which aren't found in the store are skipped.
+    pub async fn restore_processed_ops(
+        &self,
+        processed: impl IntoIterator<Item = (TopicId, Hash)>,
+    ) -> anyhow::Result<()> {
+        let operations = self.load_in_causal_order(processed).await?;
+        let restored = operations.len();
+        for operation in operations {
+            let topic = operation.header.extensions.topic;
+            let hash = operation.hash;
+            self.process_ordering(operation).await?;
+            self.mark_op_processed(topic, &hash);
+        }
+
+        // These have all been processed already, so don't hand them out again.
+        let _ = self.next_ordering().await?;
+
+        tracing::info!(restored, "restored processed operations");
+        Ok(())
+    }
+
+    /// Load operations from the store, sorted so that each one comes after
+    /// the ones among them which it depends on.
+    ///
+    /// Operations which aren't found in the store are skipped.
+    pub async fn load_in_causal_order(
+        &self,
+        hashes: impl IntoIterator<Item = (TopicId, Hash)>,
+    ) -> anyhow::Result<Vec<Operation<Extensions>>> {
+        let mut operations = vec![];
+        for (_, hash) in hashes {
+            let Some((header, body)) = self
+                .store
+                .get_operation(hash)
+                .await
+                .map_err(|err| anyhow::anyhow!("failed to get operation {hash}: {err}"))?
+            else {
+                continue;
+            };
+            operations.push(Operation { hash, header, body });
+        }
+        Ok(causal_order(operations))
+    }
+
     pub fn is_op_processed(&self, topic: &TopicId, hash: &Hash) -> bool {
         self.processed_ops
             .read()
@@ -236,61 +294,114 @@ where
     }
 }
 
-impl OpStore<SqliteStore<TopicId, Extensions>> {
-    pub fn report<'a>(&self, _topics: impl IntoIterator<Item = &'a TopicId>) -> String {
-        tracing::warn!("report() not implemented for SqliteStore");
-        format!("report() not implemented for SqliteStore")
+impl<S> OpStore<S>
+where
+    S: OperationStore<TopicId, Extensions> + LogStore<TopicId, Extensions>,
+    S: Send + Sync,
+{
+    /// A listing of the operations in the given topics, for debugging.
+    pub async fn report<'a>(&self, topics: impl IntoIterator<Item = &'a TopicId>) -> String {
+        let topics = topics.into_iter().collect::<Vec<_>>();
+        let mut lines = vec![];
+        for topic in &topics {
+            let mut authors = match self.get_log_heights(topic).await {
+                Ok(heights) => heights
+                    .into_iter()
+                    .map(|(author, _)| author)
+                    .collect::<Vec<_>>(),
+                Err(err) => {
+                    lines.push(format!("• {topic:?}: {err}"));
+                    continue;
+                }
+            };
+            authors.sort();
+            for author in authors {
+                let log = match self.store.get_log(&author, topic, None).await {
+                    Ok(log) => log.unwrap_or_default(),
+                    Err(err) => {
+                        lines.push(format!("• {topic:?} {}: {err}", author.renamed()));
+                        continue;
+                    }
+                };
+                for (header, body) in log {
+                    let desc = match body.clone().map(|body| Payload::try_from_body(&body)) {
+                        Some(Ok(p)) => format!("{p:?}"),
+                        Some(Err(_)) => "<invalid>".to_string(),
+                        None => "_".to_string(),
+                    };
+                    if topics.len() == 1 {
+                        lines.push(format!(
+                            "• {} {:2} {} : {}",
+                            header.public_key.renamed(),
+                            header.seq_num,
+                            header.hash().renamed(),
+                            desc
+                        ));
+                    } else {
+                        let t = format!("{topic:?}");
+                        lines.push(format!(
+                            "• {:>24} {} {:2} {} : {}",
+                            t,
+                            header.public_key.renamed(),
+                            header.seq_num,
+                            header.hash().renamed(),
+                            desc
+                        ));
+                    }
+                }
+            }
+        }
+        lines.join("\n")
     }
 }
 
-impl OpStore<MemoryStore<TopicId, Extensions>> {
-    pub fn report<'a>(&self, topics: impl IntoIterator<Item = &'a TopicId>) -> String {
-        let topics = topics.into_iter().collect::<Vec<_>>();
-        let s = self.store.read_store();
-        let mut ops = s
-            .operations
+/// Sort operations so that each one comes after the ones among them which it
+/// depends on, through its backlink or its previous operations.
+fn causal_order(operations: Vec<Operation<Extensions>>) -> Vec<Operation<Extensions>> {
+    let hashes = operations.iter().map(|op| op.hash).collect::<HashSet<_>>();
+    let mut waiting_on = HashMap::new();
+    let mut dependents: HashMap<Hash, Vec<Hash>> = HashMap::new();
+    let mut ready = vec![];
+    for op in &operations {
+        let deps = op
+            .header
+            .backlink
             .iter()
-            .filter(|(_, (l, _, _, _))| {
-                topics.is_empty() || topics.iter().find(|topic| **topic == l).is_some()
-            })
-            .collect::<Vec<_>>();
-        ops.sort_by_key(|(_, (t, header, _, _))| (t, header.public_key.renamed(), header.seq_num));
-        ops.into_iter()
-            .map(|(h, (t, header, body, _))| {
-                let desc = match body
-                    .clone()
-                    .map(|body| Payload::try_from_body(&body).unwrap())
-                {
-                    // Some(Payload::Space(args)) => {
-                    //     let space_op = GroupOp::new(header.clone(), args);
-                    //     format!("{:?}", space_op.arg_type())
-                    // }
-                    Some(p) => format!("{p:?}"),
-                    None => "_".to_string(),
-                };
-                if topics.len() == 1 {
-                    format!(
-                        "• {} {:2} {} : {}",
-                        header.public_key.renamed(),
-                        header.seq_num,
-                        h.renamed(),
-                        desc
-                    )
-                } else {
-                    let t = format!("{t:?}");
-                    format!(
-                        "• {:>24} {} {:2} {} : {}",
-                        t,
-                        header.public_key.renamed(),
-                        header.seq_num,
-                        h.renamed(),
-                        desc
-                    )
-                }
-            })
-            .collect::<Vec<_>>()
-            .join("\n")
+            .chain(op.header.previous.iter())
+            .filter(|dep| hashes.contains(dep))
+            .collect::<HashSet<_>>();
+        if deps.is_empty() {
+            ready.push(op.hash);
+        } else {
+            waiting_on.insert(op.hash, deps.len());
+        }
+        for dep in deps {
+            dependents.entry(*dep).or_default().push(op.hash);
+        }
     }
+
+    let mut operations = operations
+        .into_iter()
+        .map(|op| (op.hash, op))
+        .collect::<HashMap<_, _>>();
+    let mut ordered = Vec::with_capacity(operations.len());
+    while let Some(hash) = ready.pop() {
+        for dependent in dependents.remove(&hash).unwrap_or_default() {
+            let waiting = waiting_on
+                .get_mut(&dependent)
+                .expect("dependent is waiting");
+            *waiting -= 1;
+            if *waiting == 0 {
+                waiting_on.remove(&dependent);
+                ready.push(dependent);
+            }
+        }
+        ordered.extend(operations.remove(&hash));
+    }
+
+    // Hashes can't form a cycle, but don't lose anything if they somehow do.
+    ordered.extend(operations.into_values());
+    ordered
 }
 
 impl<S> OperationStore<TopicId, Extensions> for OpStore<S>
@@ -417,32 +528,3 @@ where
         self.store.get_log_hashes(public_key, topic, from).await
     }
 }
-
-#[async_trait::async_trait]
-impl<S> mailbox_client::store::MailboxStore<MailboxOperation> for OpStore<S>
-where
-    S: OperationStore<TopicId, Extensions> + LogStore<TopicId, Extensions>,
-    S: Send + Sync + 'static,
-{
-    async fn get_log(
-        &self,
-        author: &DeviceId,
-        topic: &TopicId,
-        from: u64,
-    ) -> Result<Option<Vec<MailboxOperation>>, anyhow::Error> {
-        let log = self
-            .store
-            .get_log(author, topic, Some(from))
-            .await
-            .map_err(|err| anyhow::anyhow!("failed to get log for {author:?}: {topic:?}: {err}"))?;
-        Ok(log.map(|log| {
-            log.into_iter()
-                .map(|(header, body)| MailboxOperation { header, body })
-                .collect()
-        }))
-    }
-
-    async fn get_log_heights(&self, topic: &TopicId) -> anyhow::Result<Vec<(DeviceId, u64)>> {
-        OpStore::get_log_heights(self, topic).await
-    }
-}
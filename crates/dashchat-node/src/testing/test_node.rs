@@ -11,7 +11,7 @@ use tokio::sync::{Mutex, mpsc::Receiver};
 use mailbox_client::{MailboxClient, mem::MemMailbox};
 
 use crate::{
-    AgentId, DeviceGroupPayload, NodeConfig, Notification, Payload, Profile,
+    Event, NodeConfig, Notification, Profile,
     mailbox::MailboxOperation,
     node::{LocalStore, Node},
     testing::behavior::Behavior,
@@ -24,6 +24,8 @@ pub struct TestNode {
     #[deref]
     node: Node,
     pub watcher: Arc<Mutex<Watcher<Notification>>>,
+    /// The events besides processed operations, which go to `watcher`
+    pub events: Arc<Mutex<Watcher<Event>>>,
 
     // store temp directory is deleted when this is dropped
     _store_dir: Arc<TempDir>,
@@ -35,12 +37,30 @@ impl TestNode {
         let dir = tempfile::tempdir().unwrap();
         let path = dir.path().join("store.db");
         let local_store = LocalStore::new(path).unwrap();
+        let (event_tx, mut event_rx) = tokio::sync::mpsc::channel(100);
         let (notification_tx, notification_rx) = tokio::sync::mpsc::channel(100);
+        let (other_tx, other_rx) = tokio::sync::mpsc::channel(100);
+        tokio::spawn(async move {
+            while let Some(event) = event_rx.recv().await {
+                match event {
+                    Event::Notification(notification) => {
+                        if notification_tx.send(notification).await.is_err() {
+                            break;
+                        }
+                    }
+                    // Not every test watches these, so they're dropped rather than
+                    // holding up the node once the channel is full.
+                    event => {
+                        let _ = other_tx.try_send(event);
+                    }
+                }
+            }
+        });
         if config.use_named_id {
             local_store.device_id().unwrap().with_name(name);
             local_store.agent_id().unwrap().with_name(name);
         }
-        let node = Node::new(local_store, config.node_config, Some(notification_tx))
+        let node = Node::new(local_store, config.node_config, Some(event_tx))
             .await
             .unwrap();
         if config.create_profile {
@@ -54,6 +74,7 @@ impl TestNode {
         Self {
             node,
             watcher: Arc::new(Mutex::new(Watcher(notification_rx))),
+            events: Arc::new(Mutex::new(Watcher(other_rx))),
             _store_dir: Arc::new(dir),
         }
     }
@@ -71,35 +92,6 @@ impl TestNode {
         Behavior::new(self.clone())
     }
 
-    pub async fn get_contacts(&self) -> anyhow::Result<Vec<AgentId>> {
-        // FIXME: use all local device IDs
-        let ids = self
-            .get_interleaved_logs(self.device_group_topic().into(), vec![self.device_id()])
-            .await?
-            .into_iter()
-            .filter_map(|(_, payload)| match payload {
-                Some(Payload::DeviceGroup(DeviceGroupPayload::AddContact(qr))) => Some(qr.agent_id),
-                _ => None,
-            })
-            .collect();
-        Ok(ids)
-    }
-
-    pub async fn get_rejected_contact_requests(&self) -> anyhow::Result<Vec<AgentId>> {
-        let ids = self
-            .get_interleaved_logs(self.device_group_topic().into(), vec![self.device_id()])
-            .await?
-            .into_iter()
-            .filter_map(|(_, payload)| match payload {
-                Some(Payload::DeviceGroup(DeviceGroupPayload::RejectContactRequest(agent_id))) => {
-                    Some(agent_id)
-                }
-                _ => None,
-            })
-            .collect();
-        Ok(ids)
-    }
-
     pub async fn subscribed_topics(&self) -> BTreeSet<TopicId> {
         let mailbox_topics = self.mailboxes.subscribed_topics().await;
         mailbox_topics
@@ -216,7 +208,7 @@ pub async fn consistency(
 ) -> anyhow::Result<()> {
     let topics = topics.into_iter().collect::<HashSet<_>>();
     let nodes = nodes.into_iter().collect::<Vec<_>>();
-    wait_for_resetting(config.poll_interval, config.poll_timeout, || async {
+    let result = wait_for_resetting(config.poll_interval, config.poll_timeout, || async {
         // TODO: Fix this when we have a proper way to access operations
         // The operations field is now private in the new p2panda-store version
         let sets = nodes
@@ -253,18 +245,20 @@ pub async fn consistency(
             Err(diffs)
         }
     })
-    .await
-    .map_err(|diffs| {
+    .await;
+
+    if let Err(diffs) = result {
         for n in nodes {
             println!(
                 ">>> {:?}\n{}\n",
                 n.device_id(),
-                n.op_store.report(topics.clone())
+                n.op_store.report(topics.iter().copied()).await
             );
         }
         println!("consistency report: {:#?}", diffs);
-        anyhow::anyhow!("consistency check failed")
-    })
+        anyhow::bail!("consistency check failed");
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, Default, PartialEq)]
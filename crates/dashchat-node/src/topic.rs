@@ -213,12 +213,17 @@ impl Topic<kind::Inbox> {
 }
 
 impl Topic<kind::DeviceGroup> {
-    // TODO: use a random topic stored in LocalStore instead
+    /// The topic a device group starts on. It moves to a random topic
+    /// whenever a device is revoked.
     pub fn device_group(agent_id: AgentId) -> Self {
         let mut hasher = blake3::Hasher::new();
         hasher.update(agent_id.as_bytes());
         Self::new(hasher.finalize().into())
     }
+
+    pub fn random() -> Self {
+        Self::new(rand::random())
+    }
 }
 
 impl Topic<kind::Untyped> {
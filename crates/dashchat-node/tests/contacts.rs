@@ -2,7 +2,7 @@
 
 use std::time::Duration;
 
-use dashchat_node::{testing::*, *};
+use dashchat_node::{testing::*, topic::TopicId, *};
 use mailbox_client::mem::MemMailbox;
 use named_id::*;
 
@@ -71,6 +71,7 @@ async fn test_reject_contact_request() {
     // Verify the rejection was recorded
     let rejected = alice.get_rejected_contact_requests().await.unwrap();
     assert_eq!(rejected, vec![bobbi.agent_id()]);
+    assert_eq!(alice.pending_contact_requests().await.unwrap(), vec![]);
 }
 
 /// Test that multiple contact requests can be rejected independently.
@@ -142,3 +143,91 @@ async fn test_reject_multiple_contact_requests() {
     assert!(rejected.contains(&bobbi.agent_id()));
     assert!(!rejected.contains(&carol.agent_id()));
 }
+
+/// Test that removing a contact drops them from the contacts list
+/// and unsubscribes from their topics.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_remove_contact() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let mailbox = MemMailbox::new();
+    let alice = TestNode::new(NodeConfig::testing(), "alice")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+    let bobbi = TestNode::new(NodeConfig::testing(), "bobbi")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+
+    assert_eq!(alice.get_contacts().await.unwrap(), vec![bobbi.agent_id()]);
+
+    let chat_id = TopicId::from(alice.direct_chat_topic(bobbi.agent_id()));
+    let announcements = TopicId::from(Topic::announcements(bobbi.agent_id()));
+    assert!(alice.subscribed_topics().await.contains(&chat_id));
+
+    alice.remove_contact(bobbi.agent_id()).await.unwrap();
+
+    assert_eq!(alice.get_contacts().await.unwrap(), vec![]);
+    let topics = alice.subscribed_topics().await;
+    assert!(!topics.contains(&chat_id));
+    assert!(!topics.contains(&announcements));
+}
+
+/// Test that a contact request stays pending until it's accepted,
+/// without having to catch the notification for it.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_accept_pending_contact_request() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let mailbox = MemMailbox::new();
+    let alice = TestNode::new(NodeConfig::testing(), "alice")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+    let bobbi = TestNode::new(NodeConfig::testing(), "bobbi")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+
+    let qr = alice
+        .new_qr_code(ShareIntent::AddContact, true)
+        .await
+        .unwrap();
+    let expires_at = qr.inbox_topic.clone().unwrap().expires_at;
+    bobbi.add_contact(qr).await.unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(5),
+        || async {
+            let requests = alice.pending_contact_requests().await.unwrap();
+            (requests.len() == 1).ok_or("no pending request")
+        },
+    )
+    .await
+    .unwrap();
+
+    let request = alice.pending_contact_requests().await.unwrap().remove(0);
+    assert_eq!(request.code.agent_id, bobbi.agent_id());
+    assert_eq!(Some(request.profile), bobbi.my_profile().await.unwrap());
+    assert_eq!(request.expires_at, expires_at);
+
+    alice
+        .accept_contact_request(bobbi.agent_id())
+        .await
+        .unwrap();
+
+    assert_eq!(alice.get_contacts().await.unwrap(), vec![bobbi.agent_id()]);
+    assert_eq!(alice.pending_contact_requests().await.unwrap(), vec![]);
+    assert!(matches!(
+        alice.accept_contact_request(bobbi.agent_id()).await,
+        Err(AddContactError::NoPendingRequest(_))
+    ));
+}
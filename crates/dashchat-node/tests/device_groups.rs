@@ -1,5 +1,7 @@
 #![feature(bool_to_result)]
 
+use std::time::Duration;
+
 use named_id::*;
 
 use dashchat_node::{testing::*, *};
@@ -12,8 +14,44 @@ const TRACING_FILTER: [&str; 4] = [
     "p2panda_spaces=info",
 ];
 
+/// Link a new device to the agent of an existing one, approving it on the existing device
+/// after checking that both show the same verification code.
+async fn link(existing: &TestNode, new: &TestNode) {
+    let code = existing
+        .new_qr_code(ShareIntent::AddDevice, true)
+        .await
+        .unwrap();
+    new.add_contact(code).await.unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let requests = existing.pending_device_requests().await.unwrap();
+            let codes = requests
+                .iter()
+                .map(|r| (r.device, r.verification_code.clone()))
+                .collect::<Vec<_>>();
+            (codes == vec![(new.device_id(), new.verification_code())]).ok_or(codes)
+        },
+    )
+    .await
+    .unwrap();
+    existing.approve_device(new.device_id()).await.unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async { (new.agent_id() == existing.agent_id()).ok_or(new.agent_id()) },
+    )
+    .await
+    .unwrap();
+}
+
+/// A new device scans the AddDevice code of alice's device, and becomes a full peer:
+/// it takes on alice's agent, and gets her contacts, group chats and profile,
+/// and the direct chat messages which were sealed before it was linked.
 #[tokio::test(flavor = "multi_thread")]
-#[ignore = "device groups are not supported yet"]
 async fn device_group_solo() {
     dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
 
@@ -26,25 +64,283 @@ async fn device_group_solo() {
         .await
         .add_mailbox_client(mailbox.client())
         .await;
+    let bobbi = TestNode::new(NodeConfig::testing(), "bobbi")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
 
     println!("nodes:");
     println!("alice: {:?}", alice.device_id().short());
     println!("alicia: {:?}", alicia.device_id().short());
+    println!("bobbi: {:?}", bobbi.device_id().short());
 
     #[cfg(feature = "p2p")]
-    introduce_and_wait([&alice.network, &alicia.network]).await;
+    introduce_and_wait([&alice.network, &alicia.network, &bobbi.network]).await;
 
     println!("peers see each other");
 
     alice
-        .add_contact(
-            alicia
-                .new_qr_code(ShareIntent::AddDevice, true)
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let direct = alice.direct_chat_topic(bobbi.agent_id());
+    let group = alice.create_group_chat().await.unwrap();
+    alice
+        .send_message(group, "before linking".into())
+        .await
+        .unwrap();
+    bobbi
+        .send_message(direct, "sealed before linking".into())
+        .await
+        .unwrap();
+
+    link(&alice, &alicia).await;
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let contacts = alicia.get_contacts().await.unwrap();
+            let groups = alicia.get_group_chats().unwrap();
+            let profile = alicia.my_profile().await.unwrap();
+            let ready = contacts == vec![bobbi.agent_id()]
+                && groups == vec![group]
+                && profile.is_some_and(|p| p.name == "alice");
+            ready.ok_or((contacts, groups))
+        },
+    )
+    .await
+    .unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let page = alicia
+                .message_history(group, HistoryQuery::default())
+                .await
+                .unwrap();
+            (page.messages.len() == 1).ok_or(page.messages.len())
+        },
+    )
+    .await
+    .unwrap();
+
+    // Alice's device backfills what bobbi sealed for it alone.
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let page = alicia
+                .message_history(direct, HistoryQuery::default())
                 .await
-                .unwrap(),
-        )
+                .unwrap();
+            (page.messages.len() == 1).ok_or(page.messages.len())
+        },
+    )
+    .await
+    .unwrap();
+    assert!(
+        alice
+            .my_devices()
+            .await
+            .unwrap()
+            .contains(&alicia.device_id())
+    );
+
+    // Bobbi sees what the new device writes as coming from alice.
+    alicia
+        .send_message(direct, "from my new device".into())
+        .await
+        .unwrap();
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let page = bobbi
+                .message_history(direct, HistoryQuery::default())
+                .await
+                .unwrap();
+            let authors = page.messages.iter().map(|m| m.author).collect::<Vec<_>>();
+            (authors == vec![Some(bobbi.agent_id()), Some(alice.agent_id())]).ok_or(authors)
+        },
+    )
+    .await
+    .unwrap();
+
+    // A device which already has contacts can't be linked.
+    let code = alice
+        .new_qr_code(ShareIntent::AddDevice, true)
         .await
         .unwrap();
+    assert!(bobbi.link_device(code).await.is_err());
+}
+
+/// Alice revokes her linked device alicia: bobbi keeps what alicia wrote before,
+/// and ignores what she writes afterwards.
+#[tokio::test(flavor = "multi_thread")]
+async fn revoke_device() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let mailbox = MemMailbox::new();
+    let alice = TestNode::new(NodeConfig::testing(), "alice")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+    let alicia = TestNode::new(NodeConfig::testing(), "alicia")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+    let bobbi = TestNode::new(NodeConfig::testing(), "bobbi")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+
+    #[cfg(feature = "p2p")]
+    introduce_and_wait([&alice.network, &alicia.network, &bobbi.network]).await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let direct = alice.direct_chat_topic(bobbi.agent_id());
+
+    link(&alice, &alicia).await;
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let contacts = alicia.get_contacts().await.unwrap();
+            (contacts == vec![bobbi.agent_id()]).ok_or(contacts)
+        },
+    )
+    .await
+    .unwrap();
+
+    alicia
+        .send_message(direct, "before revocation".into())
+        .await
+        .unwrap();
+    // Only what alice has seen of alicia when revoking her still counts.
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let mut counts = vec![];
+            for node in [&alice, &bobbi] {
+                let page = node
+                    .message_history(direct, HistoryQuery::default())
+                    .await
+                    .unwrap();
+                counts.push(page.messages.len());
+            }
+            (counts == vec![1, 1]).ok_or(counts)
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(alice.remove_device(alice.device_id()).await.is_err());
+    alice.remove_device(alicia.device_id()).await.unwrap();
+    assert!(
+        !alice
+            .my_devices()
+            .await
+            .unwrap()
+            .contains(&alicia.device_id())
+    );
+    // Alice's device group moved somewhere alicia wasn't told about.
+    assert_ne!(
+        alice.device_group_topic(),
+        Topic::device_group(alice.agent_id())
+    );
+    assert_ne!(alicia.device_group_topic(), alice.device_group_topic());
+
+    alicia
+        .send_message(direct, "after revocation".into())
+        .await
+        .unwrap();
+    alice
+        .send_message(direct, "from my remaining device".into())
+        .await
+        .unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let page = bobbi
+                .message_history(direct, HistoryQuery::default())
+                .await
+                .unwrap();
+            let devices = page.messages.iter().map(|m| m.device).collect::<Vec<_>>();
+            (devices == vec![alicia.device_id(), alice.device_id()]).ok_or(devices)
+        },
+    )
+    .await
+    .unwrap();
+}
+
+/// A device which asks to be linked is only linked once it's approved,
+/// and a code links only one device.
+#[tokio::test(flavor = "multi_thread")]
+async fn device_request_needs_approval() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let mailbox = MemMailbox::new();
+    let alice = TestNode::new(NodeConfig::testing(), "alice")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+    let alicia = TestNode::new(NodeConfig::testing(), "alicia")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+    let mallory = TestNode::new(NodeConfig::testing(), "mallory")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+
+    let code = alice
+        .new_qr_code(ShareIntent::AddDevice, true)
+        .await
+        .unwrap();
+    let mallory_agent = mallory.agent_id();
+    mallory.link_device(code.clone()).await.unwrap();
+    alicia.link_device(code).await.unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let requests = alice.pending_device_requests().await.unwrap();
+            (requests.len() == 2).ok_or(requests)
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(mallory.agent_id(), mallory_agent);
+    assert!(
+        !alice
+            .my_devices()
+            .await
+            .unwrap()
+            .contains(&mallory.device_id())
+    );
+
+    alice.approve_device(alicia.device_id()).await.unwrap();
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async { (alicia.agent_id() == alice.agent_id()).ok_or(alicia.agent_id()) },
+    )
+    .await
+    .unwrap();
 
-    todo!("accept");
+    // The code was used, so the other request can't be approved anymore.
+    assert!(alice.pending_device_requests().await.unwrap().is_empty());
+    assert!(alice.approve_device(mallory.device_id()).await.is_err());
+    assert_eq!(mallory.agent_id(), mallory_agent);
 }
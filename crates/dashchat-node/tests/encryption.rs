@@ -0,0 +1,153 @@
+#![feature(bool_to_result)]
+
+use std::time::Duration;
+
+use dashchat_node::{testing::*, *};
+
+const TRACING_FILTER: [&str; 4] = [
+    "dashchat=info",
+    "p2panda_stream=warn",
+    "p2panda_auth=warn",
+    "named_id=warn",
+];
+
+/// Direct chat payloads are stored and synced sealed for the devices of both agents,
+/// while both of them read them as usual.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_direct_chat_is_sealed() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let chat_id = alice.direct_chat_topic(bobbi.agent_id());
+
+    alice
+        .send_message(chat_id, "meet me at the old mill".into())
+        .await
+        .unwrap();
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let page = bobbi
+                .message_history(chat_id, HistoryQuery::default())
+                .await
+                .unwrap();
+            let texts = page
+                .messages
+                .iter()
+                .filter_map(|m| match &m.content {
+                    HistoryContent::Text(edited) => Some(edited.content.text.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            (texts == vec!["meet me at the old mill".to_string()]).ok_or(texts)
+        },
+    )
+    .await
+    .unwrap();
+
+    for node in [&alice, &bobbi] {
+        let log = node
+            .get_log(chat_id.into(), alice.device_id())
+            .await
+            .unwrap();
+        let body = log.last().unwrap().1.clone().unwrap();
+        assert!(
+            !body
+                .to_bytes()
+                .windows(b"old mill".len())
+                .any(|w| w == b"old mill")
+        );
+        let Payload::Sealed(sealed) = Payload::try_from_body(&body).unwrap() else {
+            panic!("direct chat payload is not sealed");
+        };
+        let mut recipients = sealed
+            .recipients
+            .iter()
+            .map(|(device, _)| *device)
+            .collect::<Vec<_>>();
+        recipients.sort();
+        let mut expected = vec![alice.device_id(), bobbi.device_id()];
+        expected.sort();
+        assert_eq!(recipients, expected);
+    }
+}
+
+/// Contact requests are sealed to the ephemeral key of the inbox they are sent to,
+/// so that only the device which issued the code can read them.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_contact_request_is_sealed() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    let code = alice
+        .new_qr_code(ShareIntent::AddContact, true)
+        .await
+        .unwrap();
+    assert!(code.inbox_key.is_some());
+    assert!(
+        alice
+            .new_qr_code(ShareIntent::AddContact, false)
+            .await
+            .unwrap()
+            .inbox_key
+            .is_none()
+    );
+    let inbox_topic = code.inbox_topic.clone().unwrap().topic;
+
+    bobbi.add_contact(code).await.unwrap();
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let requests = alice.pending_contact_requests().await.unwrap();
+            let agents = requests
+                .iter()
+                .map(|request| request.code.agent_id)
+                .collect::<Vec<_>>();
+            (agents == vec![bobbi.agent_id()]).ok_or(agents)
+        },
+    )
+    .await
+    .unwrap();
+
+    let log = alice
+        .get_log(inbox_topic.into(), bobbi.device_id())
+        .await
+        .unwrap();
+    let body = log.last().unwrap().1.clone().unwrap();
+    assert!(
+        !body
+            .to_bytes()
+            .windows(b"bobbi".len())
+            .any(|w| w == b"bobbi")
+    );
+    let Payload::Sealed(sealed) = Payload::try_from_body(&body).unwrap() else {
+        panic!("contact request is not sealed");
+    };
+    let recipients = sealed
+        .recipients
+        .iter()
+        .map(|(device, _)| *device)
+        .collect::<Vec<_>>();
+    assert_eq!(recipients, vec![alice.device_id()]);
+}
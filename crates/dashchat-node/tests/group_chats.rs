@@ -6,7 +6,7 @@
 
 use std::time::Duration;
 
-use dashchat_node::{testing::*, *};
+use dashchat_node::{testing::*, topic::TopicId, *};
 use mailbox_client::mem::MemMailbox;
 
 use named_id::*;
@@ -100,3 +100,243 @@ async fn test_direct_chat() {
         Some("Hello".into())
     );
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_group_chat() {
+    dashchat_node::testing::setup_tracing(
+        &[
+            "dashchat=info",
+            "p2panda_stream=warn",
+            "p2panda_auth=warn",
+            "named_id=warn",
+        ],
+        true,
+    );
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi", "carol"],
+    )
+    .await;
+    let [alice, bobbi, carol] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&carol, ShareIntent::AddContact)
+        .await
+        .unwrap();
+
+    let chat_id = alice.create_group_chat().await.unwrap();
+    assert_eq!(alice.get_group_chats().unwrap(), vec![chat_id]);
+
+    alice
+        .invite_to_group(chat_id, bobbi.agent_id())
+        .await
+        .unwrap();
+    alice
+        .invite_to_group(chat_id, carol.agent_id())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        bobbi
+            .behavior()
+            .accept_next_group_invitation()
+            .await
+            .unwrap(),
+        chat_id
+    );
+    assert_eq!(
+        carol
+            .behavior()
+            .accept_next_group_invitation()
+            .await
+            .unwrap(),
+        chat_id
+    );
+    assert_eq!(bobbi.get_group_chats().unwrap(), vec![chat_id]);
+
+    bobbi.send_message(chat_id, "Hi all".into()).await.unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let msgs = [
+                alice.get_messages(chat_id).await.unwrap().len(),
+                bobbi.get_messages(chat_id).await.unwrap().len(),
+                carol.get_messages(chat_id).await.unwrap().len(),
+            ];
+            msgs.iter().all(|m| *m == 1).ok_or(msgs)
+        },
+    )
+    .await
+    .unwrap();
+
+    let expected = vec![alice.agent_id(), bobbi.agent_id(), carol.agent_id()];
+    for node in [&alice, &bobbi, &carol] {
+        assert_eq!(node.get_group_members(chat_id).await.unwrap(), expected);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_leave_group() {
+    dashchat_node::testing::setup_tracing(
+        &[
+            "dashchat=info",
+            "p2panda_stream=warn",
+            "p2panda_auth=warn",
+            "named_id=warn",
+        ],
+        true,
+    );
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+
+    let chat_id = alice.create_group_chat().await.unwrap();
+    alice
+        .invite_to_group(chat_id, bobbi.agent_id())
+        .await
+        .unwrap();
+    bobbi
+        .behavior()
+        .accept_next_group_invitation()
+        .await
+        .unwrap();
+
+    bobbi.send_message(chat_id, "Bye all".into()).await.unwrap();
+    bobbi.leave_group(chat_id).await.unwrap();
+
+    assert!(bobbi.get_group_chats().unwrap().is_empty());
+    assert!(
+        !bobbi
+            .mailboxes
+            .subscribed_topics()
+            .await
+            .contains(&TopicId::from(chat_id))
+    );
+    assert!(bobbi.leave_group(chat_id).await.is_err());
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let members = alice.get_group_members(chat_id).await.unwrap();
+            (members == vec![alice.agent_id()]).ok_or(members)
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(alice.get_messages(chat_id).await.unwrap().len(), 1);
+
+    // Bobbi doesn't receive anything from the group chat anymore:
+    // by the time a later direct message arrives, the group chat message
+    // would have arrived as well.
+    alice
+        .send_message(chat_id, "Bye bobbi".into())
+        .await
+        .unwrap();
+    let direct_chat_id = alice.direct_chat_topic(bobbi.agent_id());
+    alice
+        .send_message(direct_chat_id, "Still here".into())
+        .await
+        .unwrap();
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let msgs = bobbi.get_messages(direct_chat_id).await.unwrap().len();
+            (msgs == 1).ok_or(msgs)
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(bobbi.get_messages(chat_id).await.unwrap().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_group_invitation_policy() {
+    dashchat_node::testing::setup_tracing(
+        &[
+            "dashchat=info",
+            "p2panda_stream=warn",
+            "p2panda_auth=warn",
+            "named_id=warn",
+        ],
+        true,
+    );
+
+    let mailbox = MemMailbox::new();
+    let mut manual = NodeConfig::testing();
+    manual.group_invitations = GroupInvitationPolicy::Manual;
+
+    let alice = TestNode::new(NodeConfig::testing(), "alice")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+    let bobbi = TestNode::new(NodeConfig::testing(), "bobbi")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+    let carol = TestNode::new(manual, "carol")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+
+    for other in [&bobbi, &carol] {
+        alice
+            .behavior()
+            .initiate_and_establish_contact(other, ShareIntent::AddContact)
+            .await
+            .unwrap();
+    }
+
+    let chat_id = alice.create_group_chat().await.unwrap();
+    for other in [&bobbi, &carol] {
+        alice
+            .invite_to_group(chat_id, other.agent_id())
+            .await
+            .unwrap();
+    }
+
+    // Bobbi joins by himself, Carol has to accept
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let joined = bobbi.get_group_chats().unwrap() == vec![chat_id];
+            let pending = carol.pending_group_invitations().unwrap()
+                == vec![GroupInvitation {
+                    chat_id,
+                    inviter: alice.agent_id(),
+                }];
+            (joined && pending).ok_or((joined, pending))
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(carol.get_group_chats().unwrap().is_empty());
+    carol.accept_group_invitation(chat_id).await.unwrap();
+    assert_eq!(carol.get_group_chats().unwrap(), vec![chat_id]);
+    assert!(carol.pending_group_invitations().unwrap().is_empty());
+    assert!(carol.decline_group_invitation(chat_id).is_err());
+}
@@ -0,0 +1,871 @@
+#![feature(bool_to_result)]
+
+use std::time::Duration;
+
+use dashchat_node::{testing::*, *};
+
+const TRACING_FILTER: [&str; 4] = [
+    "dashchat=info",
+    "p2panda_stream=warn",
+    "p2panda_auth=warn",
+    "named_id=warn",
+];
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_edit_message() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let chat_id = alice.direct_chat_topic(bobbi.agent_id());
+
+    let hash = alice
+        .send_message(chat_id, "Helo".into())
+        .await
+        .unwrap()
+        .hash();
+    alice
+        .edit_message(chat_id, hash, "Hello".into())
+        .await
+        .unwrap();
+    alice
+        .edit_message(chat_id, hash, "Hello bobbi".into())
+        .await
+        .unwrap();
+
+    // Only the author can edit a message.
+    assert!(
+        bobbi
+            .edit_message(chat_id, hash, "Bye".into())
+            .await
+            .is_err()
+    );
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let message = bobbi.get_edited_message(chat_id, hash).await.unwrap();
+            let versions = message.as_ref().map(|m| m.history.len());
+            (versions == Some(3)).ok_or(versions)
+        },
+    )
+    .await
+    .unwrap();
+
+    let message = bobbi
+        .get_edited_message(chat_id, hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(message.content, "Hello bobbi".into());
+    assert_eq!(message.author(), alice.device_id());
+    assert_eq!(
+        message
+            .history
+            .iter()
+            .map(|version| version.content.clone())
+            .collect::<Vec<_>>(),
+        vec![
+            ChatMessageContent::from("Helo"),
+            "Hello".into(),
+            "Hello bobbi".into()
+        ]
+    );
+    assert_eq!(
+        alice.get_edited_message(chat_id, hash).await.unwrap(),
+        Some(message)
+    );
+
+    // Edits only target messages.
+    assert!(
+        alice
+            .edit_message(chat_id, message.history[1].hash, "Hi".into())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_delete_message() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let chat_id = alice.direct_chat_topic(bobbi.agent_id());
+
+    let hash = alice
+        .send_message(chat_id, "Oops".into())
+        .await
+        .unwrap()
+        .hash();
+    alice
+        .edit_message(chat_id, hash, "Oops!".into())
+        .await
+        .unwrap();
+    alice.send_message(chat_id, "Hi".into()).await.unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let msgs = bobbi.get_messages(chat_id).await.unwrap().len();
+            (msgs == 2).ok_or(msgs)
+        },
+    )
+    .await
+    .unwrap();
+
+    // Only the author can delete a message.
+    assert!(bobbi.delete_message(chat_id, hash).await.is_err());
+
+    alice.delete_message(chat_id, hash).await.unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let msgs = bobbi.get_messages(chat_id).await.unwrap().len();
+            (msgs == 1).ok_or(msgs)
+        },
+    )
+    .await
+    .unwrap();
+
+    for node in [&alice, &bobbi] {
+        let messages = node.get_messages(chat_id).await.unwrap();
+        assert_eq!(messages[0].content, "Hi".into());
+        assert_eq!(node.get_edited_message(chat_id, hash).await.unwrap(), None);
+
+        // The headers are kept, only the bodies of the message and its edit are gone.
+        let log = node
+            .get_log(chat_id.into(), alice.device_id())
+            .await
+            .unwrap();
+        assert_eq!(log.len(), 4);
+        assert_eq!(
+            log.iter()
+                .map(|(_, body)| body.is_some())
+                .collect::<Vec<_>>(),
+            vec![false, false, true, true]
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_reply() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let chat_id = alice.direct_chat_topic(bobbi.agent_id());
+
+    let hash = alice
+        .send_message(chat_id, "How are you?".into())
+        .await
+        .unwrap()
+        .hash();
+    bobbi
+        .send_message(chat_id, ChatMessageContent::reply("Great!", hash))
+        .await
+        .unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let msgs = alice.get_messages(chat_id).await.unwrap().len();
+            (msgs == 2).ok_or(msgs)
+        },
+    )
+    .await
+    .unwrap();
+
+    let messages = alice.get_messages(chat_id).await.unwrap();
+    let reply = messages
+        .iter()
+        .find(|message| message.content.reply_to.is_some())
+        .unwrap();
+    assert_eq!(reply.author, bobbi.device_id());
+    assert_eq!(
+        reply.quoted,
+        Some(QuotedMessage {
+            hash,
+            author: Some(alice.device_id()),
+            excerpt: Some("How are you?".to_string()),
+        })
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_receipts() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let chat_id = alice.direct_chat_topic(bobbi.agent_id());
+
+    let first = alice
+        .send_message(chat_id, "First".into())
+        .await
+        .unwrap()
+        .hash();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let status = alice.message_status(chat_id, first).await.unwrap();
+            status
+                .delivered_to
+                .contains(&bobbi.device_id())
+                .ok_or(status)
+        },
+    )
+    .await
+    .unwrap();
+    assert!(
+        alice
+            .message_status(chat_id, first)
+            .await
+            .unwrap()
+            .published
+    );
+
+    bobbi.mark_read(chat_id, first).await.unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let status = alice.message_status(chat_id, first).await.unwrap();
+            status.read_by.contains(&bobbi.agent_id()).ok_or(status)
+        },
+    )
+    .await
+    .unwrap();
+
+    // With read receipts disabled, messages are still marked as delivered but never as read.
+    bobbi.set_read_receipts_enabled(chat_id, false).unwrap();
+    let second = alice
+        .send_message(chat_id, "Second".into())
+        .await
+        .unwrap()
+        .hash();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let status = alice.message_status(chat_id, second).await.unwrap();
+            status
+                .delivered_to
+                .contains(&bobbi.device_id())
+                .ok_or(status)
+        },
+    )
+    .await
+    .unwrap();
+
+    bobbi.mark_read(chat_id, second).await.unwrap();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert!(
+        alice
+            .message_status(chat_id, second)
+            .await
+            .unwrap()
+            .read_by
+            .is_empty()
+    );
+}
+
+/// Typing indicators are gossiped directly to the devices I'm connected to.
+#[cfg(feature = "p2p")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_typing_indicator() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    cluster.introduce_all().await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let chat_id = alice.direct_chat_topic(bobbi.agent_id());
+
+    alice
+        .send_ephemeral(chat_id, EphemeralPayload::Typing)
+        .await
+        .unwrap();
+
+    let author = bobbi
+        .events
+        .lock()
+        .await
+        .watch_mapped(Duration::from_secs(5), |e: &Event| match e {
+            Event::Ephemeral {
+                chat_id: c,
+                author,
+                payload: EphemeralPayload::Typing,
+            } if *c == chat_id => Some(*author),
+            _ => None,
+        })
+        .await
+        .expect("Bobbi should see that Alice is typing");
+    assert_eq!(author, alice.device_id());
+}
+
+/// Typing indicators are never written to a log, so they never reach the mailboxes.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_typing_indicator_is_not_stored() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let chat_id = alice.direct_chat_topic(bobbi.agent_id());
+    let log_len = alice
+        .get_log(chat_id.into(), alice.device_id())
+        .await
+        .unwrap()
+        .len();
+
+    alice
+        .send_ephemeral(chat_id, EphemeralPayload::Typing)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        alice
+            .get_log(chat_id.into(), alice.device_id())
+            .await
+            .unwrap()
+            .len(),
+        log_len
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_attachment() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let chat_id = alice.direct_chat_topic(bobbi.agent_id());
+
+    // Large enough to be split into several chunks.
+    let data = (0..blobs::CHUNK_SIZE * 2 + 100)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    let size = data.len() as u64;
+    let message = alice
+        .send_attachment(
+            chat_id,
+            data.clone(),
+            "application/octet-stream".to_string(),
+            "data.bin".to_string(),
+        )
+        .await
+        .unwrap()
+        .hash();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            bobbi
+                .download_attachment(chat_id, message)
+                .await
+                .map(|_| ())
+        },
+    )
+    .await
+    .unwrap();
+
+    let path = bobbi.download_attachment(chat_id, message).await.unwrap();
+    assert_eq!(std::fs::read(path).unwrap(), data);
+
+    bobbi
+        .events
+        .lock()
+        .await
+        .watch_mapped(Duration::from_secs(5), |e: &Event| match e {
+            Event::DownloadProgress { received, .. } if *received == size => Some(()),
+            _ => None,
+        })
+        .await
+        .expect("Bobbi should be notified of the download's progress");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_message_history() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let chat_id = alice.direct_chat_topic(bobbi.agent_id());
+
+    let mut hashes = vec![];
+    for text in ["one", "two", "three", "four", "five"] {
+        let header = alice.send_message(chat_id, text.into()).await.unwrap();
+        hashes.push(header.hash());
+    }
+    alice
+        .edit_message(chat_id, hashes[1], "TWO".into())
+        .await
+        .unwrap();
+    alice.delete_message(chat_id, hashes[2]).await.unwrap();
+    bobbi
+        .add_reaction(
+            chat_id,
+            ChatReaction {
+                emoji: Some("👍".into()),
+                target: hashes[0],
+            },
+        )
+        .await
+        .unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            for node in [&alice, &bobbi] {
+                let page = node
+                    .message_history(chat_id, HistoryQuery::default())
+                    .await
+                    .unwrap();
+                let reacted = page.messages.first().map(|m| m.reactions.len());
+                (page.messages.len() == 4 && reacted == Some(1)).ok_or(page.messages.len())?;
+            }
+            Ok::<_, usize>(())
+        },
+    )
+    .await
+    .unwrap();
+
+    let texts = |page: &HistoryPage| {
+        page.messages
+            .iter()
+            .map(|m| match &m.content {
+                HistoryContent::Text(message) => message.content.text.clone(),
+                HistoryContent::Attachment(attachment) => attachment.name.clone(),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // The latest page, oldest message first.
+    let page = alice
+        .message_history(
+            chat_id,
+            HistoryQuery {
+                limit: 2,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(texts(&page), vec!["four", "five"]);
+    assert!(page.has_more);
+
+    // Going back holds the closest messages before the cursor, still oldest first.
+    let before_five = alice
+        .message_history(
+            chat_id,
+            HistoryQuery {
+                before: Some(hashes[4]),
+                limit: 2,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(texts(&before_five), vec!["TWO", "four"]);
+    assert!(before_five.has_more);
+
+    // Going back from the oldest message of the page, skipping the deleted message.
+    let page = alice
+        .message_history(
+            chat_id,
+            HistoryQuery {
+                before: Some(page.messages[0].hash),
+                limit: 2,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(texts(&page), vec!["one", "TWO"]);
+    assert!(!page.has_more);
+
+    let first = &page.messages[0];
+    assert_eq!(first.hash, hashes[0]);
+    assert_eq!(first.author, Some(alice.agent_id()));
+    assert_eq!(first.device, alice.device_id());
+    assert_eq!(
+        first.reactions,
+        vec![ReactionCount {
+            emoji: "👍".into(),
+            count: 1,
+            agents: vec![bobbi.agent_id()],
+        }]
+    );
+    let HistoryContent::Text(edited) = &page.messages[1].content else {
+        panic!("expected a text message");
+    };
+    assert_eq!(edited.history.len(), 2);
+
+    // Going forward from a cursor.
+    let page = alice
+        .message_history(
+            chat_id,
+            HistoryQuery {
+                after: Some(hashes[1]),
+                limit: 1,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(texts(&page), vec!["four"]);
+    assert!(page.has_more);
+
+    // Bobbi sees the same history.
+    assert_eq!(
+        bobbi
+            .message_history(chat_id, HistoryQuery::default())
+            .await
+            .unwrap(),
+        alice
+            .message_history(chat_id, HistoryQuery::default())
+            .await
+            .unwrap()
+    );
+}
+
+/// Only the latest reaction of each agent counts, and a reaction
+/// without an emoji removes the earlier one.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_reactions() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let chat_id = alice.direct_chat_topic(bobbi.agent_id());
+
+    let target = alice
+        .send_message(chat_id, "hello".into())
+        .await
+        .unwrap()
+        .hash();
+    let other = alice
+        .send_message(chat_id, "world".into())
+        .await
+        .unwrap()
+        .hash();
+    let react = |emoji: Option<&str>| ChatReaction {
+        emoji: emoji.map(Into::into),
+        target,
+    };
+    alice
+        .add_reaction(chat_id, react(Some("👍")))
+        .await
+        .unwrap();
+    alice
+        .add_reaction(chat_id, react(Some("❤️")))
+        .await
+        .unwrap();
+    bobbi
+        .add_reaction(chat_id, react(Some("❤️")))
+        .await
+        .unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            for node in [&alice, &bobbi] {
+                let reactions = node.reactions(chat_id, target).await.unwrap();
+                let counts = reactions
+                    .iter()
+                    .map(|r| (r.emoji.clone(), r.count))
+                    .collect::<Vec<_>>();
+                (counts == vec![("❤️".to_string(), 2)]).ok_or(counts)?;
+            }
+            Ok::<_, Vec<(String, usize)>>(())
+        },
+    )
+    .await
+    .unwrap();
+
+    bobbi.add_reaction(chat_id, react(None)).await.unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let reactions = alice.reactions(chat_id, target).await.unwrap();
+            (reactions.len() == 1 && reactions[0].count == 1).ok_or(reactions)
+        },
+    )
+    .await
+    .unwrap();
+
+    let batch = alice
+        .reactions_batch(chat_id, &[target, other])
+        .await
+        .unwrap();
+    assert_eq!(batch[&target][0].agents, vec![alice.agent_id()]);
+    assert!(batch[&other].is_empty());
+}
+
+/// Search ignores case and diacritics, matches the start of words,
+/// and follows edits and deletions.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_search_messages() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let chat_id = alice.direct_chat_topic(bobbi.agent_id());
+    let group_id = alice.create_group_chat().await.unwrap();
+
+    let meet = alice
+        .send_message(chat_id, "Meet me at the Café at noon".into())
+        .await
+        .unwrap()
+        .hash();
+    alice
+        .send_message(chat_id, "Lunch tomorrow?".into())
+        .await
+        .unwrap();
+    alice
+        .send_message(group_id, "Anyone up for the cafe?".into())
+        .await
+        .unwrap();
+    let closed = bobbi
+        .send_message(chat_id, "CAFE is closed".into())
+        .await
+        .unwrap()
+        .hash();
+
+    let search = |query: &'static str, chat_id: Option<ChatId>| {
+        let alice = &alice;
+        async move {
+            alice
+                .search_messages(query, chat_id, 10)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|result| result.snippet)
+                .collect::<Vec<_>>()
+        }
+    };
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let found = search("café", Some(chat_id)).await;
+            (found.len() == 2).ok_or(found)
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        search("cafe", Some(chat_id)).await,
+        vec!["CAFE is closed", "Meet me at the Café at noon"]
+    );
+    assert_eq!(search("cafe", None).await.len(), 3);
+    assert_eq!(search("lun TOMORROW", None).await, vec!["Lunch tomorrow?"]);
+    assert!(search("cafe lunch", None).await.is_empty());
+
+    alice
+        .edit_message(chat_id, meet, "Meet me at the park".into())
+        .await
+        .unwrap();
+    bobbi.delete_message(chat_id, closed).await.unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let found = search("cafe", Some(chat_id)).await;
+            found.is_empty().ok_or(found)
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(search("park", None).await, vec!["Meet me at the park"]);
+}
+
+/// Messages from others after my read marker are unread,
+/// and the chat list shows the latest message of each chat.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_chat_summaries() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let cluster = TestCluster::new(
+        NodeConfig::testing(),
+        ClusterConfig::default(),
+        ["alice", "bobbi"],
+    )
+    .await;
+    let [alice, bobbi] = cluster.nodes().await;
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+    let chat_id = alice.direct_chat_topic(bobbi.agent_id());
+
+    let mut hashes = vec![];
+    for text in ["one", "two", "three"] {
+        let header = bobbi.send_message(chat_id, text.into()).await.unwrap();
+        hashes.push(header.hash());
+    }
+
+    let summary = || async {
+        let summaries = alice.chat_summaries().await.unwrap();
+        summaries
+            .into_iter()
+            .find(|summary| summary.chat_id == chat_id)
+            .unwrap()
+    };
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(10),
+        || async {
+            let summary = summary().await;
+            (summary.unread == 3).ok_or(summary)
+        },
+    )
+    .await
+    .unwrap();
+    let last_message = summary().await.last_message.unwrap();
+    assert_eq!(last_message.excerpt, "three");
+    assert_eq!(last_message.author, Some(bobbi.agent_id()));
+
+    alice.mark_read(chat_id, hashes[1]).await.unwrap();
+    assert_eq!(summary().await.unread, 1);
+
+    // Marking an earlier message as read doesn't move the marker back.
+    alice.mark_read(chat_id, hashes[0]).await.unwrap();
+    assert_eq!(summary().await.unread, 1);
+
+    // My own messages are never unread.
+    alice.send_message(chat_id, "four".into()).await.unwrap();
+    let summary = summary().await;
+    assert_eq!(summary.unread, 1);
+    assert_eq!(summary.last_message.unwrap().excerpt, "four");
+    assert!(summary.last_activity.is_some());
+}
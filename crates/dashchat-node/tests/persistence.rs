@@ -0,0 +1,116 @@
+use dashchat_node::{topic::TopicId, *};
+
+const TRACING_FILTER: [&str; 3] = ["dashchat=info", "p2panda_stream=info", "named_id=warn"];
+
+/// Operations authored before a restart are still there afterwards,
+/// and are not considered new by the restarted node.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_op_store_survives_restart() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let dir = tempfile::tempdir().unwrap();
+    let local_store = LocalStore::new(dir.path().join("store.db")).unwrap();
+    let mut config = NodeConfig::testing();
+    config.op_store = OpStoreBackend::Sqlite;
+
+    let profile = Profile {
+        name: "alice".to_string(),
+        avatar: None,
+    };
+
+    let announcements = {
+        let node = Node::new(local_store.clone(), config.clone(), None)
+            .await
+            .unwrap();
+        node.set_profile(profile.clone()).await.unwrap();
+        Topic::announcements(node.agent_id())
+    };
+
+    assert!(local_store.op_store_path().exists());
+
+    let node = Node::new(local_store.clone(), config, None).await.unwrap();
+    assert_eq!(node.my_profile().await.unwrap(), Some(profile));
+
+    let log = node
+        .get_log(announcements.into(), node.device_id())
+        .await
+        .unwrap();
+    // The key bundle of the device, then the profile.
+    assert_eq!(log.len(), 2);
+    assert!(
+        node.op_store
+            .is_op_processed(&announcements.into(), &log[1].0.hash())
+    );
+}
+
+/// Topics subscribed to before a restart are subscribed to again afterwards.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_resubscribe_on_restart() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let dir = tempfile::tempdir().unwrap();
+    let local_store = LocalStore::new(dir.path().join("store.db")).unwrap();
+    let config = NodeConfig::testing();
+
+    let chat_id = ChatId::random();
+    {
+        let node = Node::new(local_store.clone(), config.clone(), None)
+            .await
+            .unwrap();
+        node.join_group(chat_id).await.unwrap();
+    }
+
+    let node = Node::new(local_store.clone(), config, None).await.unwrap();
+    let topics = node.mailboxes.subscribed_topics().await;
+    assert!(topics.contains(&TopicId::from(chat_id)));
+    assert!(topics.contains(&TopicId::from(node.device_group_topic())));
+    assert!(topics.contains(&TopicId::from(Topic::announcements(node.agent_id()))));
+}
+
+/// The read model can be rebuilt from the op store, with the same result
+/// as updating it while operations are processed.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rebuild_read_model() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let dir = tempfile::tempdir().unwrap();
+    let local_store = LocalStore::new(dir.path().join("store.db")).unwrap();
+    let mut config = NodeConfig::testing();
+    config.op_store = OpStoreBackend::Sqlite;
+
+    let node = Node::new(local_store.clone(), config, None).await.unwrap();
+    let chat_id = node.create_group_chat().await.unwrap();
+    let first = node
+        .send_message(chat_id, "first".into())
+        .await
+        .unwrap()
+        .hash();
+    node.send_message(chat_id, "second".into()).await.unwrap();
+    node.edit_message(chat_id, first, "first!".into())
+        .await
+        .unwrap();
+    node.add_reaction(
+        chat_id,
+        ChatReaction {
+            emoji: Some("🎉".into()),
+            target: first,
+        },
+    )
+    .await
+    .unwrap();
+
+    let history = node
+        .message_history(chat_id, HistoryQuery::default())
+        .await
+        .unwrap();
+    assert_eq!(history.messages.len(), 2);
+    assert_eq!(history.messages[0].reactions.len(), 1);
+
+    node.rebuild_read_model().await.unwrap();
+    assert_eq!(
+        node.message_history(chat_id, HistoryQuery::default())
+            .await
+            .unwrap(),
+        history
+    );
+}
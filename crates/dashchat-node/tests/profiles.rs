@@ -35,7 +35,7 @@ async fn test_set_profile_and_my_profile() {
 
     let profile = Profile {
         name: "Alice".to_string(),
-        avatar: Some("alice_avatar.png".to_string()),
+        avatar: Some(alice.upload_avatar(b"alice".to_vec()).await.unwrap()),
     };
     alice.set_profile(profile.clone()).await.unwrap();
 
@@ -52,7 +52,7 @@ async fn test_set_profile_overwrites_previous_profile() {
     // Update profile with new name and avatar
     let updated_profile = Profile {
         name: "Alice Updated".to_string(),
-        avatar: Some("new_avatar.png".to_string()),
+        avatar: Some(alice.upload_avatar(b"new avatar".to_vec()).await.unwrap()),
     };
     alice.set_profile(updated_profile.clone()).await.unwrap();
 
@@ -82,9 +82,10 @@ async fn test_profiles_sync_between_contacts() {
     introduce_and_wait([&alice.network, &bobbi.network]).await;
 
     // Set initial profiles before adding contacts
+    let avatar = b"this is a picture of alice".to_vec();
     let profile = Profile {
         name: "Alice".to_string(),
-        avatar: Some("this is a picture of alice".to_string()),
+        avatar: Some(alice.upload_avatar(avatar.clone()).await.unwrap()),
     };
     alice.set_profile(profile.clone()).await.unwrap();
     bobbi
@@ -136,4 +137,116 @@ async fn test_profiles_sync_between_contacts() {
     )
     .await
     .unwrap();
+
+    // Profiles are read once the operation has been processed.
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(5),
+        || async {
+            let received = bobbi.get_profile(alice.agent_id()).await.unwrap();
+            (received.as_ref() == Some(&profile)).ok_or(received)
+        },
+    )
+    .await
+    .unwrap();
+
+    // Bobbi's contacts are joined with their latest profile.
+    assert_eq!(
+        bobbi.contacts().await.unwrap(),
+        vec![Contact {
+            agent_id: alice.agent_id(),
+            profile: Some(profile.clone()),
+        }]
+    );
+
+    // Bobbi fetches Alice's avatar from the mailbox.
+    let path = bobbi.get_avatar(alice.agent_id()).await.unwrap().unwrap();
+    assert_eq!(std::fs::read(path).unwrap(), avatar);
+    assert_eq!(bobbi.get_avatar(bobbi.agent_id()).await.unwrap(), None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_profiles_written_by_other_agents_are_ignored() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let mailbox = MemMailbox::new();
+    let alice = TestNode::new(NodeConfig::testing(), "alice--")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+    let bobbi = TestNode::new(NodeConfig::testing(), "--bobbi")
+        .await
+        .add_mailbox_client(mailbox.client())
+        .await;
+
+    #[cfg(feature = "p2p")]
+    introduce_and_wait([&alice.network, &bobbi.network]).await;
+
+    let profile = Profile {
+        name: "Alice".to_string(),
+        avatar: None,
+    };
+    alice.set_profile(profile.clone()).await.unwrap();
+
+    alice
+        .behavior()
+        .initiate_and_establish_contact(&bobbi, ShareIntent::AddContact)
+        .await
+        .unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(5),
+        || async {
+            let received = bobbi.get_profile(alice.agent_id()).await.unwrap();
+            (received.as_ref() == Some(&profile)).ok_or(received)
+        },
+    )
+    .await
+    .unwrap();
+
+    // Bobbi follows Alice's announcements, so it can write there too.
+    let announcements = TopicId::from(Topic::announcements(alice.agent_id()));
+    bobbi
+        .author_anywhere(
+            announcements,
+            Payload::Announcements(AnnouncementsPayload::SetProfile(Profile {
+                name: "Not Alice".to_string(),
+                avatar: None,
+            })),
+        )
+        .await
+        .unwrap();
+
+    wait_for(
+        Duration::from_millis(100),
+        Duration::from_secs(5),
+        || async {
+            alice
+                .op_store
+                .get_log(&bobbi.device_id(), &announcements, None)
+                .await
+                .map_err(|_| "failed to get log")?
+                .ok_or("no log found")
+                .map(|_| ())
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(alice.my_profile().await.unwrap(), Some(profile.clone()));
+    assert_eq!(
+        bobbi.get_profile(alice.agent_id()).await.unwrap(),
+        Some(profile)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_upload_avatar_rejects_large_images() {
+    dashchat_node::testing::setup_tracing(&TRACING_FILTER, true);
+
+    let alice = TestNode::new(NodeConfig::testing(), "alice").await;
+
+    let result = alice.upload_avatar(vec![0; 2 * 1024 * 1024]).await;
+    assert!(matches!(result, Err(Error::UploadAvatar(_))));
 }
@@ -32,8 +32,24 @@ pub trait MailboxClient<Item: MailboxItem>: Send + Sync + 'static {
         &self,
         request: FetchRequest<Item>,
     ) -> Result<FetchResponse<Item>, anyhow::Error>;
+
+    /// Store one chunk of a file, for anyone who knows the file's ID to fetch.
+    async fn store_chunk(
+        &self,
+        file: FileId,
+        index: u64,
+        data: Vec<u8>,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Fetch one chunk of a file, if the mailbox has it.
+    async fn fetch_chunk(&self, file: FileId, index: u64)
+    -> Result<Option<Vec<u8>>, anyhow::Error>;
 }
 
+/// The ID of a file which is transferred in chunks.
+/// Chunks are opaque to the mailboxes, so the ID needn't be the hash of the file.
+pub type FileId = [u8; 32];
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(bound(deserialize = "Item: DeserializeOwned"))]
 pub struct FetchRequest<Item: MailboxItem>(pub BTreeMap<Item::Topic, FetchTopicRequest<Item>>);
@@ -60,6 +60,11 @@ where
         self.topics.lock().await.keys().cloned().collect()
     }
 
+    /// Whether an item has been published to at least one mailbox.
+    pub async fn is_published(&self, hash: &Item::Hash) -> anyhow::Result<bool> {
+        self.store.is_published(hash).await
+    }
+
     pub fn trigger_sync(&self) {
         _ = self.trigger.try_send(());
     }
@@ -82,6 +87,28 @@ where
         Ok(())
     }
 
+    /// Store a chunk of a file in every mailbox.
+    pub async fn store_chunk(&self, file: FileId, index: u64, data: Vec<u8>) -> anyhow::Result<()> {
+        let mailboxes = self.mailboxes.lock().await.clone();
+        for mailbox in mailboxes {
+            mailbox.store_chunk(file, index, data.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// Fetch a chunk of a file from the first mailbox which has it.
+    pub async fn fetch_chunk(&self, file: FileId, index: u64) -> anyhow::Result<Option<Vec<u8>>> {
+        let mailboxes = self.mailboxes.lock().await.clone();
+        for mailbox in mailboxes {
+            match mailbox.fetch_chunk(file, index).await {
+                Ok(Some(data)) => return Ok(Some(data)),
+                Ok(None) => {}
+                Err(err) => tracing::warn!(?err, "fetch chunk error"),
+            }
+        }
+        Ok(None)
+    }
+
     pub async fn spawn(store: Store, config: MailboxesConfig) -> Result<Self, anyhow::Error> {
         let (trigger_tx, mut trigger_rx) = mpsc::channel(1);
         let manager = Self::new(store, config, trigger_tx);
@@ -156,6 +183,19 @@ where
         }
     }
 
+    /// Immediately sync the given topics with every mailbox.
+    /// The topics must still be subscribed to.
+    pub async fn sync_topics_with_all(
+        &self,
+        topics: impl Iterator<Item = Item::Topic> + Clone,
+    ) -> anyhow::Result<()> {
+        let mailboxes = self.mailboxes.lock().await.clone();
+        for mailbox in mailboxes {
+            self.sync_topics(topics.clone(), mailbox).await?;
+        }
+        Ok(())
+    }
+
     /// Immediately sync the given topics with the given mailbox:
     /// - Ensure all items held by the mailbox are fetched
     /// - Publish any items that the mailbox is missing to the mailbox
@@ -217,7 +257,12 @@ where
             }
         }
 
+        let hashes = ops_to_publish
+            .iter()
+            .map(|op| op.hash())
+            .collect::<Vec<_>>();
         mailbox.publish(ops_to_publish).await?;
+        self.store.add_published(&hashes).await?;
 
         Ok(())
     }
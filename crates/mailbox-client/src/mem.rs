@@ -30,12 +30,14 @@ pub type MemMailboxLogs<Item> = HashMap<
 #[derive(Clone)]
 pub struct MemMailbox<Item: MailboxItem> {
     ops: Arc<RwLock<MemMailboxLogs<Item>>>,
+    chunks: Arc<RwLock<HashMap<(FileId, u64), Vec<u8>>>>,
 }
 
 impl<Item: MailboxItem> MemMailbox<Item> {
     pub fn new() -> Self {
         Self {
             ops: Arc::new(RwLock::new(HashMap::new())),
+            chunks: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
@@ -145,6 +147,25 @@ where
 
         Ok(FetchResponse(response))
     }
+
+    async fn store_chunk(&self, file: FileId, index: u64, data: Vec<u8>) -> anyhow::Result<()> {
+        self.mailbox
+            .chunks
+            .write()
+            .await
+            .insert((file, index), data);
+        Ok(())
+    }
+
+    async fn fetch_chunk(&self, file: FileId, index: u64) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self
+            .mailbox
+            .chunks
+            .read()
+            .await
+            .get(&(file, index))
+            .cloned())
+    }
 }
 
 #[cfg(test)]
@@ -13,4 +13,10 @@ pub trait MailboxStore<Item: MailboxItem>: Clone + Send + Sync + 'static {
         &self,
         topic: &Item::Topic,
     ) -> Result<Vec<(Item::Author, u64)>, anyhow::Error>;
+
+    /// Remember that items were published to at least one mailbox.
+    async fn add_published(&self, hashes: &[Item::Hash]) -> Result<(), anyhow::Error>;
+
+    /// Whether an item was published to at least one mailbox.
+    async fn is_published(&self, hash: &Item::Hash) -> Result<bool, anyhow::Error>;
 }
@@ -1,6 +1,9 @@
 use std::collections::{BTreeMap, HashMap};
 
-use mailbox_server::{Blob, GetBlobsRequest, GetBlobsResponse, StoreBlobsRequest};
+use mailbox_server::{
+    Blob, GetBlobsRequest, GetBlobsResponse, GetChunkRequest, GetChunkResponse, StoreBlobsRequest,
+    StoreChunkRequest,
+};
 
 use super::*;
 
@@ -141,6 +144,67 @@ where
 
         Ok(FetchResponse(result))
     }
+
+    async fn store_chunk(
+        &self,
+        file: FileId,
+        index: u64,
+        data: Vec<u8>,
+    ) -> Result<(), anyhow::Error> {
+        let request = StoreChunkRequest {
+            file: hex::encode(file),
+            index,
+            data: Blob::new(data),
+        };
+        let response = self
+            .client
+            .post(format!("{}/chunks/store", self.base_url))
+            .json(&request)
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            Err(anyhow::anyhow!(
+                "Failed to store chunk: {} - {}",
+                status,
+                body
+            ))
+        }
+    }
+
+    async fn fetch_chunk(
+        &self,
+        file: FileId,
+        index: u64,
+    ) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        let request = GetChunkRequest {
+            file: hex::encode(file),
+            index,
+        };
+        let response = self
+            .client
+            .post(format!("{}/chunks/get", self.base_url))
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "Failed to fetch chunk: {} - {}",
+                status,
+                body
+            ));
+        }
+
+        let response = response.json::<GetChunkResponse>().await?;
+        Ok(response.data.map(Blob::into_vec))
+    }
 }
 
 impl<Item: MailboxItem> ToyMailboxClient<Item>
@@ -0,0 +1,118 @@
+use axum::{extract::State, http::StatusCode, Json};
+use redb::{Database, ReadableDatabase, TableDefinition};
+use serde::{Deserialize, Serialize};
+
+use crate::{AppState, Blob};
+
+/// The ID of a file which is split into chunks, which is the hex-encoded hash of its content
+pub type FileId = String;
+
+/// Chunks of attached files, keyed by the file ID and the index of the chunk.
+/// Unlike the blobs of operations, chunks aren't tied to a topic.
+pub const CHUNKS_TABLE: TableDefinition<(&str, u64), &[u8]> = TableDefinition::new("chunks");
+
+/// When a chunk of each file was last stored, in seconds since the UNIX epoch.
+/// Files are cleaned up a while after that, see [`crate::cleanup_old_chunks`].
+pub const CHUNK_FILES_TABLE: TableDefinition<&str, u64> = TableDefinition::new("chunk_files");
+
+#[derive(Serialize, Deserialize)]
+pub struct StoreChunkRequest {
+    pub file: FileId,
+    pub index: u64,
+    pub data: Blob,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetChunkRequest {
+    pub file: FileId,
+    pub index: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetChunkResponse {
+    pub data: Option<Blob>,
+}
+
+pub async fn store_chunk(
+    State(state): State<AppState>,
+    Json(payload): Json<StoreChunkRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = state.db.clone();
+    // Use spawn_blocking because redb's begin_write() is a blocking call
+    tokio::task::spawn_blocking(move || store_chunk_inner(&db, &payload))
+        .await
+        .map_err(|e| {
+            tracing::error!("Task join error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?
+        .map_err(|e| {
+            tracing::error!("{}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e)
+        })?;
+    Ok(StatusCode::CREATED)
+}
+
+fn store_chunk_inner(db: &Database, request: &StoreChunkRequest) -> Result<(), String> {
+    let write_txn = db
+        .begin_write()
+        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+    {
+        let mut chunks_table = write_txn
+            .open_table(CHUNKS_TABLE)
+            .map_err(|e| format!("Failed to open chunks table: {}", e))?;
+        chunks_table
+            .insert(
+                (request.file.as_str(), request.index),
+                request.data.as_slice(),
+            )
+            .map_err(|e| format!("Failed to insert chunk: {}", e))?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| format!("Failed to get the time: {}", e))?
+            .as_secs();
+        write_txn
+            .open_table(CHUNK_FILES_TABLE)
+            .map_err(|e| format!("Failed to open chunk files table: {}", e))?
+            .insert(request.file.as_str(), now)
+            .map_err(|e| format!("Failed to insert chunk file: {}", e))?;
+    }
+    write_txn
+        .commit()
+        .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+
+    tracing::debug!("Stored chunk {} of {}", request.index, request.file);
+    Ok(())
+}
+
+pub async fn get_chunk(
+    State(state): State<AppState>,
+    Json(payload): Json<GetChunkRequest>,
+) -> Result<Json<GetChunkResponse>, (StatusCode, String)> {
+    let db = state.db.clone();
+    // Use spawn_blocking because redb's begin_read() can block while waiting for writes
+    tokio::task::spawn_blocking(move || get_chunk_inner(&db, &payload))
+        .await
+        .map_err(|e| {
+            tracing::error!("Task join error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("{}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e)
+        })
+}
+
+fn get_chunk_inner(db: &Database, request: &GetChunkRequest) -> Result<GetChunkResponse, String> {
+    let read_txn = db
+        .begin_read()
+        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+    let chunks_table = read_txn
+        .open_table(CHUNKS_TABLE)
+        .map_err(|e| format!("Failed to open chunks table: {}", e))?;
+    let data = chunks_table
+        .get((request.file.as_str(), request.index))
+        .map_err(|e| format!("Failed to read chunk: {}", e))?
+        .map(|data| Blob::new(data.value().to_vec()));
+    Ok(GetChunkResponse { data })
+}
@@ -2,12 +2,14 @@ use redb::{Database, ReadableTable};
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::{BlobsKey, BLOBS_TABLE};
+use crate::{BlobsKey, BLOBS_TABLE, CHUNKS_TABLE, CHUNK_FILES_TABLE};
 
 const CLEANUP_INTERVAL: Duration = Duration::from_secs(5 * 60); // 5 minutes
 const MESSAGE_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60); // 7 days
+/// Files are kept longer than messages, since avatars are fetched long after they're uploaded.
+const CHUNK_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60); // 30 days
 
-/// Spawns a background task that periodically cleans up old messages
+/// Spawns a background task that periodically cleans up old messages and files
 pub fn spawn_cleanup_task(db: Arc<Database>) {
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
@@ -18,6 +20,9 @@ pub fn spawn_cleanup_task(db: Arc<Database>) {
             if let Err(e) = cleanup_old_messages(&db).await {
                 tracing::error!("Failed to cleanup old messages: {}", e);
             }
+            if let Err(e) = cleanup_old_chunks(&db).await {
+                tracing::error!("Failed to cleanup old chunks: {}", e);
+            }
         }
     });
 }
@@ -65,6 +70,46 @@ pub async fn cleanup_old_messages(db: &Database) -> Result<(), Box<dyn std::erro
     Ok(())
 }
 
+/// Deletes the chunks of all files which no chunk was stored for in CHUNK_MAX_AGE
+pub async fn cleanup_old_chunks(db: &Database) -> Result<(), Box<dyn std::error::Error>> {
+    tracing::info!("Starting cleanup of old chunks");
+
+    let cutoff_time = (std::time::SystemTime::now() - CHUNK_MAX_AGE)
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+
+    let write_txn = db.begin_write()?;
+    let mut deleted_count = 0;
+
+    {
+        let mut files = write_txn.open_table(CHUNK_FILES_TABLE)?;
+        let mut chunks = write_txn.open_table(CHUNKS_TABLE)?;
+
+        // Collect files to delete
+        let mut files_to_delete: Vec<String> = Vec::new();
+
+        for entry in files.iter()? {
+            let (file, stored_at) = entry?;
+            if stored_at.value() < cutoff_time {
+                files_to_delete.push(file.value().to_string());
+            }
+        }
+
+        // Delete their chunks
+        for file in &files_to_delete {
+            files.remove(file.as_str())?;
+            chunks.retain_in((file.as_str(), 0)..=(file.as_str(), u64::MAX), |_, _| false)?;
+            deleted_count += 1;
+        }
+    }
+
+    write_txn.commit()?;
+
+    tracing::info!("Cleanup completed: deleted {} old files", deleted_count);
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,6 +123,8 @@ mod tests {
         let write_txn = db.begin_write().unwrap();
         {
             let _table = write_txn.open_table(BLOBS_TABLE).unwrap();
+            let _chunks = write_txn.open_table(CHUNKS_TABLE).unwrap();
+            let _chunk_files = write_txn.open_table(CHUNK_FILES_TABLE).unwrap();
         }
         write_txn.commit().unwrap();
 
@@ -136,4 +183,45 @@ mod tests {
             assert!(table.get(&recent_key).unwrap().is_some());
         }
     }
+
+    #[tokio::test]
+    async fn test_cleanup_old_chunks() {
+        let (db, _temp_file) = create_test_db();
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let old_time = now - 31 * 24 * 60 * 60;
+
+        {
+            let write_txn = db.begin_write().unwrap();
+            {
+                let mut files = write_txn.open_table(CHUNK_FILES_TABLE).unwrap();
+                files.insert("old-file", old_time).unwrap();
+                files.insert("recent-file", now).unwrap();
+                let mut chunks = write_txn.open_table(CHUNKS_TABLE).unwrap();
+                chunks.insert(("old-file", 0), b"old 0".as_slice()).unwrap();
+                chunks.insert(("old-file", 1), b"old 1".as_slice()).unwrap();
+                chunks
+                    .insert(("recent-file", 0), b"recent".as_slice())
+                    .unwrap();
+            }
+            write_txn.commit().unwrap();
+        }
+
+        cleanup_old_chunks(&db).await.unwrap();
+
+        // Verify the old file's chunks are deleted and the recent file's remain
+        {
+            let read_txn = db.begin_read().unwrap();
+            let files = read_txn.open_table(CHUNK_FILES_TABLE).unwrap();
+            assert!(files.get("old-file").unwrap().is_none());
+            assert!(files.get("recent-file").unwrap().is_some());
+            let chunks = read_txn.open_table(CHUNKS_TABLE).unwrap();
+            assert!(chunks.get(("old-file", 0)).unwrap().is_none());
+            assert!(chunks.get(("old-file", 1)).unwrap().is_none());
+            assert!(chunks.get(("recent-file", 0)).unwrap().is_some());
+        }
+    }
 }
@@ -10,6 +10,7 @@ use tower_http::{cors::CorsLayer, trace::TraceLayer};
 
 mod blob;
 mod blobs_table;
+mod chunks;
 mod cleanup;
 mod get_blobs;
 mod store_blobs;
@@ -21,7 +22,11 @@ pub mod test_utils;
 
 pub use blob::Blob;
 pub use blobs_table::{BlobsKey, BlobsKeyError, BlobsKeyPrefix, BLOBS_TABLE};
-pub use cleanup::{cleanup_old_messages, spawn_cleanup_task};
+pub use chunks::{
+    get_chunk, store_chunk, FileId, GetChunkRequest, GetChunkResponse, StoreChunkRequest,
+    CHUNKS_TABLE, CHUNK_FILES_TABLE,
+};
+pub use cleanup::{cleanup_old_chunks, cleanup_old_messages, spawn_cleanup_task};
 pub use get_blobs::{get_blobs_for_topics, GetBlobsRequest, GetBlobsResponse};
 pub use store_blobs::{store_blobs, StoreBlobsRequest};
 pub use watermark::compute_initial_watermarks;
@@ -56,6 +61,8 @@ pub fn init_db(db_path: PathBuf) -> Result<Database, Box<dyn std::error::Error>>
     {
         let _blobs_table = write_txn.open_table(BLOBS_TABLE)?;
         let _watermarks_table = write_txn.open_table(WATERMARKS_TABLE)?;
+        let _chunks_table = write_txn.open_table(CHUNKS_TABLE)?;
+        let _chunk_files_table = write_txn.open_table(CHUNK_FILES_TABLE)?;
     }
     write_txn.commit()?;
 
@@ -78,6 +85,8 @@ pub fn create_app_with_arc(db: Arc<Database>) -> Router {
         .route("/health", get(health_check))
         .route("/blobs/store", post(store_blobs))
         .route("/blobs/get", post(get_blobs_for_topics))
+        .route("/chunks/store", post(store_chunk))
+        .route("/chunks/get", post(get_chunk))
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
         .with_state(state)
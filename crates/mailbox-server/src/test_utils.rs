@@ -2,7 +2,7 @@ use axum_test::{TestServer, TestServerConfig, Transport};
 use redb::Database;
 use tempfile::NamedTempFile;
 
-use crate::{create_app, BLOBS_TABLE, WATERMARKS_TABLE};
+use crate::{create_app, BLOBS_TABLE, CHUNKS_TABLE, CHUNK_FILES_TABLE, WATERMARKS_TABLE};
 
 pub fn create_test_db() -> (Database, NamedTempFile) {
     let temp_file = NamedTempFile::new().unwrap();
@@ -12,6 +12,8 @@ pub fn create_test_db() -> (Database, NamedTempFile) {
     {
         let _blobs_table = write_txn.open_table(BLOBS_TABLE).unwrap();
         let _watermarks_table = write_txn.open_table(WATERMARKS_TABLE).unwrap();
+        let _chunks_table = write_txn.open_table(CHUNKS_TABLE).unwrap();
+        let _chunk_files_table = write_txn.open_table(CHUNK_FILES_TABLE).unwrap();
     }
     write_txn.commit().unwrap();
 
@@ -1,4 +1,4 @@
-use mailbox_server::{test_utils::create_test_server, GetBlobsResponse};
+use mailbox_server::{test_utils::create_test_server, GetBlobsResponse, GetChunkResponse};
 use serde_json::json;
 
 #[tokio::test]
@@ -554,3 +554,30 @@ async fn test_no_missing_when_server_is_ahead() {
     // No missing since server is ahead
     assert!(topic_response.missing.is_empty());
 }
+
+#[tokio::test]
+async fn test_store_and_retrieve_chunk() {
+    let (server, _temp_file) = create_test_server();
+
+    let chunk_b64 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, b"chunk");
+
+    server
+        .post("/chunks/store")
+        .json(&json!({ "file": "file-1", "index": 1, "data": chunk_b64 }))
+        .await
+        .assert_status(axum::http::StatusCode::CREATED);
+
+    let body: GetChunkResponse = server
+        .post("/chunks/get")
+        .json(&json!({ "file": "file-1", "index": 1 }))
+        .await
+        .json();
+    assert_eq!(body.data.unwrap().as_ref(), b"chunk");
+
+    let body: GetChunkResponse = server
+        .post("/chunks/get")
+        .json(&json!({ "file": "file-1", "index": 0 }))
+        .await
+        .json();
+    assert!(body.data.is_none());
+}
@@ -1,20 +1,24 @@
 import { invoke } from '@tauri-apps/api/core';
 
-import { ChatId } from '../types';
+import { ChatId, ChatSummary } from '../types';
 
 export interface IChatsClient {
-	createGroupChat(chatId: ChatId): Promise<void>;
+	createGroupChat(): Promise<ChatId>;
 	getGroupChats(): Promise<Array<ChatId>>;
+	/// All chats, most recently active first
+	getChatSummaries(): Promise<Array<ChatSummary>>;
 }
 
 export class ChatsClient implements IChatsClient {
-	createGroupChat(groupChatId: ChatId): Promise<void> {
-		return invoke('create_group_chat', {
-			groupChatId,
-		});
+	createGroupChat(): Promise<ChatId> {
+		return invoke('create_group_chat');
 	}
 
 	getGroupChats(): Promise<Array<ChatId>> {
 		return invoke('get_group_chats');
 	}
+
+	getChatSummaries(): Promise<Array<ChatSummary>> {
+		return invoke('get_chat_summaries');
+	}
 }
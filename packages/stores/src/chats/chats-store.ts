@@ -10,15 +10,6 @@ import { AgentId, PublicKey, TopicId } from '../p2panda/types';
 import { ChatId, Payload } from '../types';
 import { ChatsClient } from './chats-client';
 
-function random_hexadecimal(length: number) {
-	var result = '';
-	var characters = 'abcdef0123456789';
-	var charactersLength = characters.length;
-	for (let i = 0; i < length; i++)
-		result += characters.charAt(Math.floor(Math.random() * charactersLength));
-	return result;
-}
-
 export class ChatsStore {
 	constructor(
 		protected logsStore: LogsStore<Payload>,
@@ -26,10 +17,8 @@ export class ChatsStore {
 		public client: ChatsClient,
 	) {}
 
-	async createGroup(initialMembers: PublicKey[]): Promise<GroupChatStore> {
-		const chatId = random_hexadecimal(64);
-
-		await this.client.createGroupChat(chatId);
+	async createGroup(initialMembers: AgentId[]): Promise<GroupChatStore> {
+		const chatId = await this.client.createGroupChat();
 
 		const groupStore = this.groupChats(chatId);
 
@@ -86,7 +75,8 @@ export class ChatsStore {
 				type: 'ContactRequest',
 				chatId: pendingRequest.code.agent_id,
 				name: pendingRequest.profile.name,
-				avatar: pendingRequest.profile.avatar,
+				// Avatars are only fetched for contacts
+				avatar: undefined,
 				lastEvent: {
 					summary: '',
 					timestamp: pendingRequest.timestamp,
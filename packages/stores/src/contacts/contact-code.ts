@@ -4,24 +4,28 @@ import { decode, encode } from 'cbor-web';
 
 import { ContactCode } from '../types';
 
+/// Contact codes are a CBOR map keyed by field name, so that fields can be added later:
+/// codes without the optional fields still decode, and unknown fields are skipped.
 export function encodeContactCode(contactCode: ContactCode): string {
-	const bin = encode([
-		contactCode.device_pubkey,
-		contactCode.agent_id,
-		contactCode.inbox_topic,
-		contactCode.share_intent,
-	]);
+	const { inbox_topic, inbox_key, ...required } = contactCode;
+	const bin = encode({
+		...required,
+		...(inbox_topic !== undefined && { inbox_topic }),
+		...(inbox_key !== undefined && { inbox_key }),
+	});
 	return fromByteArray(bin);
 }
 
 export function decodeContactCode(contactCodeString: string): ContactCode {
 	const bin = toByteArray(contactCodeString);
-	const [device_pubkey, agent_id, inbox_topic, share_intent] = decode(bin);
+	const code = decode(bin);
 	return {
-		device_pubkey,
-		agent_id,
-		inbox_topic,
-		share_intent,
+		device_pubkey: code.device_pubkey,
+		key_bundle: code.key_bundle,
+		agent_id: code.agent_id,
+		inbox_topic: code.inbox_topic ?? undefined,
+		inbox_key: code.inbox_key ?? undefined,
+		share_intent: code.share_intent,
 	};
 }
 
@@ -1,21 +1,51 @@
 import { invoke } from '@tauri-apps/api/core';
 
-import { AgentId, type TopicId } from '../p2panda/types';
+import { AgentId, Hash, type TopicId } from '../p2panda/types';
 import { ContactCode } from '../types';
 
+/// A profile as it's displayed, with the avatar as a data URL
 export interface Profile {
 	name: string;
 	avatar: string | undefined;
 }
 
+/// A thumbnail image, referred to by the hash of its content
+export interface Avatar {
+	hash: Hash;
+	size: number;
+}
+
+/// A profile as it's sent in payloads
+export interface ProfilePayload {
+	name: string;
+	avatar: Avatar | undefined;
+}
+
+/// One of my contacts, with the latest profile they announced
+export interface Contact {
+	agent_id: AgentId;
+	profile: ProfilePayload | undefined;
+}
+
+/// A contact request which hasn't been accepted or rejected yet
+export interface PendingContactRequest {
+	code: ContactCode;
+	profile: ProfilePayload;
+	// ISO 8601 date at which the inbox it arrived in expires
+	expires_at: string;
+}
+
 export interface IContactsClient {
 	/// Profiles
 
 	myAgentId(): Promise<AgentId>;
 
-	// Sets the profile for this user
+	// Sets the profile for this user, turning the avatar into a thumbnail
 	setProfile(profile: Profile): Promise<void>;
 
+	// The avatar of the given agent as a data URL
+	getAvatar(agentId: AgentId): Promise<string | undefined>;
+
 	/// contacts
 
 	// Creates a new contact code to be shared
@@ -23,16 +53,23 @@ export interface IContactsClient {
 
 	activeInboxTopics(): Promise<TopicId[]>
 
-	// getContacts(): Promise<Array<PublicKey>>;
+	// My contacts across all my devices
+	getContacts(): Promise<Array<Contact>>;
 
 	// Add contact
 	addContact(code: ContactCode): Promise<void>;
 
+	// Contact requests which haven't been accepted or rejected on any of my devices
+	getPendingContactRequests(): Promise<Array<PendingContactRequest>>;
+
+	// Accept the pending contact request from the given agent
+	acceptContactRequest(agentId: AgentId): Promise<void>;
+
 	// Reject contact request
 	rejectContactRequest(agentId: AgentId): Promise<void>;
 
 	// Remove contact
-	// removeContact(contact: ContactId): Promise<void>;
+	removeContact(agentId: AgentId): Promise<void>;
 
 	/// Contact Requests
 
@@ -55,11 +92,22 @@ export class ContactsClient implements IContactsClient {
 	}
 
 	async setProfile(profile: Profile): Promise<void> {
+		const avatar = profile.avatar
+			? Array.from(
+					new Uint8Array(await (await fetch(profile.avatar)).arrayBuffer()),
+				)
+			: undefined;
 		return invoke('set_profile', {
-			profile,
+			name: profile.name,
+			avatar,
 		});
 	}
 
+	async getAvatar(agentId: AgentId): Promise<string | undefined> {
+		const avatar: string | null = await invoke('get_avatar', { agentId });
+		return avatar ?? undefined;
+	}
+
 	createContactCode(): Promise<ContactCode> {
 		return invoke('create_contact_code');
 	}
@@ -74,19 +122,29 @@ export class ContactsClient implements IContactsClient {
 		});
 	}
 
+	getPendingContactRequests(): Promise<Array<PendingContactRequest>> {
+		return invoke('get_pending_contact_requests');
+	}
+
+	acceptContactRequest(agentId: AgentId): Promise<void> {
+		return invoke('accept_contact_request', {
+			agentId,
+		});
+	}
+
 	rejectContactRequest(agentId: AgentId): Promise<void> {
 		return invoke('reject_contact_request', {
 			agentId,
 		});
 	}
 
-	// getContacts(): Promise<Array<PublicKey>> {
-	// 	return invoke('get_contacts');
-	// }
+	getContacts(): Promise<Array<Contact>> {
+		return invoke('get_contacts');
+	}
 
-	// removeContact(contactId: ContactId): Promise<void> {
-	// 	return invoke('remove_contact', {
-	// 		contactId,
-	// 	});
-	// }
+	removeContact(agentId: AgentId): Promise<void> {
+		return invoke('remove_contact', {
+			agentId,
+		});
+	}
 }
@@ -6,10 +6,10 @@ import { SimplifiedOperation } from '../p2panda/simplified-types';
 import { AgentId, PublicKey, TopicId } from '../p2panda/types';
 import { personalTopicFor } from '../topics';
 import { AnnouncementPayload, ContactCode, Payload } from '../types';
-import { IContactsClient, Profile } from './contacts-client';
+import { IContactsClient, Profile, ProfilePayload } from './contacts-client';
 
 export interface ContactRequest {
-	profile: Profile;
+	profile: ProfilePayload;
 	code: ContactCode;
 	timestamp: number;
 }
@@ -155,7 +155,7 @@ export class ContactsStore {
 		const log: SimplifiedOperation<Payload>[] =
 			Object.values(operations)[0] || [];
 
-		const setProfiles: Array<[number, Profile]> = log
+		const setProfiles: Array<[number, ProfilePayload]> = log
 			.filter(
 				l =>
 					l.body?.type === 'Announcements' &&
@@ -175,7 +175,12 @@ export class ContactsStore {
 			return undefined;
 		}
 
-		const profile: Profile = lastOperation[1];
+		const profile: Profile = {
+			name: lastOperation[1].name,
+			avatar: lastOperation[1].avatar
+				? await this.client.getAvatar(agentId)
+				: undefined,
+		};
 		return profile;
 	});
 
@@ -1,7 +1,8 @@
 // Common error variants shared across multiple error types
 export type Error =
 	| { kind: 'InitializeTopic'; message: string }
-	| { kind: 'AuthorOperation'; message: string };
+	| { kind: 'AuthorOperation'; message: string }
+	| { kind: 'UploadAvatar'; message: string };
 
 export type AddContactError =
 	| { kind: 'ProfileNotCreated'; message: null }
@@ -1,14 +1,64 @@
 import { invoke } from '@tauri-apps/api/core';
 
-import { type TopicId } from '../p2panda/types';
+import { type AgentId, type DeviceId, type TopicId } from '../p2panda/types';
+import { type ContactCode } from '../types';
+
+/// A device which asked to be linked to my agent, waiting for approval
+export interface PendingDeviceRequest {
+	device: DeviceId;
+	/// Shown on the requesting device too, to check that it's the right one
+	verification_code: string;
+	// ISO 8601 date at which the inbox it arrived in expires
+	expires_at: string;
+}
 
 export interface IDevicesClient {
 
 	myDeviceGroupTopicId(): Promise<TopicId>;
+	/// A code for a new device to scan, to link it to my agent
+	createDeviceCode(): Promise<ContactCode>;
+	/// Ask the device which shows the code to link this device to its agent
+	linkDevice(deviceCode: ContactCode): Promise<AgentId>;
+	/// The code to show while this device is being linked
+	verificationCode(): Promise<string>;
+	getPendingDeviceRequests(): Promise<Array<PendingDeviceRequest>>;
+	/// Link a device which asked to be linked, after checking its verification code
+	approveDevice(device: DeviceId): Promise<void>;
+	myDevices(): Promise<Array<DeviceId>>;
+	/// Revoke another of my devices, so that what it writes from now on is ignored
+	removeDevice(device: DeviceId): Promise<void>;
 }
 
 export class DevicesClient implements IDevicesClient {
 	myDeviceGroupTopicId(): Promise<TopicId> {
 		return invoke('my_device_group_topic');
 	}
+
+	createDeviceCode(): Promise<ContactCode> {
+		return invoke('create_device_code');
+	}
+
+	linkDevice(deviceCode: ContactCode): Promise<AgentId> {
+		return invoke('link_device', { deviceCode });
+	}
+
+	verificationCode(): Promise<string> {
+		return invoke('verification_code');
+	}
+
+	getPendingDeviceRequests(): Promise<Array<PendingDeviceRequest>> {
+		return invoke('get_pending_device_requests');
+	}
+
+	approveDevice(device: DeviceId): Promise<void> {
+		return invoke('approve_device', { device });
+	}
+
+	myDevices(): Promise<Array<DeviceId>> {
+		return invoke('my_devices');
+	}
+
+	removeDevice(device: DeviceId): Promise<void> {
+		return invoke('remove_device', { device });
+	}
 }
@@ -1,7 +1,20 @@
 import { invoke } from '@tauri-apps/api/core';
-
-import { AgentId, PublicKey, TopicId } from '../p2panda/types';
-import { ChatId, MessageContent, Payload } from '../types';
+import { listen } from '@tauri-apps/api/event';
+import { type UnsubscribeFunction } from 'emittery';
+
+import { AgentId, Hash, PublicKey, TopicId } from '../p2panda/types';
+import {
+	ChatId,
+	DownloadProgress,
+	EditedMessage,
+	EphemeralEvent,
+	HistoryPage,
+	MessageContent,
+	MessageStatus,
+	Payload,
+	ReactionCount,
+	SearchResult,
+} from '../types';
 
 export interface Message {
 	content: MessageContent;
@@ -11,7 +24,7 @@ export interface Message {
 
 export interface IGroupChatClient {
 	/// Members
-	addMember(chatId: ChatId, member: PublicKey): Promise<void>;
+	addMember(chatId: ChatId, member: AgentId): Promise<void>;
 	removeMember(chatId: ChatId, member: PublicKey): Promise<void>;
 
 	promoteToAdministrator(chatId: ChatId, member: AgentId): Promise<void>;
@@ -20,13 +33,56 @@ export interface IGroupChatClient {
 	/// Messages
 
 	sendMessage(chatId: ChatId, content: MessageContent): Promise<void>;
-
-	leaveGroup(): Promise<void>;
+	editMessage(
+		chatId: ChatId,
+		target: Hash,
+		content: MessageContent,
+	): Promise<void>;
+	deleteMessage(chatId: ChatId, target: Hash): Promise<void>;
+	sendAttachment(
+		chatId: ChatId,
+		data: Uint8Array,
+		mime: string,
+		name: string,
+	): Promise<void>;
+	/// Returns the local path of the attachment's file
+	downloadAttachment(chatId: ChatId, message: Hash): Promise<string>;
+	onDownloadProgress(
+		handler: (progress: DownloadProgress) => void,
+	): UnsubscribeFunction;
+	markRead(chatId: ChatId, upTo: Hash): Promise<void>;
+	sendTyping(chatId: ChatId): Promise<void>;
+	onEphemeral(handler: (event: EphemeralEvent) => void): UnsubscribeFunction;
+	messageStatus(chatId: ChatId, hash: Hash): Promise<MessageStatus>;
+	readReceiptsEnabled(chatId: ChatId): Promise<boolean>;
+	setReadReceiptsEnabled(chatId: ChatId, enabled: boolean): Promise<void>;
+	getEditedMessage(
+		chatId: ChatId,
+		hash: Hash,
+	): Promise<EditedMessage | undefined>;
+	/// A page of messages before or after the given cursors, or the latest ones
+	getMessageHistory(
+		chatId: ChatId,
+		page?: { before?: Hash; after?: Hash; limit?: number },
+	): Promise<HistoryPage>;
+	/// The current reactions to each of the messages, by message hash
+	getReactions(
+		chatId: ChatId,
+		targets: Array<Hash>,
+	): Promise<Record<Hash, Array<ReactionCount>>>;
+	/// Messages matching the query, newest first, in one chat or in all of them
+	searchMessages(
+		query: string,
+		chatId?: ChatId,
+		limit?: number,
+	): Promise<Array<SearchResult>>;
+
+	leaveGroup(chatId: ChatId): Promise<void>;
 	deleteGroup(): Promise<void>;
 }
 
 export class GroupChatClient implements IGroupChatClient {
-	addMember(chatId: ChatId, member: PublicKey): Promise<void> {
+	addMember(chatId: ChatId, member: AgentId): Promise<void> {
 		return invoke('add_member', {
 			chatId,
 			member,
@@ -34,8 +90,100 @@ export class GroupChatClient implements IGroupChatClient {
 	}
 	async removeMember(chatId: ChatId, member: PublicKey): Promise<void> {}
 
-	sendMessage(topic: ChatId, content: MessageContent): Promise<void> {
-		return invoke('send_message', { topic, content });
+	sendMessage(chatId: ChatId, content: MessageContent): Promise<void> {
+		return invoke('send_message', { chatId, content });
+	}
+	editMessage(
+		chatId: ChatId,
+		target: Hash,
+		content: MessageContent,
+	): Promise<void> {
+		return invoke('edit_message', { chatId, target, content });
+	}
+	deleteMessage(chatId: ChatId, target: Hash): Promise<void> {
+		return invoke('delete_message', { chatId, target });
+	}
+	sendAttachment(
+		chatId: ChatId,
+		data: Uint8Array,
+		mime: string,
+		name: string,
+	): Promise<void> {
+		return invoke('send_attachment', {
+			chatId,
+			data: Array.from(data),
+			mime,
+			name,
+		});
+	}
+	downloadAttachment(chatId: ChatId, message: Hash): Promise<string> {
+		return invoke('download_attachment', { chatId, message });
+	}
+	onDownloadProgress(
+		handler: (progress: DownloadProgress) => void,
+	): UnsubscribeFunction {
+		let unsubs: (() => void) | undefined;
+		listen('p2panda://download-progress', e => {
+			handler(e.payload as DownloadProgress);
+		}).then(u => (unsubs = u));
+
+		return () => {
+			if (unsubs) unsubs();
+		};
+	}
+	markRead(chatId: ChatId, upTo: Hash): Promise<void> {
+		return invoke('mark_read', { chatId, upTo });
+	}
+	sendTyping(chatId: ChatId): Promise<void> {
+		return invoke('send_typing', { chatId });
+	}
+	onEphemeral(handler: (event: EphemeralEvent) => void): UnsubscribeFunction {
+		let unsubs: (() => void) | undefined;
+		listen('p2panda://ephemeral', e => {
+			handler(e.payload as EphemeralEvent);
+		}).then(u => (unsubs = u));
+
+		return () => {
+			if (unsubs) unsubs();
+		};
+	}
+	messageStatus(chatId: ChatId, hash: Hash): Promise<MessageStatus> {
+		return invoke('message_status', { chatId, hash });
+	}
+	readReceiptsEnabled(chatId: ChatId): Promise<boolean> {
+		return invoke('read_receipts_enabled', { chatId });
+	}
+	setReadReceiptsEnabled(chatId: ChatId, enabled: boolean): Promise<void> {
+		return invoke('set_read_receipts_enabled', { chatId, enabled });
+	}
+	async getEditedMessage(
+		chatId: ChatId,
+		hash: Hash,
+	): Promise<EditedMessage | undefined> {
+		const message: EditedMessage | null = await invoke('get_edited_message', {
+			chatId,
+			hash,
+		});
+		return message ?? undefined;
+	}
+	getMessageHistory(
+		chatId: ChatId,
+		page: { before?: Hash; after?: Hash; limit?: number } = {},
+	): Promise<HistoryPage> {
+		return invoke('get_message_history', { chatId, ...page });
+	}
+	getReactions(
+		chatId: ChatId,
+		targets: Array<Hash>,
+	): Promise<Record<Hash, Array<ReactionCount>>> {
+		return invoke('get_reactions', { chatId, targets });
+	}
+	searchMessages(
+		query: string,
+		chatId?: ChatId,
+		limit?: number,
+	): Promise<Array<SearchResult>> {
+		return invoke('search_messages', { query, chatId, limit });
 	}
 	async promoteToAdministrator(
 		chatId: ChatId,
@@ -46,7 +194,8 @@ export class GroupChatClient implements IGroupChatClient {
 		member: AgentId,
 	): Promise<void> {}
 
-	async leaveGroup(): Promise<void> {
+	leaveGroup(chatId: ChatId): Promise<void> {
+		return invoke('leave_group', { chatId });
 	}
 
 	async deleteGroup(): Promise<void> {}
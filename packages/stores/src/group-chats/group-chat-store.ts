@@ -3,7 +3,7 @@ import { ReactivePromise, reactive } from 'signalium';
 import { Profile } from '../contacts/contacts-client';
 import { ContactsStore } from '../contacts/contacts-store';
 import { LogsStore } from '../p2panda/logs-store';
-import { AgentId, PublicKey } from '../p2panda/types';
+import { AgentId, DeviceId, Hash, PublicKey } from '../p2panda/types';
 import { ChatId, MessageContent, Payload } from '../types';
 import { GroupChatClient, Message } from './group-chat-client';
 
@@ -36,135 +36,20 @@ export class GroupChatStore {
 		return info;
 	});
 
+	/// The latest page of text messages, from the chat's history in the node,
+	/// which get_message_history serves in place of the former get_messages command
 	messages = reactive(async () => {
-		// const allLogs = await this.logsStore.logsForAllAuthors(this.chatId);
-		// const messages = await invoke('get_messages', {
-		// 	chatId: this.chatId,
-		// });
-		// const messages : Array<SimplifiedOperation<ChatMessageContent>> = [{
-		// 	hash: '',
-		// 	header: {
-
-		// 	}
-		// }]
-		const messages: Array<Message> = [
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-			{
-				content: 'heeey',
-				author: await this.contactsStore.myAgentId(),
-				timestamp: Date.now(),
-			},
-		];
-
+		const page = await this.client.getMessageHistory(this.chatId);
+		const messages: Array<Message> = [];
+		for (const message of page.messages) {
+			if (message.content.type === 'Text' && message.author) {
+				messages.push({
+					content: message.content.payload.content,
+					author: message.author,
+					timestamp: message.timestamp,
+				});
+			}
+		}
 		return messages;
 	});
 
@@ -208,11 +93,54 @@ export class GroupChatStore {
 
 	/// Actions
 
-	addMember(member: PublicKey) {
+	addMember(member: AgentId) {
 		return this.client.addMember(this.chatId, member);
 	}
 
 	sendMessage(content: MessageContent) {
 		return this.client.sendMessage(this.chatId, content);
 	}
+
+	editMessage(target: Hash, content: MessageContent) {
+		return this.client.editMessage(this.chatId, target, content);
+	}
+
+	deleteMessage(target: Hash) {
+		return this.client.deleteMessage(this.chatId, target);
+	}
+
+	async sendAttachment(file: File) {
+		const data = new Uint8Array(await file.arrayBuffer());
+		return this.client.sendAttachment(
+			this.chatId,
+			data,
+			file.type || 'application/octet-stream',
+			file.name,
+		);
+	}
+
+	downloadAttachment(message: Hash) {
+		return this.client.downloadAttachment(this.chatId, message);
+	}
+
+	markRead(upTo: Hash) {
+		return this.client.markRead(this.chatId, upTo);
+	}
+
+	sendTyping() {
+		return this.client.sendTyping(this.chatId);
+	}
+
+	/// Calls the handler with the device of whoever is typing in this chat
+	onTyping(handler: (author: DeviceId) => void) {
+		return this.client.onEphemeral(event => {
+			if (event.chat_id === this.chatId && event.payload.type === 'Typing') {
+				handler(event.author);
+			}
+		});
+	}
+
+	leaveGroup() {
+		return this.client.leaveGroup(this.chatId);
+	}
 }
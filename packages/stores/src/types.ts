@@ -1,10 +1,11 @@
-import { Profile } from './contacts/contacts-client';
+import { ProfilePayload } from './contacts/contacts-client';
 import {
 	AgentId,
 	DeviceId,
 	Hash,
 	LongTermKeyBundle,
 	PublicKey,
+	Signature,
 	TopicId,
 } from './p2panda/types';
 
@@ -17,9 +18,161 @@ export interface SpaceControlMessage {
 	// spaces_args: SpacesArgs,
 }
 
-export type MessageContent = string;
-export type AnnouncementPayload = { type: 'SetProfile'; payload: Profile };
-export type ChatPayload = { type: 'Message'; payload: MessageContent };
+/// Plain text, or text replying to the message with the given header hash
+export type MessageContent = string | { text: string; reply_to: Hash };
+
+export function messageText(content: MessageContent): string {
+	return typeof content === 'string' ? content : content.text;
+}
+/// The signed prekey which payloads for a device are sealed to,
+/// signed once more by the device itself
+export interface KeyBundle {
+	bundle: LongTermKeyBundle;
+	signature: Signature;
+}
+
+/// The latest operation of a log which a revoking device had seen
+export interface LogHeight {
+	topic: TopicId;
+	seq_num: number;
+}
+
+export type AnnouncementPayload =
+	| { type: 'SetProfile'; payload: ProfilePayload }
+	| { type: 'AddDevice'; payload: DeviceId }
+	| {
+			type: 'RemoveDevice';
+			payload: { device: DeviceId; up_to: Array<LogHeight> };
+	  }
+	| { type: 'KeyBundle'; payload: KeyBundle };
+export type ChatPayload =
+	| { type: 'Message'; payload: MessageContent }
+	| {
+			type: 'Attachment';
+			payload: { hash: Hash; size: number; mime: string; name: string };
+	  }
+	| { type: 'Edit'; payload: { target: Hash; content: MessageContent } }
+	| { type: 'Delete'; payload: { target: Hash } }
+	| { type: 'Receipt'; payload: Receipt };
+
+export type Receipt =
+	| { type: 'Delivered'; payload: { up_to: Hash } }
+	| { type: 'Read'; payload: { up_to: Hash } };
+
+/// Progress of downloading the file of an attachment
+export interface DownloadProgress {
+	hash: Hash;
+	received: number;
+	size: number;
+}
+
+/// Never stored, only seen by the members who are online
+export type EphemeralPayload = { type: 'Typing' };
+
+export interface EphemeralEvent {
+	chat_id: ChatId;
+	author: DeviceId;
+	payload: EphemeralPayload;
+}
+
+export interface MessageStatus {
+	/// Whether the message was published to a mailbox
+	published: boolean;
+	/// Devices which have received the message
+	delivered_to: Array<DeviceId>;
+	/// Agents who have read the message
+	read_by: Array<AgentId>;
+}
+
+export interface MessageVersion {
+	content: MessageContent;
+	author: DeviceId;
+	timestamp: number;
+	hash: Hash;
+}
+
+export interface EditedMessage {
+	/// Hash of the header of the original message
+	hash: Hash;
+	/// Content of the latest version
+	content: MessageContent;
+	/// All versions, oldest first, starting with the original message
+	history: Array<MessageVersion>;
+}
+
+/// The message a reply refers to
+export interface QuotedMessage {
+	hash: Hash;
+	/// Undefined if the message hasn't arrived yet or was deleted
+	author: DeviceId | undefined;
+	excerpt: string | undefined;
+}
+
+export interface Attachment {
+	hash: Hash;
+	size: number;
+	mime: string;
+	name: string;
+}
+
+/// Everyone who currently reacts to a message with the same emoji
+export interface ReactionCount {
+	emoji: string;
+	count: number;
+	/// Agents I know of, so there may be fewer than the count
+	agents: Array<AgentId>;
+}
+
+export type HistoryContent =
+	| { type: 'Text'; payload: EditedMessage }
+	| { type: 'Attachment'; payload: Attachment };
+
+/// A message with its edits and reactions applied
+export interface HistoryMessage {
+	hash: Hash;
+	/// Undefined if I don't know the agent of the device
+	author: AgentId | undefined;
+	device: DeviceId;
+	timestamp: number;
+	content: HistoryContent;
+	quoted: QuotedMessage | undefined;
+	reactions: Array<ReactionCount>;
+}
+
+/// A page of a chat's history, oldest message first
+export interface HistoryPage {
+	messages: Array<HistoryMessage>;
+	/// Whether there are more messages beyond the page
+	has_more: boolean;
+}
+
+/// A message whose latest version matches a search
+export interface SearchResult {
+	chat_id: ChatId;
+	hash: Hash;
+	author: DeviceId;
+	timestamp: number;
+	/// The part of the message around the first matching word
+	snippet: string;
+}
+
+/// The start of a message, as shown in the chat list
+export interface MessagePreview {
+	hash: Hash;
+	/// Undefined if I don't know the agent of the device
+	author: AgentId | undefined;
+	device: DeviceId;
+	excerpt: string;
+}
+
+/// What the chat list shows for a chat
+export interface ChatSummary {
+	chat_id: ChatId;
+	/// Messages from others after the latest one I marked as read
+	unread: number;
+	last_message: MessagePreview | undefined;
+	last_activity: number | undefined;
+}
 
 export interface InboxTopic {
 	expires_at: number;
@@ -31,24 +184,39 @@ export type ShareIntent = 'AddDevice' | 'AddContact';
 export interface ContactCode {
 	/// Pubkey of this node: allows adding this node to groups.
 	device_pubkey: DeviceId;
+	/// The key bundle of this node, so that direct chat payloads can be sealed for it
+	key_bundle: KeyBundle;
 	/// Agent ID to add to spaces
 	agent_id: AgentId;
 	inbox_topic: InboxTopic | undefined;
+	/// The ephemeral key which what is sent to the inbox is sealed to
+	inbox_key: KeyBundle | undefined;
 	/// The intent of the QR code: whether to add this node as a contact or a device.
 	share_intent: ShareIntent;
 }
 
 export type DeviceGroupPayload =
 	| { type: 'AddContact'; payload: ContactCode }
-	| { type: 'RejectContactRequest'; payload: AgentId };
-
-export type InboxPayload = {
-	type: 'ContactRequest';
-	payload: {
-		code: ContactCode;
-		profile: Profile;
-	};
-};
+	| { type: 'RejectContactRequest'; payload: AgentId }
+	| { type: 'MarkRead'; payload: { chat_id: ChatId; up_to: Hash } }
+	| { type: 'AddDevice'; payload: DeviceId }
+	| {
+			type: 'RemoveDevice';
+			payload: { device: DeviceId; up_to: Array<LogHeight> };
+	  }
+	| { type: 'MoveDeviceGroup'; payload: TopicId }
+	| { type: 'Backfill'; payload: { op: Hash; payload: ChatPayload } }
+	| { type: 'JoinGroup'; payload: ChatId };
+
+export type InboxPayload =
+	| {
+			type: 'ContactRequest';
+			payload: {
+				code: ContactCode;
+				profile: ProfilePayload;
+			};
+	  }
+	| { type: 'DeviceRequest'; payload: { key_bundle: KeyBundle } };
 
 export type Payload =
 	| { type: 'Announcements'; payload: AnnouncementPayload }
@@ -1,13 +1,22 @@
-// #[command]
-// pub async fn create_group_chat(group_chat_id: GroupChatId, node: State<'_, Node>) -> Result<(), String> {
-//     node.create_group_chat_space(group_chat_id)
-//         .await
-//         .map_err(|e| format!("Failed to create group: {e:?}"))
-// }
+use dashchat_node::{ChatId, ChatSummary, Node};
+use tauri::State;
 
-// #[command]
-// pub async fn get_group_chats(node: State<'_, Node>) -> Result<Vec<ChatId>, String> {
-//     node.get_groups()
-//         .await
-//         .map_err(|e| format!("Failed to get groups: {e:?}"))
-// }
+#[tauri::command]
+pub async fn create_group_chat(node: State<'_, Node>) -> Result<ChatId, String> {
+    node.create_group_chat()
+        .await
+        .map_err(|e| format!("Failed to create group: {e:?}"))
+}
+
+#[tauri::command]
+pub fn get_group_chats(node: State<'_, Node>) -> Result<Vec<ChatId>, String> {
+    node.get_group_chats()
+        .map_err(|e| format!("Failed to get groups: {e:?}"))
+}
+
+#[tauri::command]
+pub async fn get_chat_summaries(node: State<'_, Node>) -> Result<Vec<ChatSummary>, String> {
+    node.chat_summaries()
+        .await
+        .map_err(|e| format!("Failed to get chat summaries: {e:?}"))
+}
@@ -1,5 +1,6 @@
 use dashchat_node::{
-    topic::kind::Inbox, AddContactError, AgentId, Error, Node, QrCode, ShareIntent, Topic,
+    topic::kind::Inbox, AddContactError, AgentId, Contact, Error, Node, PendingContactRequest,
+    QrCode, ShareIntent, Topic,
 };
 use std::collections::BTreeSet;
 use tauri::State;
@@ -31,29 +32,42 @@ pub fn active_inbox_topics(node: State<'_, Node>) -> Result<BTreeSet<Topic<Inbox
     Ok(topics_ids)
 }
 
+#[tauri::command]
+pub async fn get_pending_contact_requests(
+    node: State<'_, Node>,
+) -> Result<Vec<PendingContactRequest>, String> {
+    let requests = node
+        .pending_contact_requests()
+        .await
+        .map_err(|e| format!("Failed to get pending contact requests: {e:?}"))?;
+
+    Ok(requests)
+}
+
+#[tauri::command]
+pub async fn accept_contact_request(
+    agent_id: AgentId,
+    node: State<'_, Node>,
+) -> Result<(), AddContactError> {
+    node.accept_contact_request(agent_id).await
+}
+
 #[tauri::command]
 pub async fn reject_contact_request(agent_id: AgentId, node: State<'_, Node>) -> Result<(), Error> {
     node.reject_contact_request(agent_id).await
 }
 
-// #[tauri::command]
-// pub async fn remove_contact(
-//     contact_id: PublicKey,
-//     node: State<'_, Node>,
-// ) -> Result<PublicKey, String> {
-//     node.remove_contact(contact_id.into())
-//         .await
-//         .map_err(|e| format!("Failed to remove contact: {e:?}"))
-// }
-
-// #[tauri::command]
-// pub async fn get_contacts(node: State<'_, Node>) -> Result<Vec<PublicKey>, String> {
-//     let pks = node
-//         .get_contacts()
-//         .await
-//         .map_err(|e| format!("Failed to get my contacts: {e:?}"))?;
+#[tauri::command]
+pub async fn remove_contact(agent_id: AgentId, node: State<'_, Node>) -> Result<(), Error> {
+    node.remove_contact(agent_id).await
+}
 
-//     let pks = pks.into_iter().map(|pk| pk.into()).collect();
+#[tauri::command]
+pub async fn get_contacts(node: State<'_, Node>) -> Result<Vec<Contact>, String> {
+    let contacts = node
+        .contacts()
+        .await
+        .map_err(|e| format!("Failed to get my contacts: {e:?}"))?;
 
-//     Ok(pks)
-// }
+    Ok(contacts)
+}
@@ -1,7 +1,59 @@
-use dashchat_node::{topic::kind, Node, Topic};
+use dashchat_node::{
+    topic::kind, AddContactError, AgentId, DeviceId, Error, Node, PendingDeviceRequest, QrCode,
+    ShareIntent, Topic,
+};
+use std::collections::BTreeSet;
 use tauri::State;
 
 #[tauri::command]
 pub fn my_device_group_topic(node: State<'_, Node>) -> Topic<kind::DeviceGroup> {
     node.device_group_topic()
 }
+
+#[tauri::command]
+pub async fn create_device_code(node: State<'_, Node>) -> Result<QrCode, Error> {
+    node.new_qr_code(ShareIntent::AddDevice, true).await
+}
+
+#[tauri::command]
+pub async fn link_device(
+    device_code: QrCode,
+    node: State<'_, Node>,
+) -> Result<AgentId, AddContactError> {
+    node.link_device(device_code).await
+}
+
+#[tauri::command]
+pub fn verification_code(node: State<'_, Node>) -> String {
+    node.verification_code()
+}
+
+#[tauri::command]
+pub async fn get_pending_device_requests(
+    node: State<'_, Node>,
+) -> Result<Vec<PendingDeviceRequest>, String> {
+    node.pending_device_requests()
+        .await
+        .map_err(|e| format!("Failed to get device requests: {e:?}"))
+}
+
+#[tauri::command]
+pub async fn approve_device(device: DeviceId, node: State<'_, Node>) -> Result<(), String> {
+    node.approve_device(device)
+        .await
+        .map_err(|e| format!("Failed to approve device: {e:?}"))
+}
+
+#[tauri::command]
+pub async fn my_devices(node: State<'_, Node>) -> Result<BTreeSet<DeviceId>, String> {
+    node.my_devices()
+        .await
+        .map_err(|e| format!("Failed to get devices: {e:?}"))
+}
+
+#[tauri::command]
+pub async fn remove_device(device: DeviceId, node: State<'_, Node>) -> Result<(), String> {
+    node.remove_device(device)
+        .await
+        .map_err(|e| format!("Failed to remove device: {e:?}"))
+}
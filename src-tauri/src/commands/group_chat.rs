@@ -1,36 +1,211 @@
-// use dashchat_node::{ChatId, ChatMessage, ChatMessageContent, Node};
-// use tauri::{command, State};
-
-// #[command]
-// pub async fn add_member(
-//     chat_id: ChatId,
-//     repped_group: ReppedGroup,
-//     node: State<'_, Node>,
-// ) -> Result<(), String> {
-//     node.add_member(chat_id, repped_group)
-//         .await
-//         .map_err(|e| format!("Failed to add member: {e:?}"))
-// }
-
-// #[command]
-// pub async fn send_message(
-//     chat_id: ChatId,
-//     content: ChatMessageContent,
-//     node: State<'_, Node>,
-// ) -> Result<(), String> {
-//     node.send_message(chat_id, content)
-//         .await
-//         .map_err(|e| format!("Failed to send message: {e:?}"))?;
-
-//     Ok(())
-// }
-
-// #[command]
-// pub async fn get_messages(
-//     chat_id: ChatId,
-//     node: State<'_, Node>,
-// ) -> Result<Vec<ChatMessage>, String> {
-//     node.get_messages(chat_id)
-//         .await
-//         .map_err(|e| format!("Failed to send message: {e:?}"))
-// }
+use dashchat_node::{
+    AgentId, ChatId, ChatMessageContent, EditedMessage, EphemeralPayload, GroupInvitation,
+    HistoryPage, HistoryQuery, MessageStatus, Node, ReactionCount, SearchResult, DEFAULT_PAGE_SIZE,
+};
+use p2panda_core::Hash;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tauri::{command, State};
+
+#[command]
+pub async fn add_member(
+    chat_id: ChatId,
+    member: AgentId,
+    node: State<'_, Node>,
+) -> Result<(), String> {
+    node.invite_to_group(chat_id, member)
+        .await
+        .map_err(|e| format!("Failed to add member: {e:?}"))
+}
+
+#[command]
+pub async fn get_members(chat_id: ChatId, node: State<'_, Node>) -> Result<Vec<AgentId>, String> {
+    node.get_group_members(chat_id)
+        .await
+        .map_err(|e| format!("Failed to get members: {e:?}"))
+}
+
+#[command]
+pub async fn leave_group(chat_id: ChatId, node: State<'_, Node>) -> Result<(), String> {
+    node.leave_group(chat_id)
+        .await
+        .map_err(|e| format!("Failed to leave group: {e:?}"))
+}
+
+#[command]
+pub async fn send_message(
+    chat_id: ChatId,
+    content: ChatMessageContent,
+    node: State<'_, Node>,
+) -> Result<(), String> {
+    node.send_message(chat_id, content)
+        .await
+        .map_err(|e| format!("Failed to send message: {e:?}"))?;
+
+    Ok(())
+}
+
+#[command]
+pub async fn edit_message(
+    chat_id: ChatId,
+    target: Hash,
+    content: ChatMessageContent,
+    node: State<'_, Node>,
+) -> Result<(), String> {
+    node.edit_message(chat_id, target, content)
+        .await
+        .map_err(|e| format!("Failed to edit message: {e:?}"))?;
+
+    Ok(())
+}
+
+#[command]
+pub async fn delete_message(
+    chat_id: ChatId,
+    target: Hash,
+    node: State<'_, Node>,
+) -> Result<(), String> {
+    node.delete_message(chat_id, target)
+        .await
+        .map_err(|e| format!("Failed to delete message: {e:?}"))?;
+
+    Ok(())
+}
+
+#[command]
+pub async fn get_edited_message(
+    chat_id: ChatId,
+    hash: Hash,
+    node: State<'_, Node>,
+) -> Result<Option<EditedMessage>, String> {
+    node.get_edited_message(chat_id, hash)
+        .await
+        .map_err(|e| format!("Failed to get edited message: {e:?}"))
+}
+
+#[command]
+pub async fn send_attachment(
+    chat_id: ChatId,
+    data: Vec<u8>,
+    mime: String,
+    name: String,
+    node: State<'_, Node>,
+) -> Result<(), String> {
+    node.send_attachment(chat_id, data, mime, name)
+        .await
+        .map_err(|e| format!("Failed to send attachment: {e:?}"))?;
+
+    Ok(())
+}
+
+#[command]
+pub async fn download_attachment(
+    chat_id: ChatId,
+    message: Hash,
+    node: State<'_, Node>,
+) -> Result<PathBuf, String> {
+    node.download_attachment(chat_id, message)
+        .await
+        .map_err(|e| format!("Failed to download attachment: {e:?}"))
+}
+
+#[command]
+pub async fn mark_read(chat_id: ChatId, up_to: Hash, node: State<'_, Node>) -> Result<(), String> {
+    node.mark_read(chat_id, up_to)
+        .await
+        .map_err(|e| format!("Failed to mark as read: {e:?}"))
+}
+
+#[command]
+pub async fn send_typing(chat_id: ChatId, node: State<'_, Node>) -> Result<(), String> {
+    node.send_ephemeral(chat_id, EphemeralPayload::Typing)
+        .await
+        .map_err(|e| format!("Failed to send typing indicator: {e:?}"))
+}
+
+#[command]
+pub async fn message_status(
+    chat_id: ChatId,
+    hash: Hash,
+    node: State<'_, Node>,
+) -> Result<MessageStatus, String> {
+    node.message_status(chat_id, hash)
+        .await
+        .map_err(|e| format!("Failed to get message status: {e:?}"))
+}
+
+#[command]
+pub fn read_receipts_enabled(chat_id: ChatId, node: State<'_, Node>) -> Result<bool, String> {
+    node.read_receipts_enabled(chat_id)
+        .map_err(|e| format!("Failed to get read receipts setting: {e:?}"))
+}
+
+#[command]
+pub fn set_read_receipts_enabled(
+    chat_id: ChatId,
+    enabled: bool,
+    node: State<'_, Node>,
+) -> Result<(), String> {
+    node.set_read_receipts_enabled(chat_id, enabled)
+        .map_err(|e| format!("Failed to set read receipts setting: {e:?}"))
+}
+
+#[command]
+pub async fn get_message_history(
+    chat_id: ChatId,
+    before: Option<Hash>,
+    after: Option<Hash>,
+    limit: Option<usize>,
+    node: State<'_, Node>,
+) -> Result<HistoryPage, String> {
+    let query = HistoryQuery {
+        before,
+        after,
+        limit: limit.unwrap_or(DEFAULT_PAGE_SIZE),
+    };
+    node.message_history(chat_id, query)
+        .await
+        .map_err(|e| format!("Failed to get message history: {e:?}"))
+}
+
+#[command]
+pub async fn get_reactions(
+    chat_id: ChatId,
+    targets: Vec<Hash>,
+    node: State<'_, Node>,
+) -> Result<HashMap<Hash, Vec<ReactionCount>>, String> {
+    node.reactions_batch(chat_id, &targets)
+        .await
+        .map_err(|e| format!("Failed to get reactions: {e:?}"))
+}
+
+#[command]
+pub async fn search_messages(
+    query: String,
+    chat_id: Option<ChatId>,
+    limit: Option<usize>,
+    node: State<'_, Node>,
+) -> Result<Vec<SearchResult>, String> {
+    node.search_messages(&query, chat_id, limit.unwrap_or(DEFAULT_PAGE_SIZE))
+        .await
+        .map_err(|e| format!("Failed to search messages: {e:?}"))
+}
+
+#[command]
+pub fn pending_group_invitations(node: State<'_, Node>) -> Result<Vec<GroupInvitation>, String> {
+    node.pending_group_invitations()
+        .map_err(|e| format!("Failed to get group invitations: {e:?}"))
+}
+
+#[command]
+pub async fn accept_group_invitation(chat_id: ChatId, node: State<'_, Node>) -> Result<(), String> {
+    node.accept_group_invitation(chat_id)
+        .await
+        .map_err(|e| format!("Failed to accept group invitation: {e:?}"))
+}
+
+#[command]
+pub fn decline_group_invitation(chat_id: ChatId, node: State<'_, Node>) -> Result<(), String> {
+    node.decline_group_invitation(chat_id)
+        .map_err(|e| format!("Failed to decline group invitation: {e:?}"))
+}
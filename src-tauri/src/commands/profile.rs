@@ -1,7 +1,64 @@
-use dashchat_node::{Error, Node, Profile};
+use std::io::Cursor;
+
+use base64::{engine::general_purpose, Engine as _};
+use dashchat_node::{AgentId, Error, Node, Profile};
+use image::{imageops::FilterType, ImageFormat};
 use tauri::State;
 
+/// Avatars are square thumbnails of this many pixels.
+const AVATAR_SIZE: u32 = 256;
+
+/// Set my profile. The avatar can be any image, which is turned into a thumbnail
+/// before it's uploaded.
 #[tauri::command]
-pub async fn set_profile(profile: Profile, node: State<'_, Node>) -> Result<(), Error> {
-    node.set_profile(profile).await
+pub async fn set_profile(
+    name: String,
+    avatar: Option<Vec<u8>>,
+    node: State<'_, Node>,
+) -> Result<(), Error> {
+    let avatar = match avatar {
+        Some(image) => {
+            let thumbnail = thumbnail(&image).map_err(|e| Error::UploadAvatar(e.to_string()))?;
+            Some(node.upload_avatar(thumbnail).await?)
+        }
+        None => None,
+    };
+    node.set_profile(Profile { name, avatar }).await
+}
+
+/// The avatar of an agent as a data URL, fetching it first if it isn't cached yet.
+#[tauri::command]
+pub async fn get_avatar(
+    agent_id: AgentId,
+    node: State<'_, Node>,
+) -> Result<Option<String>, String> {
+    let Some(path) = node
+        .get_avatar(agent_id)
+        .await
+        .map_err(|e| format!("Failed to get avatar: {e:?}"))?
+    else {
+        return Ok(None);
+    };
+    let image = tokio::fs::read(path)
+        .await
+        .map_err(|e| format!("Failed to read avatar: {e:?}"))?;
+    // Other clients may not upload PNG thumbnails, so the format comes from the image itself.
+    let format =
+        image::guess_format(&image).map_err(|e| format!("Failed to read avatar: {e:?}"))?;
+    Ok(Some(format!(
+        "data:{};base64,{}",
+        format.to_mime_type(),
+        general_purpose::STANDARD.encode(image)
+    )))
+}
+
+fn thumbnail(image: &[u8]) -> image::ImageResult<Vec<u8>> {
+    let thumbnail = image::load_from_memory(image)?.resize_to_fill(
+        AVATAR_SIZE,
+        AVATAR_SIZE,
+        FilterType::Lanczos3,
+    );
+    let mut bytes = vec![];
+    thumbnail.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+    Ok(bytes)
 }
@@ -1,6 +1,7 @@
-use dashchat_node::Node;
+use dashchat_node::{ChatId, DeviceId, EphemeralPayload, Event, Node, Notification};
 use mailbox_client::toy::ToyMailboxClient;
-use p2panda_core::{cbor::encode_cbor, Body};
+use p2panda_core::{cbor::encode_cbor, Body, Hash};
+use serde::Serialize;
 use tauri::{Emitter, Manager, RunEvent};
 
 use crate::{
@@ -17,6 +18,22 @@ mod menu;
 #[cfg(mobile)]
 mod push_notifications;
 
+/// An ephemeral payload from another device, emitted as `p2panda://ephemeral`.
+#[derive(Clone, Serialize)]
+struct EphemeralEvent {
+    chat_id: ChatId,
+    author: DeviceId,
+    payload: EphemeralPayload,
+}
+
+/// Progress of downloading a file, emitted as `p2panda://download-progress`.
+#[derive(Clone, Serialize)]
+struct DownloadProgressEvent {
+    hash: Hash,
+    received: u64,
+    size: u64,
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let mut builder = tauri::Builder::default();
@@ -52,18 +69,49 @@ pub fn run() {
             commands::logs::get_log,
             commands::logs::get_authors,
             commands::profile::set_profile,
+            commands::profile::get_avatar,
             commands::devices::my_device_group_topic,
+            commands::devices::create_device_code,
+            commands::devices::link_device,
+            commands::devices::verification_code,
+            commands::devices::get_pending_device_requests,
+            commands::devices::approve_device,
+            commands::devices::my_devices,
+            commands::devices::remove_device,
             commands::contacts::my_agent_id,
             commands::contacts::create_contact_code,
             commands::contacts::add_contact,
             commands::contacts::active_inbox_topics,
+            commands::contacts::get_pending_contact_requests,
+            commands::contacts::accept_contact_request,
             commands::contacts::reject_contact_request,
+            commands::contacts::remove_contact,
+            commands::contacts::get_contacts,
             commands::direct_messages::direct_message_chat_id,
             commands::direct_messages::direct_messages_send_message,
-            // commands::chats::create_group,
-            // commands::group_chat::add_member,
-            // commands::group_chat::send_message,
-            // commands::group_chat::get_messages,
+            commands::chats::create_group_chat,
+            commands::chats::get_group_chats,
+            commands::chats::get_chat_summaries,
+            commands::group_chat::add_member,
+            commands::group_chat::get_members,
+            commands::group_chat::leave_group,
+            commands::group_chat::send_message,
+            commands::group_chat::get_message_history,
+            commands::group_chat::get_reactions,
+            commands::group_chat::search_messages,
+            commands::group_chat::edit_message,
+            commands::group_chat::delete_message,
+            commands::group_chat::send_attachment,
+            commands::group_chat::download_attachment,
+            commands::group_chat::mark_read,
+            commands::group_chat::send_typing,
+            commands::group_chat::message_status,
+            commands::group_chat::read_receipts_enabled,
+            commands::group_chat::set_read_receipts_enabled,
+            commands::group_chat::get_edited_message,
+            commands::group_chat::pending_group_invitations,
+            commands::group_chat::accept_group_invitation,
+            commands::group_chat::decline_group_invitation,
         ])
         .plugin(
             tauri_plugin_log::Builder::default()
@@ -105,10 +153,45 @@ pub fn run() {
                 handle.manage(node);
 
                 tauri::async_runtime::spawn(async move {
-                    while let Some(notification) = notification_rx.recv().await {
-                        log::info!("Received notification: {:?}", notification);
+                    while let Some(event) = notification_rx.recv().await {
+                        log::info!("Received event: {:?}", event);
+
+                        let Notification { header, payload } = match event {
+                            Event::Notification(notification) => notification,
+                            Event::Ephemeral {
+                                chat_id,
+                                author,
+                                payload,
+                            } => {
+                                let event = EphemeralEvent {
+                                    chat_id,
+                                    author,
+                                    payload,
+                                };
+                                if let Err(err) = handle.emit("p2panda://ephemeral", event) {
+                                    log::error!("Failed to emit ephemeral payload: {err:?}");
+                                }
+                                continue;
+                            }
+                            Event::DownloadProgress {
+                                hash,
+                                received,
+                                size,
+                            } => {
+                                let event = DownloadProgressEvent {
+                                    hash,
+                                    received,
+                                    size,
+                                };
+                                if let Err(err) = handle.emit("p2panda://download-progress", event)
+                                {
+                                    log::error!("Failed to emit download progress: {err:?}");
+                                }
+                                continue;
+                            }
+                        };
 
-                        let body = match encode_cbor(&notification.payload) {
+                        let body = match encode_cbor(&payload) {
                             Ok(body) => body,
                             Err(err) => {
                                 log::error!("Failed to serialize payload: {err:?}");
@@ -117,7 +200,7 @@ pub fn run() {
                         };
                         let _node = handle.state::<Node>();
                         let simplified_operation =
-                            match simplify(notification.header, Some(Body::new(&body[..]))) {
+                            match simplify(header, Some(Body::new(&body[..]))) {
                                 Ok(o) => o,
                                 Err(err) => {
                                     log::error!("Failed to simplify operation: {err:?}");